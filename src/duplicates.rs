@@ -0,0 +1,98 @@
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::pinjsonfs::name_of;
+
+/// JSON pointers of every object key that repeats an earlier key in the same
+/// object, in document order. serde_json keeps only the last value for such a
+/// key, so each of these is data the mounted tree silently drops.
+pub(crate) fn duplicate_keys(data: &str) -> Vec<String> {
+    let mut found = vec![];
+    let mut de = serde_json::Deserializer::from_str(data);
+    // Malformed input is reported by the real parse.
+    let _ = Scan {
+        pointer: String::new(),
+        found: &mut found,
+    }
+    .deserialize(&mut de);
+    found
+}
+
+/// Walks one value, recording duplicate keys below `pointer`.
+struct Scan<'a> {
+    pointer: String,
+    found: &'a mut Vec<String>,
+}
+
+impl Scan<'_> {
+    fn child(&mut self, name: &str) -> Scan<'_> {
+        Scan {
+            pointer: format!("{}/{}", self.pointer, name),
+            found: self.found,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Scan<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Scan<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let mut seen = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let name = name_of(&key);
+            if !seen.insert(key) {
+                self.found.push(format!("{}/{}", self.pointer, name));
+            }
+            map.next_value_seed(self.child(&name))?;
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while seq
+            .next_element_seed(self.child(&index.to_string()))?
+            .is_some()
+        {
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
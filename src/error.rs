@@ -0,0 +1,225 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::format::Format;
+
+/// Errors raised while loading the backing JSON file.
+#[derive(Debug)]
+pub(crate) enum JsonFsError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The file is not a valid gzip stream.
+    Decompress {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+        /// The lines up to the error's, with a caret under its column.
+        context: String,
+    },
+    /// The YAML or TOML file is malformed, or holds what JSON can't.
+    Decode {
+        path: PathBuf,
+        format: Format,
+        message: String,
+    },
+    /// The `--root-pointer` doesn't name a node of the document.
+    Pointer {
+        path: PathBuf,
+        pointer: String,
+    },
+    /// The mounted root is a scalar, which can't be a directory.
+    ScalarRoot {
+        path: PathBuf,
+        pointer: String,
+    },
+    /// Another jsonfs holds the lock on the file.
+    Locked {
+        path: PathBuf,
+    },
+    /// The `--schema` file isn't a JSON Schema.
+    Schema {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl JsonFsError {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        JsonFsError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn decompress(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        JsonFsError::Decompress {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// A syntax error in `text`, the file's content, quoted around the error.
+    pub(crate) fn parse(path: impl Into<PathBuf>, source: serde_json::Error, text: &str) -> Self {
+        JsonFsError::Parse {
+            path: path.into(),
+            line: source.line(),
+            column: source.column(),
+            context: excerpt(text, source.line(), source.column()),
+            source,
+        }
+    }
+
+    pub(crate) fn decode(path: impl Into<PathBuf>, format: Format, message: String) -> Self {
+        JsonFsError::Decode {
+            path: path.into(),
+            format,
+            message,
+        }
+    }
+
+    pub(crate) fn pointer(path: impl Into<PathBuf>, pointer: &str) -> Self {
+        JsonFsError::Pointer {
+            path: path.into(),
+            pointer: pointer.to_string(),
+        }
+    }
+
+    pub(crate) fn scalar_root(path: impl Into<PathBuf>, pointer: &str) -> Self {
+        JsonFsError::ScalarRoot {
+            path: path.into(),
+            pointer: pointer.to_string(),
+        }
+    }
+
+    pub(crate) fn locked(path: impl Into<PathBuf>) -> Self {
+        JsonFsError::Locked { path: path.into() }
+    }
+
+    pub(crate) fn schema(path: impl Into<PathBuf>, message: String) -> Self {
+        JsonFsError::Schema {
+            path: path.into(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for JsonFsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonFsError::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            JsonFsError::Decompress { path, source } => {
+                write!(f, "failed to decompress {}: {}", path.display(), source)
+            }
+            JsonFsError::Parse {
+                path,
+                line,
+                column,
+                source,
+                context,
+            } => {
+                // serde_json appends the position itself; report it once, up front.
+                let message = source.to_string();
+                let suffix = format!(" at line {} column {}", line, column);
+                write!(
+                    f,
+                    "failed to parse {} at line {} column {}: {}",
+                    path.display(),
+                    line,
+                    column,
+                    message.strip_suffix(&suffix).unwrap_or(&message)
+                )?;
+                if !context.is_empty() {
+                    write!(f, "\n{}", context)?;
+                }
+                Ok(())
+            }
+            JsonFsError::Decode {
+                path,
+                format,
+                message,
+            } => write!(
+                f,
+                "failed to parse {} as {}: {}",
+                path.display(),
+                format,
+                message
+            ),
+            JsonFsError::Pointer { path, pointer } => {
+                write!(f, "{:?} does not point into {}", pointer, path.display())
+            }
+            JsonFsError::ScalarRoot { path, pointer } if pointer.is_empty() => write!(
+                f,
+                "{} holds a single JSON value, not an object or array; \
+                 use --wrap-scalar to mount it as {{\"value\": ...}}",
+                path.display()
+            ),
+            JsonFsError::ScalarRoot { path, pointer } => write!(
+                f,
+                "{:?} points at a single JSON value in {}, not an object or array",
+                pointer,
+                path.display()
+            ),
+            JsonFsError::Locked { path } => write!(
+                f,
+                "{} is already mounted by another jsonfs; unmount that first, \
+                 or use --shared to mount it read-only",
+                path.display()
+            ),
+            JsonFsError::Schema { path, message } => {
+                write!(
+                    f,
+                    "{} is not a valid JSON Schema: {}",
+                    path.display(),
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for JsonFsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonFsError::Io { source, .. } => Some(source),
+            JsonFsError::Decompress { source, .. } => Some(source),
+            JsonFsError::Parse { source, .. } => Some(source),
+            JsonFsError::Decode { .. }
+            | JsonFsError::Pointer { .. }
+            | JsonFsError::ScalarRoot { .. }
+            | JsonFsError::Locked { .. }
+            | JsonFsError::Schema { .. } => None,
+        }
+    }
+}
+
+/// Up to three lines of `text` ending at the 1-based `line`, numbered, with a
+/// caret under `column`; empty if `text` has no such line.
+fn excerpt(text: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    if line == 0 || line > lines.len() {
+        return String::new();
+    }
+    let first = line.saturating_sub(2).max(1);
+    let width = line.to_string().len();
+    let mut out = String::new();
+    for (number, text) in (first..=line).zip(&lines[first - 1..line]) {
+        out.push_str(&format!("{:>width$} | {}\n", number, text));
+    }
+    out.push_str(&format!(
+        "{:>width$} | {:>column$}",
+        "",
+        "^",
+        column = column.max(1)
+    ));
+    out
+}
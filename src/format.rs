@@ -0,0 +1,186 @@
+use serde_json::{Map, Number, Value};
+use slog::warn;
+use std::fmt;
+use std::path::Path;
+
+use crate::pinjsonfs::name_of;
+
+/// On-disk syntax of the backing file. The mounted tree is always a JSON
+/// value; YAML and TOML are converted on load and save, which loses what
+/// JSON has no place for (see `parse` and `render`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Format {
+    #[default]
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// The format `path`'s extension names, looking past a trailing `.gz`;
+    /// JSON for any other.
+    pub(crate) fn of_path(path: &Path) -> Format {
+        let path = match path.extension() {
+            Some(ext) if ext == "gz" => Path::new(path.file_stem().unwrap_or_default()),
+            _ => path,
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("toml") => Format::Toml,
+            _ => Format::Json,
+        }
+    }
+
+    /// Parses the text of a YAML or TOML file. Aliases are expanded and `<<`
+    /// merge keys applied, so anchors don't survive a save; non-string keys
+    /// become their text, and TOML datetimes become strings.
+    pub(crate) fn parse(self, data: &str) -> Result<Value, String> {
+        match self {
+            Format::Json => serde_json::from_str(data).map_err(|err| err.to_string()),
+            Format::Yaml => {
+                let mut yaml: serde_yaml::Value =
+                    serde_yaml::from_str(data).map_err(|err| err.to_string())?;
+                yaml.apply_merge().map_err(|err| err.to_string())?;
+                from_yaml(yaml)
+            }
+            Format::Toml => {
+                let table: toml::Table = data
+                    .parse()
+                    .map_err(|err: toml::de::Error| err.to_string().trim_end().to_string())?;
+                Ok(from_toml(toml::Value::Table(table)))
+            }
+        }
+    }
+
+    /// The text of `value` in a YAML or TOML file (JSON is rendered by the
+    /// caller, which knows the indent). TOML has no null: null members and
+    /// elements are left out, with a warning. Its root must be a table.
+    pub(crate) fn render(self, value: &Value) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string(value).map_err(|err| err.to_string()),
+            Format::Yaml => serde_yaml::to_string(&to_yaml(value)).map_err(|err| err.to_string()),
+            Format::Toml => match to_toml(value, "") {
+                Some(toml::Value::Table(table)) => {
+                    toml::to_string_pretty(&table).map_err(|err| err.to_string())
+                }
+                _ => Err("a TOML document must be a table".to_string()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        })
+    }
+}
+
+/// A number as JSON holds it; `None` for a NaN or infinity, which it can't.
+fn number(n: f64) -> Option<Value> {
+    Number::from_f64(n).map(Value::Number)
+}
+
+fn from_yaml(yaml: serde_yaml::Value) -> Result<Value, String> {
+    Ok(match yaml {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+            (Some(n), _, _) => n.into(),
+            (_, Some(n), _) => n.into(),
+            (_, _, Some(f)) => number(f).ok_or_else(|| format!("{} has no JSON equivalent", n))?,
+            _ => unreachable!(),
+        },
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(seq) => {
+            Value::Array(seq.into_iter().map(from_yaml).collect::<Result<_, _>>()?)
+        }
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut map = Map::new();
+            for (key, value) in mapping {
+                let key = match key {
+                    serde_yaml::Value::String(s) => s,
+                    serde_yaml::Value::Null => "null".to_string(),
+                    serde_yaml::Value::Bool(b) => b.to_string(),
+                    serde_yaml::Value::Number(n) => n.to_string(),
+                    _ => return Err("a mapping key must be a scalar".to_string()),
+                };
+                map.insert(key, from_yaml(value)?);
+            }
+            Value::Object(map)
+        }
+        serde_yaml::Value::Tagged(tagged) => from_yaml(tagged.value)?,
+    })
+}
+
+fn to_yaml(value: &Value) -> serde_yaml::Value {
+    match value {
+        Value::Null => serde_yaml::Value::Null,
+        Value::Bool(b) => serde_yaml::Value::Bool(*b),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => n.into(),
+            (_, Some(n)) => n.into(),
+            _ => n.as_f64().unwrap_or(f64::NAN).into(),
+        },
+        Value::String(s) => serde_yaml::Value::String(s.clone()),
+        Value::Array(vec) => serde_yaml::Value::Sequence(vec.iter().map(to_yaml).collect()),
+        Value::Object(map) => serde_yaml::Value::Mapping(
+            map.iter()
+                .map(|(key, value)| (serde_yaml::Value::String(key.clone()), to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn from_toml(toml: toml::Value) -> Value {
+    match toml {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(n) => n.into(),
+        // TOML allows nan and inf, which JSON can only hold as text.
+        toml::Value::Float(f) => number(f).unwrap_or_else(|| Value::String(f.to_string())),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(vec) => Value::Array(vec.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// `value` as TOML, or `None` for a null, which is warned about by its
+/// JSON pointer and left out of its parent.
+fn to_toml(value: &Value, pointer: &str) -> Option<toml::Value> {
+    Some(match value {
+        Value::Null => {
+            warn!(slog_scope::logger(), "TOML has no null, leaving it out"; "key" => pointer);
+            return None;
+        }
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => toml::Value::Integer(n),
+            // Beyond i64, TOML's integers, a number is saved as a float.
+            None => toml::Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Array(vec) => toml::Value::Array(
+            vec.iter()
+                .enumerate()
+                .filter_map(|(index, value)| to_toml(value, &format!("{}/{}", pointer, index)))
+                .collect(),
+        ),
+        Value::Object(map) => toml::Value::Table(
+            map.iter()
+                .filter_map(|(key, value)| {
+                    let pointer = format!("{}/{}", pointer, name_of(key));
+                    Some((key.clone(), to_toml(value, &pointer)?))
+                })
+                .collect(),
+        ),
+    })
+}
@@ -0,0 +1,165 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::lenient::relax;
+
+/// `original`, the JSON text last read or saved, with the scalars that
+/// differ in `new` rewritten where they stand and every other byte kept, so
+/// a save under `--in-place-edit` only changes the lines it has to. `None`
+/// if `new` has a different shape: a key added or removed, an array of
+/// another length, a scalar turned container or the other way around, or a
+/// token that isn't strict JSON (a bare `NaN`). The caller then writes the
+/// whole document anew.
+///
+/// serde_json reports no positions inside a value, so the spans come from
+/// a scan of the text of its own, of `relax`ed text if `lenient`, which
+/// keeps comments and trailing commas where they were.
+pub(crate) fn splice(original: &str, new: &Value, lenient: bool) -> Option<String> {
+    let scanned = if lenient {
+        relax(original)
+    } else {
+        original.to_string()
+    };
+    let mut scanner = Scanner {
+        text: scanned.as_bytes(),
+        pos: 0,
+        edits: vec![],
+    };
+    scanner.value(new)?;
+    scanner.skip_ws();
+    if scanner.pos != scanner.text.len() {
+        return None;
+    }
+    // Edits are found in order of the text, and never overlap.
+    let mut out = String::with_capacity(original.len());
+    let mut at = 0;
+    for (start, end, text) in scanner.edits {
+        out.push_str(&original[at..start]);
+        out.push_str(&text);
+        at = end;
+    }
+    out.push_str(&original[at..]);
+    Some(out)
+}
+
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: usize,
+    /// Byte range of each scalar that changed, with its new text.
+    edits: Vec<(usize, usize, String)>,
+}
+
+impl Scanner<'_> {
+    /// Walks the value at `pos` alongside `new`, recording an edit for each
+    /// scalar that differs.
+    fn value(&mut self, new: &Value) -> Option<()> {
+        self.skip_ws();
+        match (self.text.get(self.pos)?, new) {
+            (b'{', Value::Object(map)) => {
+                self.pos += 1;
+                let mut seen = HashSet::new();
+                let mut first = true;
+                while self.next_member(b'}', &mut first)? {
+                    self.skip_ws();
+                    let start = self.pos;
+                    self.string()?;
+                    let key: String = self.parse(start)?;
+                    self.skip_ws();
+                    self.expect(b':')?;
+                    self.value(map.get(&key)?)?;
+                    seen.insert(key);
+                }
+                (seen.len() == map.len()).then_some(())
+            }
+            (b'[', Value::Array(vec)) => {
+                self.pos += 1;
+                let mut count = 0;
+                let mut first = true;
+                while self.next_member(b']', &mut first)? {
+                    self.value(vec.get(count)?)?;
+                    count += 1;
+                }
+                (count == vec.len()).then_some(())
+            }
+            (b'{' | b'[', _) | (_, Value::Object(_) | Value::Array(_)) => None,
+            (b'"', _) => {
+                let start = self.pos;
+                self.string()?;
+                self.scalar(start, new)
+            }
+            _ => {
+                let start = self.pos;
+                while !matches!(
+                    self.text.get(self.pos),
+                    None | Some(b',' | b']' | b'}' | b' ' | b'\t' | b'\r' | b'\n')
+                ) {
+                    self.pos += 1;
+                }
+                self.scalar(start, new)
+            }
+        }
+    }
+
+    /// Records an edit if the scalar from `start` to `pos` isn't `new`.
+    fn scalar(&mut self, start: usize, new: &Value) -> Option<()> {
+        let old: Value = self.parse(start)?;
+        if old != *new {
+            let text = serde_json::to_string(new).ok()?;
+            self.edits.push((start, self.pos, text));
+        }
+        Some(())
+    }
+
+    /// Moves past the string starting at `pos`.
+    fn string(&mut self) -> Option<()> {
+        if self.text.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        let mut escaped = false;
+        for (i, &byte) in self.text.iter().enumerate().skip(self.pos + 1) {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => {
+                    self.pos = i + 1;
+                    return Some(());
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Moves on to the next member of the container, past the comma after
+    /// the one before; `false` once past its `close` instead.
+    fn next_member(&mut self, close: u8, first: &mut bool) -> Option<bool> {
+        self.skip_ws();
+        if self.text.get(self.pos) == Some(&close) {
+            self.pos += 1;
+            return Some(false);
+        }
+        if !std::mem::take(first) {
+            self.expect(b',')?;
+        }
+        Some(true)
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        (self.text.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(&self, start: usize) -> Option<T> {
+        let token = std::str::from_utf8(&self.text[start..self.pos]).ok()?;
+        serde_json::from_str(token).ok()
+    }
+
+    fn skip_ws(&mut self) {
+        while self
+            .text
+            .get(self.pos)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+}
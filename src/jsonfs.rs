@@ -2,26 +2,53 @@ use fuser::{
     FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyWrite,
     Request, FUSE_ROOT_ID,
 };
-use libc::ENOENT;
+use libc::{c_int, ENOENT};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::Arc;
 
-use std::time::{Duration, UNIX_EPOCH};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use slog::{debug, error, info, warn};
+use slog::{debug, warn};
+
+use crate::error::JsonFsError;
+use crate::pinjsonfs::{resolve_time, utf8_name, write_offset, Options};
 
 pub(crate) struct JsonFS {
-    json_path: Rc<PathBuf>,
-    json: Rc<Value>,
-    inodes: HashMap<u64, Rc<String>>,
+    json_path: Arc<PathBuf>,
+    json: Arc<Value>,
+    inodes: HashMap<u64, Arc<String>>,
+    /// Reverse of `inodes`, so a path seen again keeps its inode.
+    paths: HashMap<Arc<String>, u64>,
+    /// References the kernel holds to each inode: one per `lookup` or `mknod`
+    /// reply, until `forget` gives them back.
+    lookups: HashMap<u64, u64>,
     last_inode: u64,
+    /// Whether the tree has changes the file doesn't. Writes only set this;
+    /// the file is written on `flush`, `fsync` and unmount, so a crash loses
+    /// what was written since the last of those.
+    dirty: bool,
+    /// Times set on each inode by `setattr` or a truncation; the epoch for
+    /// the rest. Keyed by inode, which follows a node when its index shifts.
+    times: HashMap<u64, Times>,
+    /// Of the mount options, only the cache timeouts and `max_value_bytes`
+    /// apply here; `parse_args` refuses the rest with this backend.
+    options: Options,
+}
+
+/// Timestamps of one node, each `None` until something sets it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Times {
+    atime: Option<SystemTime>,
+    mtime: Option<SystemTime>,
+    ctime: Option<SystemTime>,
+    crtime: Option<SystemTime>,
 }
 
-fn get_json_at_path<'a, 'b>(json: &'b Value, path: &'a str) -> Option<&'b Value> {
+fn get_json_at_path<'a>(json: &'a Value, path: &str) -> Option<&'a Value> {
     let mut current = json;
     for key in path.split('/').filter(|s| !s.is_empty()) {
         match current {
@@ -33,49 +60,198 @@ fn get_json_at_path<'a, 'b>(json: &'b Value, path: &'a str) -> Option<&'b Value>
     Some(current)
 }
 
+fn get_json_at_path_mut<'a>(json: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    let mut current = json;
+    for key in path.split('/').filter(|s| !s.is_empty()) {
+        match current {
+            Value::Object(map) => current = map.get_mut(key)?,
+            Value::Array(vec) => current = vec.get_mut(key.parse::<usize>().ok()?)?,
+            _ => return None,
+        }
+    }
+    Some(current)
+}
+
+/// Fails with `EFBIG` when a node of `len` bytes would exceed
+/// `options.max_value_bytes`.
+fn check_size(options: &Options, len: usize) -> Result<(), c_int> {
+    match options.max_value_bytes {
+        Some(max) if len > max => Err(libc::EFBIG),
+        _ => Ok(()),
+    }
+}
+
+/// Inode listed for a child that has none yet, as libfuse lists it.
+const UNKNOWN_INO: u64 = 0xffff_ffff;
+
+/// What a node is shown as: containers are directories, scalars files.
+fn file_type_of(value: &Value) -> FileType {
+    match value {
+        Value::Object(_) | Value::Array(_) => FileType::Directory,
+        _ => FileType::RegularFile,
+    }
+}
+
 impl JsonFS {
-    pub(crate) fn new(json_path: impl AsRef<Path>) -> Self {
-        let data = fs::read_to_string(json_path.as_ref()).unwrap();
-        let json = serde_json::from_str(&data).unwrap();
+    #[cfg(test)]
+    pub(crate) fn new(json_path: impl AsRef<Path>) -> Result<Self, JsonFsError> {
+        Self::with_options(json_path, Options::default())
+    }
+
+    pub(crate) fn with_options(
+        json_path: impl AsRef<Path>,
+        options: Options,
+    ) -> Result<Self, JsonFsError> {
+        let json_path = json_path.as_ref();
+        let data = fs::read_to_string(json_path).map_err(|err| JsonFsError::io(json_path, err))?;
+        let json =
+            serde_json::from_str(&data).map_err(|err| JsonFsError::parse(json_path, err, &data))?;
 
         let mut fs = JsonFS {
-            json_path: Rc::new(json_path.as_ref().to_path_buf()),
-            json: Rc::new(json),
+            json_path: Arc::new(json_path.to_path_buf()),
+            json: Arc::new(json),
             inodes: HashMap::new(),
+            paths: HashMap::new(),
+            lookups: HashMap::new(),
             last_inode: FUSE_ROOT_ID,
+            dirty: false,
+            times: HashMap::new(),
+            options,
         };
-        fs.inodes.insert(FUSE_ROOT_ID, Rc::new("".to_string()));
-        fs
+        let root = Arc::new("".to_string());
+        fs.inodes.insert(FUSE_ROOT_ID, Arc::clone(&root));
+        fs.paths.insert(root, FUSE_ROOT_ID);
+        Ok(fs)
     }
 
+    /// Returns the inode already handed out for `path`, allocating one only
+    /// the first time the path is seen.
     fn allocate_inode(&mut self, path: String) -> u64 {
+        if let Some(&ino) = self.paths.get(&path) {
+            return ino;
+        }
         self.last_inode += 1;
-        self.inodes.insert(self.last_inode, Rc::new(path));
+        let path = Arc::new(path);
+        self.inodes.insert(self.last_inode, Arc::clone(&path));
+        self.paths.insert(path, self.last_inode);
         self.last_inode
     }
 
-    fn create_attr(&self, ino: u64, value: &Value) -> FileAttr {
-        let kind = match value {
-            Value::Object(_) | Value::Array(_) => FileType::Directory,
-            _ => FileType::RegularFile,
+    /// Path of `ino`; `ENOENT` for an inode never handed out or forgotten.
+    fn path_of(&self, ino: u64) -> Result<Arc<String>, c_int> {
+        self.inodes.get(&ino).cloned().ok_or(ENOENT)
+    }
+
+    /// Value at the path of `ino`.
+    fn value_of(&self, ino: u64) -> Result<&Value, c_int> {
+        get_json_at_path(self.json.as_ref(), &self.path_of(ino)?).ok_or(ENOENT)
+    }
+
+    /// Looks up `name` under `parent`, handing the kernel a reference to its
+    /// inode.
+    fn lookup_entry(&mut self, parent: u64, name: &str) -> Result<FileAttr, c_int> {
+        let path = format!("{}/{}", self.path_of(parent)?, name);
+        let json = Arc::clone(&self.json);
+        let value = get_json_at_path(json.as_ref(), &path).ok_or(ENOENT)?;
+        let ino = self.allocate_inode(path);
+        *self.lookups.entry(ino).or_default() += 1;
+        Ok(self.create_attr(ino, value))
+    }
+
+    fn get_attr(&self, ino: u64) -> Result<FileAttr, c_int> {
+        Ok(self.create_attr(ino, self.value_of(ino)?))
+    }
+
+    /// Takes back `nlookup` kernel references to `ino`; the inode is released
+    /// with the last one, unless it is the root. A path seen again afterwards
+    /// gets a new inode.
+    fn forget_inode(&mut self, ino: u64, nlookup: u64) {
+        let Some(count) = self.lookups.get_mut(&ino) else {
+            return;
         };
+        *count = count.saturating_sub(nlookup);
+        if *count == 0 && ino != FUSE_ROOT_ID {
+            self.lookups.remove(&ino);
+            if let Some(path) = self.inodes.remove(&ino) {
+                self.paths.remove(&path);
+            }
+        }
+    }
+
+    /// Reads up to `size` bytes of `ino` from `offset`, clamped to the content.
+    /// Reads are byte-oriented and may split a multibyte character.
+    fn read_data(&self, ino: u64, offset: i64, size: u32) -> Result<Vec<u8>, c_int> {
+        let value = self.value_of(ino)?;
+        let content = match value {
+            Value::String(s) => s.clone(),
+            _ => value.to_string(),
+        };
+
+        let content_bytes = content.as_bytes();
+        let start = (offset.max(0) as usize).min(content_bytes.len());
+        let end = start.saturating_add(size as usize).min(content_bytes.len());
+
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "read", "io"=> "out",
+            "content" => %String::from_utf8_lossy(&content_bytes[start..end]));
+        Ok(content_bytes[start..end].to_vec())
+    }
+
+    /// The children of directory `ino` as (inode, kind, name) triples, each
+    /// kind as `getattr` reports it. Listing allocates no inodes, as nothing
+    /// would ever forget them: a child not looked up yet is listed with
+    /// `UNKNOWN_INO`, and gets its inode from `lookup`.
+    fn dir_entries(&self, ino: u64) -> Result<Vec<(u64, FileType, String)>, c_int> {
+        let path = self.path_of(ino)?;
+        let json = Arc::clone(&self.json);
+        let children: Vec<(String, &Value)> = match get_json_at_path(json.as_ref(), &path) {
+            Some(Value::Object(map)) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            Some(Value::Array(vec)) => vec
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v))
+                .collect(),
+            Some(_) => return Err(libc::ENOTDIR),
+            None => return Err(ENOENT),
+        };
+        Ok(children
+            .into_iter()
+            .map(|(name, value)| {
+                let ino = self.paths.get(&format!("{}/{}", path, name)).copied();
+                (ino.unwrap_or(UNKNOWN_INO), file_type_of(value), name)
+            })
+            .collect())
+    }
+
+    fn create_attr(&self, ino: u64, value: &Value) -> FileAttr {
+        let kind = file_type_of(value);
 
         let size = match value {
             Value::String(s) => s.len() as u64,
             _ => value.to_string().len() as u64,
         };
 
+        // A directory is linked from its parent and its own ".", plus the ".."
+        // of each subdirectory; `find` relies on this to skip leaves.
+        let is_dir = |v: &&Value| v.is_object() || v.is_array();
+        let nlink = match value {
+            Value::Object(map) => 2 + map.values().filter(is_dir).count() as u32,
+            Value::Array(vec) => 2 + vec.iter().filter(is_dir).count() as u32,
+            _ => 1,
+        };
+
+        let times = self.times.get(&ino).copied().unwrap_or_default();
         FileAttr {
             ino,
             size,
-            blocks: 1,
-            atime: UNIX_EPOCH,
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
+            blocks: size.div_ceil(512),
+            atime: times.atime.unwrap_or(UNIX_EPOCH),
+            mtime: times.mtime.unwrap_or(UNIX_EPOCH),
+            ctime: times.ctime.unwrap_or(UNIX_EPOCH),
+            crtime: times.crtime.unwrap_or(UNIX_EPOCH),
             kind,
             perm: 0o644,
-            nlink: 1,
+            nlink,
             uid: 0,
             gid: 0,
             rdev: 0,
@@ -84,105 +260,160 @@ impl JsonFS {
         }
     }
 
-    fn write_json_at_path(&mut self, path: &str, content: &str) {
-        let mut current = Rc::make_mut(&mut self.json);
-        for key in path.split('/').filter(|s| !s.is_empty()) {
-            match current {
-                Value::Object(map) => current = map.get_mut(key).unwrap(),
-                Value::Array(vec) => current = vec.get_mut(key.parse::<usize>().unwrap()).unwrap(),
-                _ => break,
-            }
-        }
-        *current = Value::String(content.to_string());
+    /// Splices `data` into the text of the scalar at `path` at byte `offset`,
+    /// extending it as needed; the text is what `read` returns, and the node
+    /// becomes a string. Fails with `EINVAL`, leaving the node as it was, when
+    /// the result isn't valid UTF-8, and with `EFBIG` when it would be longer
+    /// than `max_value_bytes`.
+    fn write_json_at_path(&mut self, path: &str, offset: i64, data: &[u8]) -> Result<(), c_int> {
+        let current = get_json_at_path_mut(Arc::make_mut(&mut self.json), path).ok_or(ENOENT)?;
+        let mut bytes = match current {
+            // Never clobber a whole subtree with a scalar.
+            Value::Object(_) | Value::Array(_) => return Err(libc::EISDIR),
+            Value::String(s) => s.clone().into_bytes(),
+            _ => current.to_string().into_bytes(),
+        };
+        let offset = write_offset(offset, bytes.len())?;
+        check_size(&self.options, offset + data.len())?;
+        let end = (offset + data.len()).min(bytes.len());
+        bytes.splice(offset..end, data.iter().copied());
+
+        *current = Value::String(String::from_utf8(bytes).map_err(|_| libc::EINVAL)?);
+        self.dirty = true;
+        Ok(())
     }
-    fn write_json_at_path2(&mut self, path: &str, offset: i64, data: &str) {
-        let mut current = Rc::make_mut(&mut self.json);
 
-        // 查找路径
-        for key in path.split('/').filter(|s| !s.is_empty()) {
-            match current {
-                Value::Object(map) => current = map.get_mut(key).unwrap(),
-                Value::Array(vec) => current = vec.get_mut(key.parse::<usize>().unwrap()).unwrap(),
-                _ => break,
-            }
+    /// Cuts or pads (with spaces) the text of the node at `path` to `size`
+    /// bytes; `EINVAL` if that would split a character, `EFBIG` if it would
+    /// grow past `max_value_bytes`. As with a write, the node is left a string
+    /// whatever it was: a number cut to `""` couldn't stay one.
+    fn truncate_json_at_path(&mut self, path: &str, size: u64) -> Result<(), c_int> {
+        let current = get_json_at_path_mut(Arc::make_mut(&mut self.json), path).ok_or(ENOENT)?;
+        let mut bytes = match current {
+            Value::Object(_) | Value::Array(_) => return Err(libc::EISDIR),
+            Value::String(s) => s.clone().into_bytes(),
+            _ => current.to_string().into_bytes(),
+        };
+        let size = usize::try_from(size).map_err(|_| libc::EFBIG)?;
+        if bytes.len() < size {
+            check_size(&self.options, size)?;
         }
+        bytes.resize(size, b' ');
 
-        if let Value::String(ref mut original) = current {
-            let mut new_content = original.clone();
-
-            // 基于偏移量拼接新内容
-            if offset >= 0 && (offset as usize) < original.len() {
-                new_content.replace_range(offset as usize..offset as usize + data.len(), data);
-            } else if offset as usize >= original.len() {
-                new_content.push_str(data);
-            } else {
-                // 当offset不合法时，直接用data替换整个内容
-                new_content = data.to_string();
-            }
+        *current = Value::String(String::from_utf8(bytes).map_err(|_| libc::EINVAL)?);
+        self.dirty = true;
+        Ok(())
+    }
 
-            *original = new_content;
+    /// Applies a `setattr` to `ino`: a size, as `truncate` and `O_TRUNC` opens
+    /// ask, and the times in `set`, as `touch` and `utimensat` ask. A new size
+    /// also stamps the mtime, and any change the ctime, unless `set` names
+    /// them. Mode and owner are fixed.
+    fn set_attr(&mut self, ino: u64, size: Option<u64>, set: Times) -> Result<FileAttr, c_int> {
+        let now = SystemTime::now();
+        if let Some(size) = size {
+            self.truncate_json_at_path(&self.path_of(ino)?, size)?;
+        } else if set == Times::default() {
+            return self.get_attr(ino);
         } else {
-            println!("else current:{:?}", current);
+            self.path_of(ino)?;
         }
+        let times = self.times.entry(ino).or_default();
+        times.atime = set.atime.or(times.atime);
+        times.mtime = set.mtime.or(size.map(|_| now)).or(times.mtime);
+        times.ctime = set.ctime.or(Some(now));
+        times.crtime = set.crtime.or(times.crtime);
+        self.get_attr(ino)
     }
 
-    fn write_json_at_path3(&mut self, path: &str, offset: i64, data: &str) {
-        let mut current = Rc::make_mut(&mut self.json);
+    /// Splices `data` into `ino` at `offset`, as `write_json_at_path` does.
+    fn write_data(&mut self, ino: u64, offset: i64, data: &[u8]) -> Result<u32, c_int> {
+        let path = self.path_of(ino)?;
+        self.write_json_at_path(&path, offset, data)?;
+        Ok(data.len() as u32)
+    }
 
-        // 查找路径
-        for key in path.split('/').filter(|s| !s.is_empty()) {
-            match current {
-                Value::Object(map) => current = map.get_mut(key).unwrap(),
-                Value::Array(vec) => current = vec.get_mut(key.parse::<usize>().unwrap()).unwrap(),
-                _ => break,
-            }
-        }
+    /// Creates the empty string `name` under `parent`, handing the kernel a
+    /// reference to it.
+    fn make_node(&mut self, parent: u64, name: &str) -> Result<FileAttr, c_int> {
+        let parent_path = self.path_of(parent)?;
+        self.create_json_at_path(&parent_path, name)?;
+        let ino = self.allocate_inode(format!("{}/{}", parent_path, name));
+        *self.lookups.entry(ino).or_default() += 1;
+        Ok(self.create_attr(ino, &Value::String(String::new())))
+    }
 
-        match current {
-            Value::String(ref mut original) => {
-                let offset_usize = offset as usize;
-
-                // 确保 offset 是合法的字符边界
-                if original.is_char_boundary(offset_usize) {
-                    if offset_usize >= original.len() {
-                        // 如果 offset 超过当前字符串长度，追加数据
-                        original.push_str(data);
-                    } else {
-                        // 如果 offset 在字符串范围内，替换数据
-                        original.replace_range(offset_usize..offset_usize + data.len(), data);
-                    }
-                } else {
-                    // 如果不是字符边界，可以选择返回错误或调整 offset
-                    eprintln!("Offset is not a valid character boundary");
-                }
+    /// Adds an empty string called `name` to the object or array at `parent`.
+    /// In an array `name` is the index to insert at, up to its length; the
+    /// elements from there on move up one, and their inodes with them.
+    fn create_json_at_path(&mut self, parent: &str, name: &str) -> Result<(), c_int> {
+        let empty = Value::String(String::new());
+        match get_json_at_path_mut(Arc::make_mut(&mut self.json), parent).ok_or(ENOENT)? {
+            Value::Object(map) if map.contains_key(name) => Err(libc::EEXIST),
+            Value::Object(map) => {
+                map.insert(name.to_string(), empty);
+                self.dirty = true;
+                Ok(())
             }
-            Value::Number(num) => {
-                // 将数字转换为字符串再处理
-                let mut original = num.to_string();
-                let offset_usize = offset as usize;
-
-                if original.is_char_boundary(offset_usize) {
-                    if offset_usize >= original.len() {
-                        original.push_str(data);
-                    } else {
-                        original.replace_range(offset_usize..offset_usize + data.len(), data);
-                    }
-                    *current = Value::String(original);
-                } else {
-                    eprintln!("Offset is not a valid character boundary");
-                }
+            Value::Array(vec) => {
+                let index = name
+                    .parse()
+                    .ok()
+                    .filter(|&index| index <= vec.len())
+                    .ok_or(libc::EINVAL)?;
+                vec.insert(index, empty);
+                self.dirty = true;
+                self.shift_indices(parent, index);
+                Ok(())
             }
-            _ => {
-                // 对于其他类型，目前不支持写操作
+            _ => Err(libc::ENOTDIR),
+        }
+    }
+
+    /// Moves the inodes of the elements of the array at `parent` from `index`
+    /// on, and of everything below them, up one index, after an insertion
+    /// there.
+    fn shift_indices(&mut self, parent: &str, index: usize) {
+        let prefix = format!("{}/", parent);
+        let moved: Vec<(u64, String)> = self
+            .inodes
+            .iter()
+            .filter_map(|(&ino, path)| {
+                let rest = path.strip_prefix(&prefix)?;
+                // `below` is the rest of the path under the element, if any.
+                let (element, below) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                let element: usize = element.parse().ok().filter(|&i| i >= index)?;
+                Some((ino, format!("{}{}{}", prefix, element + 1, below)))
+            })
+            .collect();
+        for (ino, _) in &moved {
+            if let Some(old) = self.inodes.remove(ino) {
+                self.paths.remove(&old);
             }
         }
+        for (ino, path) in moved {
+            let path = Arc::new(path);
+            self.inodes.insert(ino, Arc::clone(&path));
+            self.paths.insert(path, ino);
+        }
     }
 
-    fn myflush(&mut self) {
-        eprintln!("Saving JSON data before unmounting...");
-        let json_str = serde_json::to_string_pretty(self.json.as_ref()).unwrap();
-        fs::write(self.json_path.as_ref(), json_str).unwrap();
-        eprintln!("JSON data saved successfully.");
+    /// Writes the whole document out, unless nothing changed since the last
+    /// time. A failed write is logged and keeps the changes pending.
+    fn myflush(&mut self) -> Result<(), c_int> {
+        if !self.dirty {
+            return Ok(());
+        }
+        debug!(slog_scope::logger(), "saving"; "file" => %self.json_path.display());
+        let json_str =
+            serde_json::to_string_pretty(self.json.as_ref()).map_err(|_| libc::EINVAL)?;
+        if let Err(err) = fs::write(self.json_path.as_ref(), json_str) {
+            warn!(slog_scope::logger(), "saving failed";
+                "file" => %self.json_path.display(), "error" => %err);
+            return Err(err.raw_os_error().unwrap_or(libc::EIO));
+        }
+        self.dirty = false;
+        Ok(())
     }
 }
 
@@ -198,38 +429,35 @@ struct ReadDirReply<'a> {
 impl Filesystem for JsonFS {
     fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
         debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "lookup", "io"=> "in", "parent" => parent, "name" => name.to_str().unwrap());
-        let parent_path = self.inodes.get(&parent).unwrap();
-        let path = format!("{}/{}", parent_path, name.to_str().unwrap());
-
-        let json = Rc::clone(&self.json);
-
-        if let Some(value) = get_json_at_path(json.as_ref(), &path) {
-            let ino = self.allocate_inode(path);
-            let attr = self.create_attr(ino, value);
-            debug!(slog_scope::logger(), "Filesystem func"; 
-                "op" => "lookup", "io"=> "out", "attr" => format!("{:?}", attr));
-            reply.entry(&Duration::new(1, 0), &attr, 0);
-        } else {
-            reply.error(ENOENT);
+            "op" => "lookup", "io"=> "in", "parent" => parent, "name" => ?name);
+        match utf8_name(name).and_then(|name| self.lookup_entry(parent, name)) {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func"; 
+                    "op" => "lookup", "io"=> "out", "attr" => format!("{:?}", attr));
+                reply.entry(&self.options.entry_ttl, &attr, 0);
+            }
+            Err(err) => reply.error(err),
         }
     }
 
+    /// The kernel sends `batch_forget` only from ABI 7.16; fuser passes each of
+    /// its nodes on here anyway.
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "forget", "io"=> "in", "ino" => ino, "nlookup" => nlookup);
+        self.forget_inode(ino, nlookup);
+    }
+
     fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
         debug!(slog_scope::logger(), "Filesystem func"; 
             "op" => "getattr", "io"=> "in", "ino" => ino);
-        let json = Rc::clone(&self.json);
-        if let Some(path) = self.inodes.get(&ino) {
-            if let Some(value) = get_json_at_path(json.as_ref(), path) {
-                let attr = self.create_attr(ino, value);
+        match self.get_attr(ino) {
+            Ok(attr) => {
                 debug!(slog_scope::logger(), "Filesystem func"; 
                     "op" => "getattr", "io"=> "out", "attr" => format!("{:?}", attr));
-                reply.attr(&Duration::new(1, 0), &attr);
-            } else {
-                reply.error(ENOENT);
+                reply.attr(&self.options.attr_ttl, &attr);
             }
-        } else {
-            reply.error(ENOENT);
+            Err(err) => reply.error(err),
         }
     }
     fn read(
@@ -247,27 +475,10 @@ impl Filesystem for JsonFS {
             "op" => "read", "io"=> "in", 
             "ino" => ino, "fh" => _fh, "offset" => offset, "size" => size, 
             "flags" => _flags, "lock_owner" => _lock_owner);
-        let json = Rc::clone(&self.json);
-        if let Some(path) = self.inodes.get(&ino) {
-            if let Some(value) = get_json_at_path(json.as_ref(), path) {
-                let content = match value {
-                    Value::String(s) => s.clone(),
-                    _ => value.to_string(),
-                };
-
-                let content_bytes = content.as_bytes();
-                let start = offset as usize;
-                let end = (offset as usize + size as usize).min(content_bytes.len());
 
-                debug!(slog_scope::logger(), "Filesystem func"; 
-                    "op" => "read", "io"=> "out", "content" => &content[start..end]);
-
-                reply.data(&content_bytes[start..end]);
-            } else {
-                reply.error(libc::ENOENT);
-            }
-        } else {
-            reply.error(libc::ENOENT);
+        match self.read_data(ino, offset, size) {
+            Ok(data) => reply.data(&data),
+            Err(err) => reply.error(err),
         }
     }
 
@@ -281,45 +492,27 @@ impl Filesystem for JsonFS {
     ) {
         debug!(slog_scope::logger(), "Filesystem func";
             "op" => "readdir", "io"=> "in", "ino" => ino, "fh" => _fh, "offset" => offset);
-        let json = Rc::clone(&self.json);
-        let nums: Vec<String>;
         let mut reply_res: Vec<ReadDirReply> = vec![];
-        if let Some(path) = self.inodes.get(&ino).map(|s| Rc::clone(s)) {
-            if let Some(value) = get_json_at_path(json.as_ref(), path.as_str()) {
-                let mut entries = vec![
-                    (ino, FileType::Directory, "."),
-                    (ino, FileType::Directory, ".."),
-                ];
-
-                match value {
-                    Value::Object(map) => {
-                        for (key, _) in map {
-                            let child_ino = self.allocate_inode(format!("{}/{}", path, key));
-                            entries.push((child_ino, FileType::RegularFile, key.as_str()));
-                        }
-                    }
-                    Value::Array(vec) => {
-                        nums = (0..vec.len()).map(|x| x.to_string()).collect();
-                        for (index, _) in vec.iter().enumerate() {
-                            let child_ino = self.allocate_inode(format!("{}/{}", path, index));
-                            entries.push((child_ino, FileType::RegularFile, nums[index].as_str()));
-                        }
-                    }
-                    _ => {}
-                }
-
-                for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
-                    let _ = reply.add(entry.0, (i + 1) as i64, entry.1, entry.2);
-                    reply_res.push(ReadDirReply {
-                        ino: entry.0,
-                        offset: (i + 1) as u64,
-                        file_type: entry.1,
-                        name: entry.2,
-                    })
-                }
-                debug!(slog_scope::logger(), "Filesystem func";
-                    "op" => "readdir", "io"=> "out", "reply" => format!("{:?}", reply_res));
+        if let Ok(children) = self.dir_entries(ino) {
+            let mut entries = vec![
+                (ino, FileType::Directory, "."),
+                (ino, FileType::Directory, ".."),
+            ];
+            for (child_ino, kind, name) in &children {
+                entries.push((*child_ino, *kind, name.as_str()));
             }
+
+            for (i, entry) in entries.into_iter().enumerate().skip(offset as usize) {
+                let _ = reply.add(entry.0, (i + 1) as i64, entry.1, entry.2);
+                reply_res.push(ReadDirReply {
+                    ino: entry.0,
+                    offset: (i + 1) as u64,
+                    file_type: entry.1,
+                    name: entry.2,
+                })
+            }
+            debug!(slog_scope::logger(), "Filesystem func";
+                "op" => "readdir", "io"=> "out", "reply" => format!("{:?}", reply_res));
         }
         reply.ok();
     }
@@ -341,13 +534,14 @@ impl Filesystem for JsonFS {
             "op" => "write", "io"=> "in", 
             "ino" => ino, "fh" => _fh, "offset" => _offset, "data" => format!("{:?}", data), 
             "flags" => _flags, "lock_owner" => _lock_owner);
-        let path = Rc::clone(self.inodes.get(&ino).unwrap());
-        let content = std::str::from_utf8(data).unwrap();
-
-        //self.write_json_at_path3(path.as_str(), _offset,content);
-        self.write_json_at_path(path.as_str(), content);
-        reply.written(content.len() as u32);
-        self.myflush();
+        match self.write_data(ino, _offset, data) {
+            Ok(written) => reply.written(written),
+            Err(err) => {
+                warn!(slog_scope::logger(), "Filesystem func";
+                    "op" => "write", "io"=> "out", "ino" => ino, "error" => err);
+                reply.error(err);
+            }
+        }
     }
     fn mknod(
         &mut self,
@@ -363,18 +557,14 @@ impl Filesystem for JsonFS {
             "op" => "mknod", "io"=> "in", 
             "parent" => parent, "name" => name.to_str(), "mode" => mode);
 
-        let parent_path = self.inodes.get(&parent).unwrap();
-        let path = format!("{}/{}", parent_path, name.to_str().unwrap());
-
-        // Create a new entry in the JSON structure for the file
-        self.write_json_at_path(&path, "");
-
-        let ino = self.allocate_inode(path);
-        let attr = self.create_attr(ino, &Value::String("".to_string()));
-
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "mknod", "io"=> "out", "attr" => format!("{:?}", attr));
-        reply.entry(&Duration::new(1, 0), &attr, 0);
+        match utf8_name(name).and_then(|name| self.make_node(parent, name)) {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func"; 
+                    "op" => "mknod", "io"=> "out", "attr" => format!("{:?}", attr));
+                reply.entry(&self.options.entry_ttl, &attr, 0);
+            }
+            Err(err) => reply.error(err),
+        }
     }
     fn setattr(
         &mut self,
@@ -384,11 +574,11 @@ impl Filesystem for JsonFS {
         uid: Option<u32>,
         gid: Option<u32>,
         size: Option<u64>,
-        _atime: Option<fuser::TimeOrNow>,
-        _mtime: Option<fuser::TimeOrNow>,
-        _ctime: Option<std::time::SystemTime>,
+        atime: Option<fuser::TimeOrNow>,
+        mtime: Option<fuser::TimeOrNow>,
+        ctime: Option<std::time::SystemTime>,
         fh: Option<u64>,
-        _crtime: Option<std::time::SystemTime>,
+        crtime: Option<std::time::SystemTime>,
         _chgtime: Option<std::time::SystemTime>,
         _bkuptime: Option<std::time::SystemTime>,
         flags: Option<u32>,
@@ -398,8 +588,16 @@ impl Filesystem for JsonFS {
             "op" => "setattr", "io"=> "in", 
             "ino" => ino, "mode" => mode, "uid" => uid, "gid" => gid, "size" => size, 
             "fh" => fh, "flags" => flags);
-        let attr = self.create_attr(ino, &Value::String("".to_string()));
-        reply.attr(&Duration::new(1, 0), &attr);
+        let times = Times {
+            atime: atime.map(resolve_time),
+            mtime: mtime.map(resolve_time),
+            ctime,
+            crtime,
+        };
+        match self.set_attr(ino, size, times) {
+            Ok(attr) => reply.attr(&self.options.attr_ttl, &attr),
+            Err(err) => reply.error(err),
+        }
     }
     fn flush(
         &mut self,
@@ -413,8 +611,357 @@ impl Filesystem for JsonFS {
             "op" => "flush", "io"=> "in", 
             "fh" => _fh, "lock_owner" => _lock_owner);
 
-        self.myflush();
-        reply.ok();
+        match self.myflush() {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
         debug!(slog_scope::logger(), "Filesystem func"; "op" => "flush", "io"=> "out");
     }
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "fsync", "io"=> "in", "ino" => ino);
+        match self.myflush() {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn destroy(&mut self) {
+        // Already logged; there is no one left to report it to.
+        let _ = self.myflush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A JSON file in the temporary directory, removed again when dropped.
+    struct TempJson(PathBuf);
+
+    impl std::ops::Deref for TempJson {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempJson {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempJson {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn temp_json(name: &str, content: &str) -> TempJson {
+        let path =
+            std::env::temp_dir().join(format!("jsonfs-path-{}-{}.json", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        TempJson(path)
+    }
+
+    #[test]
+    fn readdir_leaves_inodes_to_lookup() {
+        let path = temp_json("readdir", r#"{"a": {"b": 1}, "c": [1, 2], "d": 3}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let listed = fs.dir_entries(FUSE_ROOT_ID).unwrap();
+        assert!(listed.iter().all(|(ino, _, _)| *ino == UNKNOWN_INO));
+        assert_eq!(fs.inodes.len(), 1);
+        for (_, kind, name) in &listed {
+            let attr = fs.lookup_entry(FUSE_ROOT_ID, name).unwrap();
+            assert_eq!(*kind, attr.kind, "{}", name);
+        }
+        assert!(listed
+            .iter()
+            .any(|(_, kind, _)| *kind == FileType::Directory));
+
+        // Looked up, a child is listed with its inode, until forgotten.
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+        let listed = fs.dir_entries(FUSE_ROOT_ID).unwrap();
+        assert_eq!(listed[0], (a, FileType::Directory, "a".to_string()));
+        fs.forget_inode(a, 2);
+        assert_eq!(fs.dir_entries(FUSE_ROOT_ID).unwrap()[0].0, UNKNOWN_INO);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn forget_releases_inodes() {
+        let path = temp_json("forget", r#"{"a": {"b": 1}, "c": 2}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let count = fs.inodes.len();
+
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+        let b = fs.lookup_entry(a, "b").unwrap().ino;
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino, a);
+        assert_eq!(fs.inodes.len(), count + 2);
+
+        fs.forget_inode(b, 1);
+        assert_eq!(fs.inodes.len(), count + 1);
+        // `a` was looked up twice.
+        fs.forget_inode(a, 1);
+        assert_eq!(fs.inodes.get(&a).map(|path| path.as_str()), Some("/a"));
+        fs.forget_inode(a, 1);
+        assert_eq!(fs.inodes.len(), count);
+        assert!(fs.paths.len() == count && fs.lookups.is_empty());
+
+        fs.forget_inode(FUSE_ROOT_ID, 1);
+        assert!(fs.lookup_entry(FUSE_ROOT_ID, "c").is_ok());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_splice_at_their_offset() {
+        let path = temp_json("write", r#"{"s": "hello", "n": 42, "d": {}, "a": []}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = |fs: &JsonFS| fs.json["s"].clone();
+
+        fs.write_json_at_path("/s", 1, b"ipp").unwrap();
+        assert_eq!(s(&fs), "hippo");
+        fs.write_json_at_path("/s", 5, b"s!").unwrap();
+        assert_eq!(s(&fs), "hippos!");
+        fs.write_json_at_path("/s", 100, b"?").unwrap();
+        assert_eq!(s(&fs), "hippos!?");
+        fs.write_json_at_path("/s", 4, "é and more".as_bytes())
+            .unwrap();
+        assert_eq!(s(&fs), "hippé and more");
+        fs.write_json_at_path("/n", 0, b"7").unwrap();
+        assert_eq!(fs.json["n"], "72");
+
+        assert_eq!(fs.write_json_at_path("/s", -1, b"x"), Err(libc::EINVAL));
+        // Overwriting half of the é leaves invalid UTF-8.
+        assert_eq!(fs.write_json_at_path("/s", 5, b"x"), Err(libc::EINVAL));
+        assert_eq!(fs.write_json_at_path("/d", 0, b"x"), Err(libc::EISDIR));
+        assert_eq!(fs.write_json_at_path("/missing", 0, b"x"), Err(ENOENT));
+        assert_eq!(s(&fs), "hippé and more");
+
+        fs.create_json_at_path("", "new").unwrap();
+        fs.create_json_at_path("/a", "0").unwrap();
+        assert_eq!(fs.create_json_at_path("", "new"), Err(libc::EEXIST));
+        assert_eq!(fs.create_json_at_path("/a", "5"), Err(libc::EINVAL));
+        assert_eq!(fs.json["new"], "");
+        assert_eq!(fs.json["a"], serde_json::json!([""]));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn nodes_can_be_driven_without_a_mount() {
+        let path = temp_json("api", r#"{"d": {"s": "x"}, "n": 1}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let d = fs.lookup_entry(FUSE_ROOT_ID, "d").unwrap();
+        assert_eq!(d.kind, FileType::Directory);
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "missing"), Err(ENOENT));
+        assert_eq!(fs.lookup_entry(1000, "d"), Err(ENOENT));
+
+        let f = fs.make_node(d.ino, "f").unwrap();
+        assert_eq!(fs.make_node(d.ino, "f"), Err(libc::EEXIST));
+        assert_eq!(fs.write_data(f.ino, 0, b"hello").unwrap(), 5);
+        assert_eq!(fs.read_data(f.ino, 0, 16).unwrap(), b"hello");
+        assert_eq!(fs.get_attr(f.ino).unwrap().size, 5);
+
+        let names: Vec<_> = fs
+            .dir_entries(d.ino)
+            .unwrap()
+            .into_iter()
+            .map(|(_, _, name)| name)
+            .collect();
+        assert_eq!(names, ["f", "s"]);
+        let n = fs.lookup_entry(FUSE_ROOT_ID, "n").unwrap();
+        assert_eq!(fs.dir_entries(n.ino), Err(libc::ENOTDIR));
+        assert_eq!(fs.write_data(d.ino, 0, b"x"), Err(libc::EISDIR));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_are_saved_once_on_flush() {
+        let original = r#"{"s": ""}"#;
+        let path = temp_json("coalesce", original);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = fs.lookup_entry(FUSE_ROOT_ID, "s").unwrap().ino;
+
+        for (offset, chunk) in [b"ab", b"cd", b"ef"].iter().enumerate() {
+            fs.write_data(s, offset as i64 * 2, *chunk).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["s"], "abcdef");
+
+        // With nothing new, a second flush leaves the file alone.
+        fs::remove_file(&path).unwrap();
+        fs.myflush().unwrap();
+        assert!(!path.exists());
+
+        // A failed save is reported, and tried again by the next flush.
+        fs.write_data(s, 0, b"x").unwrap();
+        fs::create_dir(&path).unwrap();
+        assert_eq!(fs.myflush(), Err(libc::EISDIR));
+        fs::remove_dir(&path).unwrap();
+        fs.myflush().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("xbcdef"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn o_trunc_empties_the_file() {
+        let path = temp_json("truncate", r#"{"s": "hello", "n": 42, "d": {}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = fs.lookup_entry(FUSE_ROOT_ID, "s").unwrap().ino;
+
+        // What the kernel sends for `> s`, or File::create on an existing file.
+        assert_eq!(fs.set_attr(s, Some(0), Times::default()).unwrap().size, 0);
+        assert_eq!(fs.json["s"], "");
+        assert!(fs.read_data(s, 0, 16).unwrap().is_empty());
+        fs.write_data(s, 0, b"new").unwrap();
+        assert_eq!(fs.json["s"], "new");
+
+        let n = fs.lookup_entry(FUSE_ROOT_ID, "n").unwrap().ino;
+        assert_eq!(fs.set_attr(n, Some(1), Times::default()).unwrap().size, 1);
+        assert_eq!(fs.json["n"], "4");
+        assert_eq!(fs.set_attr(n, Some(3), Times::default()).unwrap().size, 3);
+        assert_eq!(fs.json["n"], "4  ");
+        // Other attributes leave the content alone.
+        assert_eq!(fs.set_attr(n, None, Times::default()).unwrap().size, 3);
+
+        let d = fs.lookup_entry(FUSE_ROOT_ID, "d").unwrap().ino;
+        assert_eq!(fs.set_attr(d, Some(0), Times::default()), Err(libc::EISDIR));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sizes_are_capped_by_max_value_bytes() {
+        let path = temp_json("max-value-bytes", r#"{"s": "hello"}"#);
+        let options = Options {
+            max_value_bytes: Some(8),
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let s = fs.lookup_entry(FUSE_ROOT_ID, "s").unwrap().ino;
+
+        assert_eq!(
+            fs.set_attr(s, Some(1 << 40), Times::default()),
+            Err(libc::EFBIG)
+        );
+        assert_eq!(fs.write_data(s, 5, b" world"), Err(libc::EFBIG));
+        assert_eq!(fs.json["s"], "hello");
+        assert_eq!(fs.set_attr(s, Some(8), Times::default()).unwrap().size, 8);
+        assert_eq!(fs.set_attr(s, Some(2), Times::default()).unwrap().size, 2);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn setattr_sets_times() {
+        let path = temp_json("times", r#"{"s": "hello", "t": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = fs.lookup_entry(FUSE_ROOT_ID, "s").unwrap().ino;
+        assert_eq!(fs.get_attr(s).unwrap().mtime, UNIX_EPOCH);
+
+        // What `touch -d` sends: both times, leaving the content alone.
+        let past = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let set = Times {
+            atime: Some(past),
+            mtime: Some(past),
+            ..Times::default()
+        };
+        let attr = fs.set_attr(s, None, set).unwrap();
+        assert_eq!((attr.atime, attr.mtime), (past, past));
+        assert!(attr.ctime > past);
+        assert_eq!(fs.json["s"], "hello");
+
+        // A truncation stamps the mtime, and keeps the atime.
+        let attr = fs.set_attr(s, Some(0), Times::default()).unwrap();
+        assert!(attr.mtime > past);
+        assert_eq!(attr.atime, past);
+
+        // Other nodes keep the epoch.
+        let t = fs.lookup_entry(FUSE_ROOT_ID, "t").unwrap().ino;
+        assert_eq!(fs.get_attr(t).unwrap().mtime, UNIX_EPOCH);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn directory_nlink_counts_subdirectories() {
+        let path = temp_json("nlink", r#"{"a": {}, "b": {"c": []}, "d": 1}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        assert_eq!(fs.get_attr(FUSE_ROOT_ID).unwrap().nlink, 4);
+        let b = fs.lookup_entry(FUSE_ROOT_ID, "b").unwrap();
+        assert_eq!(b.nlink, 3);
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().nlink, 2);
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "d").unwrap().nlink, 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_inserts_into_arrays() {
+        let path = temp_json("insert", r#"{"arr": ["a", "b", {"c": "d"}]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let arr = fs.lookup_entry(FUSE_ROOT_ID, "arr").unwrap().ino;
+        let b = fs.lookup_entry(arr, "1").unwrap().ino;
+        let obj = fs.lookup_entry(arr, "2").unwrap().ino;
+        let c = fs.lookup_entry(obj, "c").unwrap().ino;
+
+        let middle = fs.make_node(arr, "1").unwrap().ino;
+        assert_eq!(
+            fs.json["arr"],
+            serde_json::json!(["a", "", "b", {"c": "d"}])
+        );
+        assert!(![b, obj, c].contains(&middle));
+        // The moved elements keep their inodes.
+        assert_eq!(fs.read_data(b, 0, 16).unwrap(), b"b");
+        assert_eq!(fs.path_of(b).unwrap().as_str(), "/arr/2");
+        assert_eq!(fs.path_of(c).unwrap().as_str(), "/arr/3/c");
+        assert_eq!(fs.read_data(c, 0, 16).unwrap(), b"d");
+        assert_eq!(fs.lookup_entry(arr, "1").unwrap().ino, middle);
+
+        fs.make_node(arr, "0").unwrap();
+        assert_eq!(
+            fs.json["arr"],
+            serde_json::json!(["", "a", "", "b", {"c": "d"}])
+        );
+        assert_eq!(fs.path_of(middle).unwrap().as_str(), "/arr/2");
+        assert_eq!(fs.path_of(c).unwrap().as_str(), "/arr/4/c");
+
+        // Appending still works; past the end does not.
+        fs.make_node(arr, "5").unwrap();
+        assert_eq!(fs.make_node(arr, "7"), Err(libc::EINVAL));
+        assert_eq!(fs.make_node(arr, "x"), Err(libc::EINVAL));
+        assert_eq!(fs.json["arr"].as_array().unwrap().len(), 6);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_inside_a_character_does_not_panic() {
+        let path = temp_json("read_split", r#"{"s": "héllo"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = fs.allocate_inode("/s".to_string());
+
+        assert_eq!(fs.read_data(s, 2, 16).unwrap(), &"héllo".as_bytes()[2..]);
+        assert!(fs.read_data(s, 100, 16).unwrap().is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
 }
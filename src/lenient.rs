@@ -0,0 +1,113 @@
+/// Blanks out what `--lenient` accepts beyond strict JSON: `//` and `/* */`
+/// comments, and a comma right before a closing `}` or `]`. Each removed byte
+/// becomes a space, keeping newlines, so parse errors still point at the
+/// right line and column of the original file.
+pub(crate) fn relax(data: &str) -> String {
+    let mut out = data.as_bytes().to_vec();
+    let mut in_string = false;
+    let mut escaped = false;
+    // A comma that is trailing if the next token closes its container.
+    let mut comma = None;
+    let mut i = 0;
+    while i < out.len() {
+        let byte = out[i];
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+        match (byte, out.get(i + 1)) {
+            (b'/', Some(b'/')) => {
+                let end = out[i..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(out.len(), |n| i + n);
+                blank(&mut out[i..end]);
+                i = end;
+                continue;
+            }
+            (b'/', Some(b'*')) => {
+                let end = out[i + 2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map_or(out.len(), |n| i + 2 + n + 2);
+                blank(&mut out[i..end]);
+                i = end;
+                continue;
+            }
+            (b' ' | b'\t' | b'\r' | b'\n', _) => {}
+            (b'}' | b']', _) => {
+                if let Some(at) = comma.take() {
+                    out[at] = b' ';
+                }
+            }
+            (b',', _) => comma = Some(i),
+            (b'"', _) => {
+                in_string = true;
+                comma = None;
+            }
+            _ => comma = None,
+        }
+        i += 1;
+    }
+    // Comments are blanked whole, so no character is cut in two.
+    String::from_utf8(out).unwrap()
+}
+
+fn blank(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut().filter(|b| **b != b'\n') {
+        *byte = b' ';
+    }
+}
+
+/// Bare tokens `--allow-nan` accepts where JSON expects a number, as
+/// JavaScript and Python's `json` module write them.
+const NON_FINITE: [&str; 3] = ["-Infinity", "Infinity", "NaN"];
+
+/// Quotes each bare `NaN`, `Infinity` and `-Infinity` in `data` into a JSON
+/// string of the same text, which JSON can hold; returns the new text and
+/// how many were quoted. Quoting adds two bytes per token, so a parse error
+/// later on the same line is reported that much further right.
+pub(crate) fn quote_non_finite(data: &str) -> (String, usize) {
+    let bytes = data.as_bytes();
+    let mut out = String::with_capacity(data.len());
+    let mut count = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if byte == b'"' {
+            in_string = true;
+        } else if let Some(token) = NON_FINITE.iter().find(|token| {
+            let end = i + token.len();
+            bytes[i..].starts_with(token.as_bytes())
+                && !bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric())
+                && !(i > 0 && bytes[i - 1].is_ascii_alphanumeric())
+        }) {
+            out.push('"');
+            out.push_str(token);
+            out.push('"');
+            count += 1;
+            i += token.len();
+            continue;
+        }
+        // A character is copied whole; only its first byte matters above.
+        let len = data[i..].chars().next().map_or(1, char::len_utf8);
+        out.push_str(&data[i..i + len]);
+        i += len;
+    }
+    (out, count)
+}
@@ -1,39 +1,515 @@
-use fuser::MountOption;
+use fuser::{BackgroundSession, Filesystem, MountOption, Notifier, Session};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 use slog::{o, Drain, Logger};
-use slog_async;
-use slog_scope;
-use slog_term;
 
 use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
 
 // mod test;
 // mod tree;
-//mod jsonfs;
+mod duplicates;
+mod error;
+mod format;
+mod inplace;
+mod jsonfs;
+mod lenient;
+mod overlay;
 mod pinjsonfs;
 
-fn setup_log() -> Logger {
-    // 打开一个日志文件，支持追加模式
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open("log.txt")
-        .unwrap();
+const USAGE: &str = "Usage: jsonfs [--create] [--ignore-case] [--array-as-lines] [--infer-arrays] [--typed-extensions] [--strict-types] [--humanize] [--escape-keys] [--wrap-scalar] [--lenient] [--allow-nan] [--in-place-edit] [--show-meta] [--gzip] [--backup] [--shared] [--no-flush] [--no-perm-check] [--allow-other] [--log-file PATH] [--log-level LEVEL] [--no-term-log] [--backend pin|path] [--attr-ttl SECS] [--entry-ttl SECS]
+             [--uid UID] [--gid GID] [--atime strict|relatime|noatime] [--root-pointer POINTER] [--max-value-bytes N] [--max-depth N] [--hide GLOB]... [--overlay OVERRIDES_FILE] [--format json|yaml|toml] [--schema SCHEMA_FILE]
+             [--indent N|tab] [--read-pretty] [--flush-interval SECS] [--mount-timeout SECS] <JSON_FILE> <MOUNTPOINT>
+       jsonfs [OPTIONS] --file <JSON_FILE> --mount <MOUNTPOINT>
+       jsonfs --check <JSON_FILE>";
+
+const DEFAULT_MOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct LogOptions {
+    /// Append logs to this file as well; `None` keeps logging to the terminal only.
+    file: Option<PathBuf>,
+    level: slog::Level,
+    /// Log to stderr; disable for daemon use.
+    terminal: bool,
+}
+
+/// Which filesystem implementation to mount.
+#[derive(Debug, PartialEq)]
+enum Backend {
+    /// `pinjsonfs`, the default; supports the full set of options.
+    Pin,
+    /// `jsonfs`, the older path-keyed implementation; of the fs options it
+    /// only takes the cache timeouts and the size limit.
+    Path,
+}
+
+struct Args {
+    json_file: String,
+    /// Empty with `--check`, which doesn't mount.
+    mountpoint: String,
+    /// Only validate the file and print a summary of its nodes.
+    check: bool,
+    backend: Backend,
+    /// Insist on `allow_other`, failing the mount if fuse.conf forbids it.
+    allow_other: bool,
+    /// Save changes in the background this often; zero only saves on flush
+    /// and unmount.
+    flush_interval: Duration,
+    /// Give up if the kernel hasn't initialized the mount after this long;
+    /// zero waits indefinitely. Only the pin backend reports readiness.
+    mount_timeout: Duration,
+    log: LogOptions,
+    fs: pinjsonfs::Options,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut positionals = vec![];
+    let mut log = LogOptions {
+        file: None,
+        level: slog::Level::Info,
+        terminal: true,
+    };
+    let mut level = std::env::var("RUST_LOG").ok();
+    let mut fs = pinjsonfs::Options::default();
+    let mut check = false;
+    let mut backend = Backend::Pin;
+    let mut flush_interval = Duration::ZERO;
+    let mut mount_timeout = DEFAULT_MOUNT_TIMEOUT;
+    let mut allow_other = false;
+
+    let mut json_file = None;
+    let mut mountpoint = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Everything after `--` is positional, even if it starts with `--`.
+            "--" => positionals.extend(args.by_ref()),
+            "--file" => json_file = Some(args.next().ok_or("--file requires a path")?),
+            "--mount" => mountpoint = Some(args.next().ok_or("--mount requires a directory")?),
+            "--log-file" => {
+                log.file = Some(args.next().ok_or("--log-file requires a path")?.into());
+            }
+            "--log-level" => {
+                level = Some(args.next().ok_or("--log-level requires a level")?);
+            }
+            "--no-term-log" => log.terminal = false,
+            "--create" | "--init" => fs.create = true,
+            "--ignore-case" => fs.ignore_case = true,
+            "--array-as-lines" => fs.array_as_lines = true,
+            "--infer-arrays" => fs.infer_arrays = true,
+            "--typed-extensions" => fs.typed_extensions = true,
+            "--strict-types" => fs.strict_types = true,
+            "--humanize" => fs.humanize = true,
+            "--escape-keys" => fs.escape_keys = true,
+            "--wrap-scalar" => fs.wrap_scalar = true,
+            "--lenient" | "--json5" => fs.lenient = true,
+            "--allow-nan" => fs.allow_nan = true,
+            "--in-place-edit" => fs.in_place_edit = true,
+            "--show-meta" => fs.show_meta = true,
+            "--gzip" => fs.gzip = true,
+            "--backup" => fs.backup = true,
+            "--shared" => fs.shared = true,
+            "--no-flush" | "--memory-only" => fs.no_flush = true,
+            "--no-perm-check" => fs.no_perm_check = true,
+            "--read-pretty" => fs.read_pretty = true,
+            "--allow-other" => allow_other = true,
+            "--check" => check = true,
+            "--root-pointer" => {
+                fs.root_pointer = args
+                    .next()
+                    .ok_or("--root-pointer requires a JSON pointer")?;
+            }
+            "--overlay" => {
+                fs.overlay = Some(
+                    args.next()
+                        .ok_or("--overlay requires an overrides file")?
+                        .into(),
+                );
+            }
+            "--atime" => {
+                fs.atime = match args
+                    .next()
+                    .ok_or("--atime requires strict, relatime or noatime")?
+                    .as_str()
+                {
+                    "strict" => pinjsonfs::Atime::Strict,
+                    "relatime" => pinjsonfs::Atime::Relatime,
+                    "noatime" => pinjsonfs::Atime::Noatime,
+                    other => return Err(format!("unknown atime mode {}", other)),
+                };
+            }
+            "--schema" => {
+                fs.schema = Some(args.next().ok_or("--schema requires a schema file")?.into());
+            }
+            "--format" => {
+                fs.format = Some(
+                    match args
+                        .next()
+                        .ok_or("--format requires json, yaml or toml")?
+                        .as_str()
+                    {
+                        "json" => format::Format::Json,
+                        "yaml" => format::Format::Yaml,
+                        "toml" => format::Format::Toml,
+                        other => return Err(format!("unknown format {}", other)),
+                    },
+                );
+            }
+            "--uid" => fs.uid = parse_id(&arg, args.next())?,
+            "--gid" => fs.gid = parse_id(&arg, args.next())?,
+            "--max-value-bytes" => {
+                let value = args.next().ok_or("--max-value-bytes requires a size")?;
+                let max = value
+                    .parse()
+                    .map_err(|_| format!("invalid --max-value-bytes {}", value))?;
+                fs.max_value_bytes = Some(max);
+            }
+            "--hide" => fs.hide.push(args.next().ok_or("--hide requires a glob")?),
+            "--max-depth" => {
+                let value = args.next().ok_or("--max-depth requires a depth")?;
+                fs.max_depth = value
+                    .parse()
+                    .map_err(|_| format!("invalid --max-depth {}", value))?;
+            }
+            "--indent" => {
+                let value = args.next().ok_or("--indent requires a width or tab")?;
+                fs.indent = match value.as_str() {
+                    "tab" => "\t".to_string(),
+                    _ => " ".repeat(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid --indent {}", value))?,
+                    ),
+                };
+            }
+            "--attr-ttl" => fs.attr_ttl = parse_ttl(&arg, args.next())?,
+            "--entry-ttl" => fs.entry_ttl = parse_ttl(&arg, args.next())?,
+            "--flush-interval" => flush_interval = parse_ttl(&arg, args.next())?,
+            "--mount-timeout" => mount_timeout = parse_ttl(&arg, args.next())?,
+            "--backend" => {
+                backend = match args
+                    .next()
+                    .ok_or("--backend requires pin or path")?
+                    .as_str()
+                {
+                    "pin" => Backend::Pin,
+                    "path" => Backend::Path,
+                    other => return Err(format!("unknown backend {}", other)),
+                };
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positionals.push(arg),
+        }
+    }
+
+    if backend == Backend::Path {
+        let defaults = pinjsonfs::Options::default();
+        let unsupported = pinjsonfs::Options {
+            entry_ttl: defaults.entry_ttl,
+            attr_ttl: defaults.attr_ttl,
+            max_value_bytes: defaults.max_value_bytes,
+            ..fs.clone()
+        };
+        if unsupported != defaults || !flush_interval.is_zero() {
+            return Err("--backend path takes no filesystem options but \
+                 --attr-ttl, --entry-ttl and --max-value-bytes"
+                .to_string());
+        }
+    }
+
+    if let Some(level) = level {
+        log.level = level
+            .parse()
+            .map_err(|_| format!("invalid log level {}", level))?;
+    }
+
+    // Positionals fill in, in order, whatever the flags left unset.
+    let mut positionals = positionals.into_iter();
+    let json_file = json_file
+        .or_else(|| positionals.next())
+        .ok_or("missing <JSON_FILE>")?;
+    let mountpoint = match mountpoint.or_else(|| positionals.next()) {
+        Some(mountpoint) => mountpoint,
+        None if check => String::new(),
+        None => return Err("missing <MOUNTPOINT>".to_string()),
+    };
+    if let Some(extra) = positionals.next() {
+        return Err(format!("unexpected argument {}", extra));
+    }
+    Ok(Args {
+        json_file,
+        mountpoint,
+        check,
+        backend,
+        allow_other,
+        flush_interval,
+        mount_timeout,
+        log,
+        fs,
+    })
+}
+
+/// Parses a cache timeout in (possibly fractional) seconds.
+fn parse_ttl(flag: &str, value: Option<String>) -> Result<Duration, String> {
+    let value = value.ok_or_else(|| format!("{} requires a number of seconds", flag))?;
+    value
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid {} {}", flag, value))
+}
+
+/// Parses a numeric user or group id.
+fn parse_id(flag: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or_else(|| format!("{} requires a numeric id", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {}", flag, value))
+}
+
+/// Whether `/etc/fuse.conf` text lets unprivileged users mount with
+/// `allow_other`.
+fn fuse_conf_allows_other(conf: &str) -> bool {
+    conf.lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .any(|line| line == "user_allow_other")
+}
+
+/// Mount options: other users may access the mount and it is unmounted when
+/// jsonfs exits. Both need `allow_other` (fuser adds it for `auto_unmount`), so
+/// unless `--allow-other` insists, they are dropped with a warning when
+/// fuse.conf doesn't permit it, instead of failing the mount.
+fn mount_options(allow_other: bool) -> Vec<MountOption> {
+    let permitted = allow_other
+        || unsafe { libc::geteuid() } == 0
+        || std::fs::read_to_string("/etc/fuse.conf")
+            .is_ok_and(|conf| fuse_conf_allows_other(&conf));
+    if permitted {
+        vec![MountOption::AutoUnmount, MountOption::AllowOther]
+    } else {
+        slog::warn!(
+            slog_scope::logger(),
+            "user_allow_other is not set in /etc/fuse.conf; mounting for this user only, \
+             without auto_unmount"
+        );
+        vec![]
+    }
+}
+
+/// Both filesystems are served from a background thread, so they must stay
+/// `Send`; this stops compiling if either picks up non-thread-safe state.
+const _: fn() = || {
+    fn assert_send<FS: Filesystem + Send>() {}
+    assert_send::<pinjsonfs::SharedFS>();
+    assert_send::<jsonfs::JsonFS>();
+};
+
+/// Runs `session` on a background thread and returns once the filesystem's
+/// `init` has fired `ready`, so callers never stat a mountpoint that isn't
+/// served yet. Fails, unmounting again, if that takes longer than `timeout`
+/// (zero waits indefinitely), and with the session's own error if it ends
+/// before `init`.
+fn mount_background<FS: Filesystem + Send + 'static>(
+    session: Session<FS>,
+    ready: &mpsc::Receiver<()>,
+    timeout: Duration,
+) -> io::Result<BackgroundSession> {
+    let session = session.spawn()?;
+    match wait_ready(ready, timeout) {
+        Ok(()) => Ok(session),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!(
+                "{} was not ready after {:?}",
+                session.mountpoint.display(),
+                timeout
+            ),
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(session
+            .guard
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            .err()
+            .unwrap_or_else(|| io::Error::other("the session ended before init"))),
+    }
+}
+
+/// Waits for `session` to end, or for SIGINT or SIGTERM, which unmounts it
+/// first. Either way the session ends as it does after `fusermount -u`: the
+/// filesystem's `destroy` saves what is pending before this returns. To check
+/// by hand, mount with a long `--flush-interval`, write a file and `kill` the
+/// process; the change is in the JSON file once it has exited.
+fn serve(session: BackgroundSession, mut signals: Signals) -> io::Result<()> {
+    let (ended, wait) = mpsc::channel();
+    let handle = signals.handle();
+    let signal_ended = ended.clone();
+    let watcher = thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            let _ = signal_ended.send(Some(signal));
+        }
+    });
+    let joiner = {
+        // The rest of `session`, its mount, is dropped at the end of this
+        // block, which unmounts if the session is still running.
+        let session = session;
+        let guard = session.guard;
+        let joiner = thread::spawn(move || {
+            let result = guard.join();
+            let _ = ended.send(None);
+            result
+        });
+        if let Ok(Some(signal)) = wait.recv() {
+            slog::info!(
+                slog_scope::logger(),
+                "received signal {}, unmounting",
+                signal
+            );
+        }
+        joiner
+    };
+    handle.close();
+    let _ = watcher.join();
+    joiner
+        .join()
+        .and_then(|result| result)
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// Waits for the signal from `init`, at most `timeout` unless that is zero.
+fn wait_ready(ready: &mpsc::Receiver<()>, timeout: Duration) -> Result<(), mpsc::RecvTimeoutError> {
+    if timeout.is_zero() {
+        ready
+            .recv()
+            .map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+    } else {
+        ready.recv_timeout(timeout)
+    }
+}
+
+/// `path` made absolute: a leading `~` is the home directory, and a relative
+/// path is taken from the current directory. Symlinks are resolved if the
+/// path exists; a JSON file that `--create` will make need not.
+fn expand_path(path: &str) -> io::Result<String> {
+    let home = || {
+        std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .ok_or_else(|| io::Error::other("HOME is not set"))
+    };
+    let expanded = match path.strip_prefix('~') {
+        Some("") => home()?,
+        Some(rest) if rest.starts_with('/') => home()?.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    };
+    let absolute = std::path::absolute(&expanded)?;
+    let resolved = std::fs::canonicalize(&absolute).unwrap_or(absolute);
+    resolved
+        .into_os_string()
+        .into_string()
+        .map_err(|_| io::Error::other("the path is not UTF-8"))
+}
+
+/// Resolves the JSON file and the mountpoint with `expand_path`, so saves go
+/// to the path resolved now, whatever directory the process is in by then.
+/// `--check` has no mountpoint to resolve.
+fn expand_paths(args: &mut Args) -> Result<(), String> {
+    let mut paths = vec![&mut args.json_file];
+    if !args.check {
+        paths.push(&mut args.mountpoint);
+    }
+    for path in paths {
+        *path = expand_path(path).map_err(|err| format!("cannot resolve {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+/// Checks that `mountpoint` can be mounted on: an existing directory, not
+/// already a mountpoint. The error says how to fix it.
+fn check_mountpoint(mountpoint: &Path) -> Result<(), String> {
+    let shown = mountpoint.display();
+    let meta = match std::fs::metadata(mountpoint) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "mountpoint {} does not exist; create it with `mkdir -p {}`",
+                shown, shown
+            ));
+        }
+        Err(err) if err.raw_os_error() == Some(libc::ENOTCONN) => {
+            return Err(format!(
+                "{} is a stale mount whose filesystem has exited; unmount it with \
+                 `fusermount -u {}`",
+                shown, shown
+            ));
+        }
+        Err(err) => return Err(format!("cannot access mountpoint {}: {}", shown, err)),
+    };
+    if !meta.is_dir() {
+        return Err(format!(
+            "mountpoint {} is not a directory; mount on an empty directory instead",
+            shown
+        ));
+    }
+    let parent = std::fs::canonicalize(mountpoint)
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .and_then(|parent| std::fs::metadata(parent).ok());
+    if parent.is_some_and(|parent| parent.dev() != meta.dev()) {
+        return Err(format!(
+            "{} is already a mountpoint; unmount it first with `fusermount -u {}`",
+            shown, shown
+        ));
+    }
+    if std::fs::read_dir(mountpoint).is_ok_and(|mut entries| entries.next().is_some()) {
+        slog::warn!(
+            slog_scope::logger(),
+            "mountpoint {} is not empty; its contents are hidden while mounted",
+            shown
+        );
+    }
+    Ok(())
+}
 
+type BoxDrain =
+    Box<dyn slog::SendSyncUnwindSafeDrain<Ok = (), Err = slog::Never> + std::panic::RefUnwindSafe>;
+
+fn setup_log(options: &LogOptions) -> Logger {
     // 创建一个终端风格的 Drain
-    let decorator_term = slog_term::PlainDecorator::new(std::io::stdout());
-    let drain_term = slog_term::FullFormat::new(decorator_term).build().fuse();
-    let drain_term = slog_async::Async::new(drain_term).build().fuse();
+    let drain_term: BoxDrain = if options.terminal {
+        let decorator_term = slog_term::PlainDecorator::new(std::io::stderr());
+        let drain_term = slog_term::FullFormat::new(decorator_term).build().fuse();
+        Box::new(slog_async::Async::new(drain_term).build().fuse())
+    } else {
+        Box::new(slog::Discard)
+    };
 
-    // 创建一个文件风格的 Drain
-    let decorator_file = slog_term::PlainDecorator::new(file);
-    let drain_file = slog_term::FullFormat::new(decorator_file).build().fuse();
-    let drain_file = slog_async::Async::new(drain_file).build().fuse();
+    // 创建一个文件风格的 Drain，支持追加模式
+    let drain_file: BoxDrain = match &options.file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap_or_else(|err| {
+                    eprintln!("jsonfs: cannot open log file {}: {}", path.display(), err);
+                    std::process::exit(2);
+                });
+            let decorator_file = slog_term::PlainDecorator::new(file);
+            let drain_file = slog_term::FullFormat::new(decorator_file).build().fuse();
+            Box::new(slog_async::Async::new(drain_file).build().fuse())
+        }
+        None => Box::new(slog::Discard),
+    };
 
     // 合并两个 Drain
     let drain = slog::Duplicate::new(drain_term, drain_file).fuse();
-    let drain = slog::LevelFilter::new(drain, slog::Level::Debug).fuse();
+    let drain = slog::LevelFilter::new(drain, options.level).fuse();
 
     // 创建一个 Logger 实例
     //Logger::root(drain, o!("version" => "1.0"))
@@ -42,20 +518,389 @@ fn setup_log() -> Logger {
 
 fn main() {
     //env_logger::init();
-    let _scope_guard = slog_scope::set_global_logger(setup_log());
-    let json_file = std::env::args()
-        .nth(1)
-        .expect("Usage: hello_fuse <JSON_FILE>");
-    let mountpoint = std::env::args()
-        .nth(2)
-        .expect("Usage: hello_fuse <MOUNTPOINT>");
-
-    fuser::mount2(
-        pinjsonfs::JsonFS::new(json_file),
-        //jsonfs::JsonFS::new(json_file),
-        &mountpoint,
-        &[MountOption::AutoUnmount, MountOption::AllowOther],
-    )
-    .unwrap();
+    let mut args = parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        std::process::exit(2);
+    });
+    if let Err(err) = expand_paths(&mut args) {
+        eprintln!("jsonfs: {}", err);
+        std::process::exit(2);
+    }
+    let _scope_guard = slog_scope::set_global_logger(setup_log(&args.log));
+
+    if args.check {
+        match pinjsonfs::JsonFS::load(args.json_file.as_ref(), &args.fs) {
+            Ok(json) => {
+                println!("{}: ok", args.json_file);
+                println!("{}", pinjsonfs::Summary::of(&json));
+                return;
+            }
+            Err(err) => {
+                eprintln!("jsonfs: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Err(err) = check_mountpoint(args.mountpoint.as_ref()) {
+        eprintln!("jsonfs: {}", err);
+        std::process::exit(1);
+    }
+    let options = mount_options(args.allow_other);
+    let mounted = match args.backend {
+        Backend::Pin => pinjsonfs::JsonFS::with_options(&args.json_file, args.fs).map(|mut fs| {
+            let mut options = options.clone();
+            if fs.read_only() {
+                options.push(MountOption::RO);
+            }
+            // The notifier comes from the session, which owns the filesystem.
+            let notifier = Arc::new(OnceLock::<Notifier>::new());
+            let slot = Arc::clone(&notifier);
+            fs.set_poll_notify(Box::new(move |kh| {
+                if let Some(notifier) = slot.get() {
+                    let _ = notifier.poll(kh);
+                }
+            }));
+            // Invalidations are sent from their own thread: the kernel may be
+            // holding a lock that the request being handled is waiting on.
+            let (inval, invals) = mpsc::channel::<(u64, u64, String)>();
+            fs.set_inval_notify(Box::new(move |parent, ino, name: &str| {
+                let _ = inval.send((parent, ino, name.to_string()));
+            }));
+            let (ready, readiness) = mpsc::channel();
+            fs.set_ready_notify(Box::new(move || {
+                let _ = ready.send(());
+            }));
+            let fs = pinjsonfs::SharedFS::new(fs, args.flush_interval);
+            Session::new(fs, args.mountpoint.as_ref(), &options).and_then(|session| {
+                let _ = notifier.set(session.notifier());
+                let inval_notifier = session.notifier();
+                thread::spawn(move || {
+                    for (parent, ino, name) in invals {
+                        let _ = inval_notifier.inval_entry(parent, name.as_ref());
+                        let _ = inval_notifier.inval_inode(ino, 0, 0);
+                    }
+                });
+                // Taken over before mounting, so neither can end the process
+                // while the mount is being set up.
+                let signals = Signals::new([SIGINT, SIGTERM])?;
+                let session = mount_background(session, &readiness, args.mount_timeout)?;
+                slog::info!(slog_scope::logger(), "mounted {}", args.mountpoint);
+                serve(session, signals)
+            })
+        }),
+        Backend::Path => jsonfs::JsonFS::with_options(&args.json_file, args.fs).map(|fs| {
+            Session::new(fs, args.mountpoint.as_ref(), &options).and_then(|session| {
+                // This backend has no `init` to wait for, so it is served as
+                // soon as it is spawned; signals still unmount it, and save.
+                let signals = Signals::new([SIGINT, SIGTERM])?;
+                let session = session.spawn()?;
+                slog::info!(slog_scope::logger(), "mounted {}", args.mountpoint);
+                serve(session, signals)
+            })
+        }),
+    };
+    let mounted = mounted.unwrap_or_else(|err| {
+        eprintln!("jsonfs: {}", err);
+        std::process::exit(1);
+    });
+    if let Err(err) = mounted {
+        eprintln!("jsonfs: cannot mount on {}: {}", args.mountpoint, err);
+        std::process::exit(1);
+    }
     //fuser::spawn_mount2(JsonFS::new(json_file), &mountpoint, &[MountOption::AutoUnmount, MountOption::AllowOther]).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn paths_are_made_absolute() {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(
+            expand_path("~/jsonfs-no-such-file.json").unwrap(),
+            home.join("jsonfs-no-such-file.json").to_str().unwrap()
+        );
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            expand_path("./jsonfs-no-such-file.json").unwrap(),
+            cwd.join("jsonfs-no-such-file.json").to_str().unwrap()
+        );
+        assert_eq!(expand_path("/tmp/../tmp").unwrap(), "/tmp");
+        // Only a leading `~` of its own stands for the home directory.
+        assert_eq!(
+            expand_path("~other").unwrap(),
+            cwd.join("~other").to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn check_resolves_only_the_json_file() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut parsed = args(&["--check", "data.json"]).unwrap();
+        expand_paths(&mut parsed).unwrap();
+        assert_eq!(parsed.json_file, cwd.join("data.json").to_str().unwrap());
+        assert_eq!(parsed.mountpoint, "");
+    }
+
+    #[test]
+    fn parse_log_options() {
+        let parsed = args(&[
+            "--log-level",
+            "debug",
+            "data.json",
+            "--log-file",
+            "jsonfs.log",
+            "/mnt",
+            "--no-term-log",
+        ])
+        .unwrap();
+        assert_eq!(parsed.log.file, Some(PathBuf::from("jsonfs.log")));
+        assert_eq!(parsed.log.level, slog::Level::Debug);
+        assert!(!parsed.log.terminal);
+
+        assert!(args(&["--log-level", "loud", "data.json", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_file_and_mountpoint() {
+        let parsed = args(&["data.json", "/mnt"]).unwrap();
+        assert_eq!(parsed.json_file, "data.json");
+        assert_eq!(parsed.mountpoint, "/mnt");
+        assert!(args(&["data.json"]).is_err());
+
+        let parsed = args(&["--file", "data.json", "--create", "--mount", "/mnt"]).unwrap();
+        assert_eq!(
+            (parsed.json_file.as_str(), parsed.mountpoint.as_str()),
+            ("data.json", "/mnt")
+        );
+        let parsed = args(&["--mount", "/mnt", "data.json"]).unwrap();
+        assert_eq!(
+            (parsed.json_file.as_str(), parsed.mountpoint.as_str()),
+            ("data.json", "/mnt")
+        );
+        let parsed = args(&["--create", "--", "--odd.json", "/mnt"]).unwrap();
+        assert_eq!(parsed.json_file, "--odd.json");
+        assert!(args(&["data.json", "/mnt", "extra"]).is_err());
+        assert!(args(&["--file", "a.json", "b.json", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_switches() {
+        let parsed = args(&[
+            "--init",
+            "--ignore-case",
+            "--gzip",
+            "--allow-other",
+            "a",
+            "/mnt",
+        ])
+        .unwrap();
+        assert!(parsed.fs.create);
+        assert!(parsed.fs.ignore_case);
+        assert!(parsed.fs.gzip);
+        assert!(parsed.allow_other);
+
+        let parsed = args(&["data.json", "/mnt"]).unwrap();
+        assert!(!parsed.fs.create);
+        assert!(!parsed.fs.ignore_case);
+        assert!(!parsed.fs.gzip);
+        assert!(!parsed.allow_other);
+    }
+
+    #[test]
+    fn parse_check() {
+        let parsed = args(&["--check", "data.json"]).unwrap();
+        assert!(parsed.check);
+        assert_eq!(parsed.json_file, "data.json");
+        assert_eq!(parsed.backend, Backend::Pin);
+    }
+
+    #[test]
+    fn parse_backend() {
+        let parsed = args(&["--backend", "path", "data.json", "/mnt"]).unwrap();
+        assert_eq!(parsed.backend, Backend::Path);
+        assert!(args(&["--backend", "tree", "data.json", "/mnt"]).is_err());
+
+        // The path backend takes the TTLs and the size cap, and nothing else.
+        let parsed = args(&["--backend", "path", "--attr-ttl", "0", "a", "/mnt"]).unwrap();
+        assert_eq!(parsed.fs.attr_ttl, Duration::ZERO);
+        let parsed = args(&["--backend", "path", "--max-value-bytes", "9", "a", "/mnt"]).unwrap();
+        assert_eq!(parsed.fs.max_value_bytes, Some(9));
+        assert!(args(&["--backend", "path", "--gzip", "a", "/mnt"]).is_err());
+        assert!(args(&["--flush-interval", "5", "--backend", "path", "a", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_ttls() {
+        let parsed = args(&["--attr-ttl", "0", "--entry-ttl", "2.5", "data.json", "/mnt"]).unwrap();
+        assert_eq!(parsed.fs.attr_ttl, Duration::ZERO);
+        assert_eq!(parsed.fs.entry_ttl, Duration::from_millis(2500));
+        assert_eq!(
+            args(&["data.json", "/mnt"]).unwrap().fs.attr_ttl,
+            Duration::from_secs(1)
+        );
+        assert!(args(&["--attr-ttl", "-1", "data.json", "/mnt"]).is_err());
+        assert!(args(&["--entry-ttl", "soon", "data.json", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_indent() {
+        assert_eq!(args(&["data.json", "/mnt"]).unwrap().fs.indent, "  ");
+        assert_eq!(
+            args(&["--indent", "4", "a", "/mnt"]).unwrap().fs.indent,
+            "    "
+        );
+        assert_eq!(
+            args(&["--indent", "tab", "a", "/mnt"]).unwrap().fs.indent,
+            "\t"
+        );
+        assert!(args(&["--indent", "wide", "data.json", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_flush_interval() {
+        assert_eq!(
+            args(&["data.json", "/mnt"]).unwrap().flush_interval,
+            Duration::ZERO
+        );
+        assert_eq!(
+            args(&["--flush-interval", "30", "a", "/mnt"])
+                .unwrap()
+                .flush_interval,
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!(
+            args(&["--format", "toml", "a", "/mnt"]).unwrap().fs.format,
+            Some(format::Format::Toml)
+        );
+        assert!(args(&["--format", "ini", "a", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_max_depth() {
+        assert_eq!(
+            args(&["--max-depth", "8", "a", "/mnt"])
+                .unwrap()
+                .fs
+                .max_depth,
+            8
+        );
+        assert!(args(&["--max-depth", "deep", "a", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_hide() {
+        assert_eq!(
+            args(&["--hide", "_*", "--hide", ".*", "a", "/mnt"])
+                .unwrap()
+                .fs
+                .hide,
+            ["_*", ".*"]
+        );
+    }
+
+    #[test]
+    fn parse_owner() {
+        let parsed = args(&["--uid", "1000", "--gid", "100", "a", "/mnt"]).unwrap();
+        assert_eq!((parsed.fs.uid, parsed.fs.gid), (1000, 100));
+        assert!(args(&["--uid", "alice", "a", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_atime() {
+        assert_eq!(
+            args(&["--atime", "relatime", "a", "/mnt"])
+                .unwrap()
+                .fs
+                .atime,
+            pinjsonfs::Atime::Relatime
+        );
+        assert!(args(&["--atime", "sometimes", "a", "/mnt"]).is_err());
+    }
+
+    #[test]
+    fn parse_mount_timeout() {
+        assert_eq!(
+            args(&["data.json", "/mnt"]).unwrap().mount_timeout,
+            DEFAULT_MOUNT_TIMEOUT
+        );
+        assert_eq!(
+            args(&["--mount-timeout", "0", "a", "/mnt"])
+                .unwrap()
+                .mount_timeout,
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn mounting_waits_for_init() {
+        let (ready, readiness) = mpsc::channel();
+        let start = std::time::Instant::now();
+        let init = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            ready.send(()).unwrap();
+        });
+        wait_ready(&readiness, Duration::ZERO).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        init.join().unwrap();
+
+        let (_ready, readiness) = mpsc::channel::<()>();
+        assert_eq!(
+            wait_ready(&readiness, Duration::from_millis(10)),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        );
+        let (ready, readiness) = mpsc::channel::<()>();
+        drop(ready);
+        assert_eq!(
+            wait_ready(&readiness, Duration::from_secs(1)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn mountpoint_checks() {
+        let dir = std::env::temp_dir().join(format!("jsonfs-mnt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(check_mountpoint(&dir), Ok(()));
+
+        let missing = dir.join("missing");
+        let err = check_mountpoint(&missing).unwrap_err();
+        assert!(
+            err.contains("does not exist") && err.contains("mkdir -p"),
+            "{}",
+            err
+        );
+
+        let file = dir.join("file");
+        std::fs::write(&file, "").unwrap();
+        let err = check_mountpoint(&file).unwrap_err();
+        assert!(err.contains("not a directory"), "{}", err);
+
+        // `/proc` is a filesystem of its own wherever these tests run.
+        let err = check_mountpoint(Path::new("/proc")).unwrap_err();
+        assert!(err.contains("already a mountpoint"), "{}", err);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fuse_conf_parsing() {
+        assert!(fuse_conf_allows_other("user_allow_other\n"));
+        assert!(fuse_conf_allows_other(
+            "# comment\nmount_max = 1000\n  user_allow_other  # for jsonfs\n"
+        ));
+        assert!(!fuse_conf_allows_other(""));
+        assert!(!fuse_conf_allows_other("#user_allow_other\n"));
+        assert!(!fuse_conf_allows_other("user_allow_other_not\n"));
+    }
+}
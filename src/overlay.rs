@@ -0,0 +1,82 @@
+use serde_json::{Map, Value};
+
+/// Layers `patch` onto `doc` as a JSON Merge Patch (RFC 7386), which is how
+/// `--overlay` combines the overrides with the base: objects merge key by key,
+/// a `null` removes the key, and anything else, arrays included, replaces
+/// what was there. A merge patch has no way to set a `null`, so `null_value`
+/// stands for one.
+pub(crate) fn merge(doc: &mut Value, patch: Value) {
+    if patch == null_value() {
+        *doc = Value::Null;
+        return;
+    }
+    let Value::Object(patch) = patch else {
+        *doc = patch;
+        return;
+    };
+    if !doc.is_object() {
+        *doc = Value::Object(Map::new());
+    }
+    let Value::Object(map) = doc else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            map.remove(&key);
+        } else {
+            merge(map.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// What the overrides hold for a `null` in the document, as a `null` there
+/// removes the key instead.
+pub(crate) fn null_value() -> Value {
+    serde_json::json!({"$jsonfs": "null"})
+}
+
+/// The overrides that `merge` onto `base` to give `doc`: what differs, with
+/// `null` for each key `doc` dropped, and what `kept`, the overrides loaded
+/// before, set outright and `doc` still has, even if it now matches `base`.
+/// `None` if there is nothing to override.
+pub(crate) fn diff(base: &Value, doc: &Value, kept: &Value) -> Option<Value> {
+    match (base, doc) {
+        (Value::Object(base), Value::Object(doc)) => {
+            let mut patch: Map<String, Value> = base
+                .keys()
+                .filter(|key| !doc.contains_key(*key))
+                .map(|key| (key.clone(), Value::Null))
+                .collect();
+            for (key, value) in doc {
+                let kept = kept.get(key);
+                let changed = match base.get(key) {
+                    Some(old) => diff(old, value, kept.unwrap_or(&Value::Null)).or_else(|| {
+                        kept.filter(|kept| !kept.is_object() || **kept == null_value())
+                            .map(|_| patch_of(value))
+                    }),
+                    None => Some(patch_of(value)),
+                };
+                if let Some(changed) = changed {
+                    patch.insert(key.clone(), changed);
+                }
+            }
+            (!patch.is_empty()).then_some(Value::Object(patch))
+        }
+        _ if base == doc => None,
+        _ => Some(patch_of(doc)),
+    }
+}
+
+/// `value` as a patch that `merge` turns back into it: a `null` in an object
+/// (arrays are replaced whole) becomes `null_value`.
+fn patch_of(value: &Value) -> Value {
+    match value {
+        Value::Null => null_value(),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), patch_of(value)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
@@ -1,688 +1,6823 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use fuser::{
-    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEntry,
-    ReplyWrite, Request, FUSE_ROOT_ID,
+    FileAttr, FileType, Filesystem, KernelConfig, ReplyAttr, ReplyCreate, ReplyData,
+    ReplyDirectory, ReplyEntry, ReplyWrite, Request, TimeOrNow, FUSE_ROOT_ID,
 };
-use libc::ENOENT;
+use libc::{c_int, ENOENT};
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
 use serde_json::Value;
-use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
 
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
 
-use std::time::{Duration, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime};
 
 use slog::{debug, warn};
 
-use std::marker::PhantomPinned;
-use std::pin::Pin;
+use crate::duplicates::duplicate_keys;
+use crate::error::JsonFsError;
+use crate::format::Format;
+use crate::inplace;
+use crate::lenient::{quote_non_finite, relax};
+use crate::overlay;
 
+/// Synthetic file at the root exposing the whole serialized document.
+const DOCUMENT_NAME: &str = ".json";
+/// Reserved for the document file; node inodes are allocated above it.
+const DOCUMENT_INO: u64 = 2;
+
+/// Synthetic read-only file at the root with the node counts `--check`
+/// prints, of the tree as it is now. Listed only under `show_meta`.
+const STATS_NAME: &str = ".stats";
+const STATS_INO: u64 = 3;
+
+/// Synthetic read-only file in every directory holding its number of
+/// children. It shadows a key of the same name, like `DOCUMENT_NAME`.
+const LEN_NAME: &str = ".len";
+/// Set on a directory's inode to make the inode of its `.len` file.
+const LEN_INO: u64 = 1 << 63;
+
+/// `ioctl` command that re-reads the backing file, replacing the mounted
+/// tree; `_IO('J', 1)`, so a client can issue `ioctl(fd, JSONFS_RELOAD)` on
+/// any open file or directory in the mount.
+pub(crate) const JSONFS_RELOAD: u32 = 0x4a01;
+
+/// Key a scalar document is mounted under with `wrap_scalar`.
+const SCALAR_KEY: &str = "value";
+
+/// Read-only xattr on number files telling `integer` from `float`.
+const NUMTYPE_XATTR: &str = "user.jsonfs.numtype";
+
+/// Xattr holding a node's value as compact JSON, indented under `read_pretty`.
+/// Setting it replaces the node, files and directories alike, with the parsed
+/// value in one step; it isn't listed, as on the root it is the whole document.
+const JSON_XATTR: &str = "user.jsonfs.json";
+
+/// Read-only xattrs of the root and the document file for optimistic
+/// concurrency: a counter bumped by every change, and a hash of the document
+/// text. Read one before reading the tree and again before writing back.
+const VERSION_XATTR: &str = "user.jsonfs.version";
+const ETAG_XATTR: &str = "user.jsonfs.etag";
+
+/// Largest write the kernel may send in one request; bigger writes arrive
+/// split at increasing offsets.
+const MAX_WRITE: u32 = 1 << 20;
+
+/// Times one `Filesystem` handler, so its `io=out` record says how long it
+/// took as `dur_us`, which shows where a slow save went. A handler that ends
+/// without logging one gets it from the timer as it is dropped.
+struct OpTimer {
+    op: &'static str,
+    start: Instant,
+    logged: Cell<bool>,
+}
+
+impl OpTimer {
+    fn start(op: &'static str) -> OpTimer {
+        OpTimer {
+            op,
+            start: Instant::now(),
+            logged: Cell::new(false),
+        }
+    }
+
+    /// Microseconds since the handler started, for its `io=out` record.
+    fn dur_us(&self) -> u64 {
+        self.logged.set(true);
+        self.start.elapsed().as_micros() as u64
+    }
+}
+
+impl Drop for OpTimer {
+    fn drop(&mut self) {
+        if !self.logged.get() {
+            debug!(slog_scope::logger(), "Filesystem func";
+                "op" => self.op, "io" => "out", "dur_us" => self.dur_us());
+        }
+    }
+}
+
+/// Children of a directory as `opendir` found them: the keys of an object, in
+/// order, or the length of an array, whose indices are the rest.
+enum DirSnapshot {
+    Keys(Vec<String>),
+    Len(usize),
+}
+
+/// A node is identified by its name under its parent, so an inode keeps
+/// pointing at the same path while the tree around it changes.
 struct Inode {
     ino: u64,
-    value: *mut Value,
+    /// Inode of the containing object or array; the root is its own parent.
+    parent: u64,
+    /// Key (or array index) this node is known by under `parent`.
+    name: String,
+    /// Kind the kernel was last told about; when the value at this path turns
+    /// into the other kind, the kernel's cached entry is dropped.
+    kind: FileType,
+    /// Bumped each time `ino` is reused for a new node, so the kernel tells a
+    /// handle to the old node from the new one.
+    generation: u64,
+}
+
+/// Attributes changed through `setattr`. These live in memory only: they are
+/// not written into the JSON document and reset on remount.
+#[derive(Clone, Copy, Default)]
+struct Meta {
+    perm: Option<u16>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    atime: Option<SystemTime>,
+    mtime: Option<SystemTime>,
+    ctime: Option<SystemTime>,
+    crtime: Option<SystemTime>,
+    /// Type a node truncated out of it must be written back as, under
+    /// `strict_types`.
+    strict_type: Option<StrictType>,
+    /// An array of lines truncated to no lines, which `is_lines` alone would
+    /// show as an empty directory; it stays a file for the write that follows.
+    lines: bool,
+}
+
+/// JSON type a scalar keeps across edits under `strict_types`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StrictType {
+    String,
+    Number,
+    Bool,
+}
+
+/// When a read updates the atime of what it reads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Atime {
+    /// On every read.
+    Strict,
+    /// When the atime isn't later than the mtime or ctime, or is a day old.
+    Relatime,
+    /// Never.
+    Noatime,
+}
+
+/// How stale an atime may get under `Atime::Relatime`.
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default `Options::max_depth`: a directory that deep is the 127th container
+/// nested in the document, the most serde_json will parse, so a tree grown to
+/// the limit can still be loaded again.
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 126;
+
+/// Mount-time settings for a `JsonFS`.
+#[derive(Clone, PartialEq)]
+pub(crate) struct Options {
+    /// Start from an empty object when the JSON file doesn't exist yet; it is
+    /// written out on the first flush.
+    pub(crate) create: bool,
+    /// Match object keys case-insensitively wherever a name is resolved: in
+    /// `lookup`, and when creating, removing or renaming. A new key keeps the
+    /// case it was given, and listings show the keys as they are.
+    pub(crate) ignore_case: bool,
+    /// Present non-empty arrays of scalars as one file with a line per element.
+    pub(crate) array_as_lines: bool,
+    /// Let `create` pick the new node's type by the name's extension, which
+    /// isn't kept in the key: `.str`, `.num` (0), `.bool` (false) or `.null`.
+    /// Other names make an empty string, as without this. `mkdir` takes `.obj`
+    /// and `.arr` the same way.
+    pub(crate) typed_extensions: bool,
+    /// Let `create` retype an empty container to fit the first name: `0` in
+    /// `{}` makes it an array, any other name in `[]` makes it an object.
+    /// Without this an empty object or array keeps its kind.
+    pub(crate) infer_arrays: bool,
+    /// Show object keys percent-encoded (`/`, `%`, NUL and control characters)
+    /// instead of as JSON Pointer tokens (`~1`, `~0`).
+    pub(crate) escape_keys: bool,
+    /// Fail writes that would change a scalar's JSON type with `EINVAL`,
+    /// instead of storing whatever the text spells.
+    pub(crate) strict_types: bool,
+    /// Show numbers with thousands separators (`1,000,000`) and accept them so
+    /// grouped in writes; the document keeps the plain number.
+    pub(crate) humanize: bool,
+    /// Read and save the backing file gzip-compressed, as is done anyway for
+    /// a file name ending in `.gz`.
+    pub(crate) gzip: bool,
+    /// Syntax of the backing file (and of the overrides file); `None` to go
+    /// by each file's extension.
+    pub(crate) format: Option<Format>,
+    /// Copy the backing file to `<file>.bak` before the first save of the
+    /// mount overwrites it; later saves keep that copy.
+    pub(crate) backup: bool,
+    /// Mount the JSON file with this file's overrides merged onto it (see
+    /// `overlay::merge`). Changes are saved to the overrides file, as the
+    /// difference from the base, which is never written. A key the overrides
+    /// set stays there, even once the base matches it; a `null` is saved as
+    /// `overlay::null_value`.
+    pub(crate) overlay: Option<PathBuf>,
+    /// Let every user change every node, as long as the mount is reachable,
+    /// instead of checking the caller against each node's owner and mode.
+    pub(crate) no_perm_check: bool,
+    /// JSON Schema the document has to match to be saved; a flush of a
+    /// document that doesn't fails with `EINVAL`, leaving the file as it was.
+    pub(crate) schema: Option<PathBuf>,
+    /// Keep every change in memory only: writes succeed, but nothing is ever
+    /// saved, and the file isn't locked against other mounts.
+    pub(crate) no_flush: bool,
+    /// When another jsonfs has the file mounted, mount it read-only instead
+    /// of failing.
+    pub(crate) shared: bool,
+    /// Accept `//` and `/* */` comments and trailing commas in the file.
+    /// The document is still saved as strict JSON, so comments are lost on
+    /// the first save that `in_place_edit` can't make.
+    pub(crate) lenient: bool,
+    /// Accept the bare `NaN`, `Infinity` and `-Infinity` some producers write
+    /// for numbers JSON can't hold. Each is mounted as a string of its text
+    /// and saved as that string, so the file is strict JSON, but no longer a
+    /// number there, after the first save.
+    pub(crate) allow_nan: bool,
+    /// Save a JSON document by rewriting only the scalars that changed in the
+    /// text last read or saved, keeping its layout and comments, as long as
+    /// no key, element or container was added or removed since (see
+    /// `inplace::splice`). Other saves, and any under `overlay`, rewrite the
+    /// whole file.
+    pub(crate) in_place_edit: bool,
+    /// Mount a document that is a lone scalar as `{"value": <scalar>}`; it is
+    /// saved as the bare scalar again for as long as that is all the root
+    /// holds. Without this such a document can't be mounted.
+    pub(crate) wrap_scalar: bool,
+    /// JSON Pointer of the subtree mounted as the root; empty for the whole
+    /// document. Flushing still writes the whole document.
+    pub(crate) root_pointer: String,
+    /// Largest content, in bytes, a write, fallocate or truncate may leave in
+    /// one node; `None` for no limit.
+    pub(crate) max_value_bytes: Option<usize>,
+    /// Deepest node, in path components below the root, that can be looked up
+    /// or created; anything past it fails with `ENAMETOOLONG`.
+    pub(crate) max_depth: usize,
+    /// Globs (`*` and `?`) of object keys left out of `readdir`, as file
+    /// names; a hidden key can still be looked up by name. Array indices are
+    /// always listed.
+    pub(crate) hide: Vec<String>,
+    /// List the `.len` file of each directory, and `.stats` at the root, in
+    /// `readdir`; they can be looked up and read either way.
+    pub(crate) show_meta: bool,
+    /// Indentation of the saved document and the document file; two spaces,
+    /// serde_json's pretty default, unless set.
+    pub(crate) indent: String,
+    /// Get `JSON_XATTR` indented with `indent`, for people, instead of as
+    /// compact JSON.
+    pub(crate) read_pretty: bool,
+    /// Whether reads update atimes. These live in memory only, so none of the
+    /// modes dirties the document.
+    pub(crate) atime: Atime,
+    /// Owner of every node that hasn't been chowned; the real uid of the
+    /// mounting process unless set.
+    pub(crate) uid: u32,
+    /// Group of every node that hasn't been chowned; the real gid of the
+    /// mounting process unless set.
+    pub(crate) gid: u32,
+    /// How long the kernel may cache a name lookup.
+    pub(crate) entry_ttl: Duration,
+    /// How long the kernel may cache attributes.
+    pub(crate) attr_ttl: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            create: false,
+            ignore_case: false,
+            array_as_lines: false,
+            infer_arrays: false,
+            typed_extensions: false,
+            strict_types: false,
+            humanize: false,
+            escape_keys: false,
+            wrap_scalar: false,
+            lenient: false,
+            allow_nan: false,
+            in_place_edit: false,
+            gzip: false,
+            format: None,
+            backup: false,
+            overlay: None,
+            shared: false,
+            no_flush: false,
+            schema: None,
+            no_perm_check: false,
+            root_pointer: String::new(),
+            max_value_bytes: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            hide: vec![],
+            show_meta: false,
+            indent: "  ".to_string(),
+            read_pretty: false,
+            atime: Atime::Noatime,
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+            entry_ttl: Duration::from_secs(1),
+            attr_ttl: Duration::from_secs(1),
+        }
+    }
 }
 
+/// Delivers a poll wakeup for a kernel poll handle.
+pub(crate) type PollNotify = Box<dyn Fn(u64) + Send>;
+
+/// Drops the kernel's cached entry `name` under `parent` and its cached
+/// attributes and data of inode `ino`.
+pub(crate) type InvalNotify = Box<dyn Fn(u64, u64, &str) + Send>;
+
+/// Signals that the kernel has initialized the mount.
+pub(crate) type ReadyNotify = Box<dyn FnOnce() + Send>;
+
+/// The base document and the overrides of an `overlay` mount.
+type Layers = (Value, Value);
+
 pub(crate) struct JsonFS {
-    json_path: Rc<PathBuf>,
+    json_path: PathBuf,
     json: Value,
+    /// The document of `json_path` under `overlay`, which `json` merges the
+    /// overrides onto, and the overrides as last read or saved. A key they
+    /// hold is saved again even once it matches the base.
+    base: Option<Layers>,
+    /// Text of the JSON file as last read or saved, under `in_place_edit`
+    /// without an overlay.
+    original: Option<String>,
     ino2inode: HashMap<u64, Inode>,
-    _marker: PhantomPinned,
+    /// Inode of each registered `(parent, name)`; ordered so that the children
+    /// of a node are a contiguous range.
+    name2ino: BTreeMap<(u64, String), u64>,
+    next_ino: u64,
+    /// Inodes (and generations) of paths a reload or document write removed,
+    /// by `(parent, name)`.
+    retired: HashMap<(u64, String), (u64, u64)>,
+    /// A rebuild is registering the new tree: a retired path it restores is
+    /// the same node again, not a new one.
+    rebuilding: bool,
+    meta: HashMap<u64, Meta>,
+    /// Length of the compact JSON of each node, the size a directory reports.
+    /// Filled in as `getattr` asks; `get_node_mut` drops the entries of the
+    /// node and of everything above and below it.
+    sizes: RefCell<HashMap<u64, u64>>,
+    /// Reported for every time a node hasn't recorded itself.
+    mount_time: SystemTime,
+    /// Pending writes to the document file, until they parse or get flushed.
+    document: Option<Vec<u8>>,
+    /// Bytes of a UTF-8 character cut in two by the kernel splitting a write,
+    /// held until the rest of it arrives.
+    utf8_tails: HashMap<u64, Vec<u8>>,
+    /// The tree has changes `myflush` hasn't written yet.
+    dirty: bool,
+    /// The document is a scalar mounted wrapped, under `wrap_scalar`.
+    wrapped: bool,
+    /// The backing file is saved gzip-compressed.
+    gzip: bool,
+    /// The original backing file has been copied aside, under `backup`.
+    backed_up: bool,
+    /// Advisory lock on the saved file (see `lock_file`), held for as long as
+    /// the mount lives; `None` if it couldn't be taken.
+    _lock: Option<FileLock>,
+    /// Changes made since mounting, reloads included; see `VERSION_XATTR`.
+    version: u64,
+    /// Content of the `.stats` file, with the `version` it was counted at.
+    stats: RefCell<Option<(u64, String)>>,
+    /// Compiled `Options::schema`.
+    schema: Option<jsonschema::Validator>,
+    /// Another mount holds the lock: nothing is saved, and the session is
+    /// mounted read-only.
+    read_only: bool,
+    /// Children of each open directory handle, as of `opendir`.
+    dir_handles: HashMap<u64, DirSnapshot>,
+    /// Last file handle handed out; 0 is never used.
+    next_fh: u64,
+    /// Kernel poll handles waiting for a change of each inode.
+    polls: HashMap<u64, Vec<u64>>,
+    /// Wakes the poll handle it's given; unset until a session exists.
+    poll_notify: Option<PollNotify>,
+    /// Unset until a session exists, or in tests.
+    inval_notify: Option<InvalNotify>,
+    /// Called once `init` succeeds.
+    ready_notify: Option<ReadyNotify>,
+    options: Options,
 }
 
 impl JsonFS {
-    pub(crate) fn new(json_path: impl AsRef<Path>) -> Pin<Box<JsonFS>> {
-        let data = fs::read_to_string(json_path.as_ref()).unwrap();
-        let json = serde_json::from_str(&data).unwrap();
+    #[cfg(test)]
+    pub(crate) fn new(json_path: impl AsRef<Path>) -> Result<JsonFS, JsonFsError> {
+        Self::with_options(json_path, Options::default())
+    }
 
-        let fs = JsonFS {
-            json_path: Rc::new(json_path.as_ref().to_path_buf()),
-            json: json,
-            ino2inode: HashMap::new(),
-            _marker: PhantomPinned,
-        };
+    /// Reads and parses the document exactly as mounting it would.
+    pub(crate) fn load(json_path: &Path, options: &Options) -> Result<Value, JsonFsError> {
+        Self::load_text(json_path, options).map(|(json, _)| json)
+    }
 
-        let mut fs = Box::pin(fs);
+    /// `load`, with the text of a JSON file as it was read; `None` for
+    /// another format or a file `create` starts.
+    fn load_text(
+        json_path: &Path,
+        options: &Options,
+    ) -> Result<(Value, Option<String>), JsonFsError> {
+        match read_document(json_path, gzipped(json_path, options)) {
+            Ok(data) => {
+                let data = data?;
+                let format = format_of(json_path, options);
+                if format != Format::Json {
+                    return format
+                        .parse(&data)
+                        .map(|json| (json, None))
+                        .map_err(|err| JsonFsError::decode(json_path, format, err));
+                }
+                let mut relaxed = if options.lenient {
+                    Cow::Owned(relax(&data))
+                } else {
+                    Cow::Borrowed(&data)
+                };
+                if options.allow_nan {
+                    let (quoted, count) = quote_non_finite(&relaxed);
+                    if count > 0 {
+                        warn!(slog_scope::logger(), "NaN and Infinity read as strings";
+                            "file" => %json_path.display(), "count" => count);
+                        relaxed = Cow::Owned(quoted);
+                    }
+                }
+                // Errors quote the file as written, which `relax` keeps the
+                // positions of.
+                let json = serde_json::from_str(&relaxed)
+                    .map_err(|err| JsonFsError::parse(json_path, err, &data))?;
+                for pointer in duplicate_keys(&relaxed) {
+                    warn!(slog_scope::logger(), "duplicate key, keeping the last value";
+                        "file" => %json_path.display(), "key" => pointer);
+                }
+                drop(relaxed);
+                Ok((json, Some(data)))
+            }
+            Err(err) if options.create && err.kind() == std::io::ErrorKind::NotFound => {
+                Ok((Value::Object(serde_json::Map::new()), None))
+            }
+            Err(err) => Err(JsonFsError::io(json_path, err)),
+        }
+    }
 
-        let root = unsafe { &mut fs.as_mut().get_unchecked_mut().json as *mut Value };
+    /// Reads `json_path` with the overrides of `options.overlay`, if any,
+    /// merged onto it. The base and the overrides are returned too in that
+    /// case. An overrides
+    /// file that doesn't exist yet has none. Without an overlay, the text
+    /// `in_place_edit` saves into comes last.
+    fn load_layers(
+        json_path: &Path,
+        options: &Options,
+    ) -> Result<(Value, Option<Layers>, Option<String>), JsonFsError> {
+        let (json, text) = Self::load_text(json_path, options)?;
+        let Some(overlay) = &options.overlay else {
+            return Ok((json, None, text.filter(|_| options.in_place_edit)));
+        };
+        let overrides_options = Options {
+            create: true,
+            ..options.clone()
+        };
+        let overrides = Self::load(overlay, &overrides_options)?;
+        let mut merged = json.clone();
+        overlay::merge(&mut merged, overrides.clone());
+        Ok((merged, Some((json, overrides)), None))
+    }
 
-        debug!(slog_scope::logger(), "Filesystem init"; "root" => root as u64);
+    pub(crate) fn with_options(
+        json_path: impl AsRef<Path>,
+        options: Options,
+    ) -> Result<JsonFS, JsonFsError> {
+        let json_path = json_path.as_ref();
+        let (json, base, original) = Self::load_layers(json_path, &options)?;
+        let saved_path = options.overlay.as_deref().unwrap_or(json_path);
+        // A document started by `create` is written on the first flush.
+        let dirty = !saved_path.exists();
+        let gzip = gzipped(saved_path, &options);
+        let (lock, read_only) = match (!options.no_flush).then(|| lock_file(saved_path)) {
+            None => (None, false),
+            Some(Ok(Some(lock))) => (Some(lock), false),
+            Some(Ok(None)) if options.shared => {
+                warn!(slog_scope::logger(), "already mounted elsewhere, mounting read-only";
+                    "file" => %saved_path.display());
+                (None, true)
+            }
+            Some(Ok(None)) => return Err(JsonFsError::locked(saved_path)),
+            Some(Err(err)) => {
+                warn!(slog_scope::logger(), "cannot lock, mounting unguarded";
+                    "file" => %saved_path.display(), "error" => %err);
+                (None, false)
+            }
+        };
+        let root = resolve_pointer(&json, &options.root_pointer)
+            .ok_or_else(|| JsonFsError::pointer(json_path, &options.root_pointer))?;
+        let wrapped = wraps_scalar(root, &options)
+            .ok_or_else(|| JsonFsError::scalar_root(json_path, &options.root_pointer))?;
+        let json = if wrapped { wrap_scalar(json) } else { json };
+        let schema = options.schema.as_deref().map(load_schema).transpose()?;
 
-        fs.as_mut().traverse(root);
+        let mut fs = JsonFS {
+            json_path: json_path.to_path_buf(),
+            json,
+            base,
+            original,
+            ino2inode: HashMap::new(),
+            name2ino: BTreeMap::new(),
+            next_ino: STATS_INO + 1,
+            retired: HashMap::new(),
+            rebuilding: false,
+            meta: HashMap::new(),
+            sizes: RefCell::default(),
+            mount_time: SystemTime::now(),
+            document: None,
+            utf8_tails: HashMap::new(),
+            dirty,
+            wrapped,
+            gzip,
+            backed_up: false,
+            _lock: lock,
+            version: 0,
+            stats: RefCell::default(),
+            schema,
+            read_only,
+            dir_handles: HashMap::new(),
+            next_fh: 0,
+            polls: HashMap::new(),
+            poll_notify: None,
+            inval_notify: None,
+            ready_notify: None,
+            options,
+        };
 
-        fs.as_mut().ino2inode_mut().insert(
+        fs.ino2inode.insert(
             FUSE_ROOT_ID,
             Inode {
                 ino: FUSE_ROOT_ID,
-                value: root,
+                parent: FUSE_ROOT_ID,
+                name: String::new(),
+                kind: FileType::Directory,
+                generation: 0,
+            },
+        );
+        fs.register_children(FUSE_ROOT_ID);
+
+        // Only saving is refused, so a file that doesn't match can be fixed.
+        let _ = fs.check_schema();
+        debug!(slog_scope::logger(), "Filesystem init"; "inodes" => fs.ino2inode.len());
+
+        Ok(fs)
+    }
+
+    /// Returns the inode of `name` under `parent`, allocating one if needed,
+    /// and invalidates the kernel's view of a known one whose kind changed.
+    fn register(&mut self, parent: u64, name: &str, kind: FileType) -> u64 {
+        if let Some(&ino) = self.name2ino.get(&(parent, name.to_string())) {
+            if let Some(inode) = self.ino2inode.get_mut(&ino) {
+                if inode.kind != kind {
+                    inode.kind = kind;
+                    self.invalidate(parent, ino, name);
+                }
+            }
+            return ino;
+        }
+        let (ino, generation) = match self.retired.remove(&(parent, name.to_string())) {
+            Some((ino, generation)) if self.rebuilding => (ino, generation),
+            Some((ino, generation)) => (ino, generation + 1),
+            None => {
+                self.next_ino += 1;
+                (self.next_ino - 1, 0)
+            }
+        };
+        self.ino2inode.insert(
+            ino,
+            Inode {
+                ino,
+                parent,
+                name: name.to_string(),
+                kind,
+                generation,
             },
         );
+        self.name2ino.insert((parent, name.to_string()), ino);
+        ino
+    }
 
-        fs
+    /// Registers every descendant of the node `ino`, keeping the inodes of
+    /// paths that are already known. Walks with a work list rather than
+    /// recursion, as do the other registry walks, so depth costs heap, not stack.
+    fn register_children(&mut self, ino: u64) {
+        let mut pending = vec![ino];
+        while let Some(ino) = pending.pop() {
+            let children: Vec<_> = match self.get_node(ino) {
+                Ok(value) => child_names(value)
+                    .into_iter()
+                    .filter_map(|name| {
+                        let child = self.name2ino.get(&(ino, name.clone())).copied();
+                        let child_value = lookup_children(value, &name)?;
+                        let kind = match child {
+                            Some(child) => self.node_kind(child, child_value),
+                            None => self.kind(child_value),
+                        };
+                        Some((name, kind))
+                    })
+                    .collect(),
+                Err(_) => continue,
+            };
+            for (name, kind) in children {
+                pending.push(self.register(ino, &name, kind));
+            }
+        }
     }
 
-    fn traverse(mut self: Pin<&mut JsonFS>, root: *mut Value) {
-        let ino = root as u64;
+    /// Drops the registered descendants of `ino` whose path no longer resolves,
+    /// along with their metadata.
+    fn unregister_missing(&mut self, ino: u64) {
+        let mut pending = vec![ino];
+        while let Some(ino) = pending.pop() {
+            let (present, missing): (Vec<_>, Vec<_>) = {
+                let value = self.get_node(ino).ok();
+                self.registered_children(ino)
+                    .into_iter()
+                    .partition(|(name, _)| value.and_then(|v| lookup_children(v, name)).is_some())
+            };
+            for (_, child) in missing {
+                self.unregister(child);
+            }
+            pending.extend(present.into_iter().map(|(_, child)| child));
+        }
+    }
 
-        self.as_mut()
-            .ino2inode_mut()
-            .insert(ino, Inode { ino, value: root });
+    /// Drops `ino` and all its registered descendants.
+    fn unregister(&mut self, ino: u64) {
+        let mut pending = vec![ino];
+        while let Some(ino) = pending.pop() {
+            pending.extend(
+                self.registered_children(ino)
+                    .into_iter()
+                    .map(|(_, child)| child),
+            );
+            if let Some(inode) = self.ino2inode.remove(&ino) {
+                self.name2ino.remove(&(inode.parent, inode.name));
+            }
+            self.meta.remove(&ino);
+        }
+    }
 
-        let root_value: &mut Value;
-        unsafe {
-            root_value = &mut *root;
+    /// Renumbers the registered elements of the array `parent` from index
+    /// `from` on: one up after an insertion at `from`, one down after a
+    /// removal just before it. Each inode so stays with its element, along
+    /// with its metadata and the handles open on it; the kernel is told to
+    /// drop the entries under the old names.
+    fn shift_elements(&mut self, parent: u64, from: usize, up: bool) {
+        let shifted: Vec<_> = self
+            .registered_children(parent)
+            .into_iter()
+            .filter_map(|(name, ino)| Some((parse_index(&name).filter(|&i| i >= from)?, name, ino)))
+            .collect();
+        for (_, name, _) in &shifted {
+            self.name2ino.remove(&(parent, name.clone()));
         }
-        match root_value {
-            Value::Object(map) => {
-                for (_, value) in map.iter_mut() {
-                    self.as_mut().traverse(value as *mut Value);
-                }
+        for (index, name, ino) in shifted {
+            let index = if up { index + 1 } else { index - 1 };
+            if let Some(inode) = self.ino2inode.get_mut(&ino) {
+                inode.name = index.to_string();
             }
-            Value::Array(vec) => {
-                for value in vec.iter_mut() {
-                    self.as_mut().traverse(value as *mut Value);
-                }
+            self.name2ino.insert((parent, index.to_string()), ino);
+            self.invalidate(parent, ino, &name);
+        }
+    }
+
+    /// Brings the registry below `ino` in line with the tree after a mutation.
+    fn refresh(&mut self, ino: u64) {
+        self.unregister_missing(ino);
+        self.register_children(ino);
+    }
+
+    /// Refreshes the whole registry after the tree was swapped out. The inode
+    /// of a path that vanished is kept aside and handed back if a later
+    /// rebuild restores the path, so handles the kernel still holds work again.
+    /// A node created there by other means gets the inode with a new
+    /// generation instead.
+    fn rebuild(&mut self) {
+        let known: Vec<_> = self
+            .ino2inode
+            .values()
+            .map(|inode| {
+                (
+                    inode.ino,
+                    inode.parent,
+                    inode.name.clone(),
+                    inode.generation,
+                )
+            })
+            .collect();
+        self.rebuilding = true;
+        self.refresh(FUSE_ROOT_ID);
+        self.rebuilding = false;
+        for (ino, parent, name, generation) in known {
+            if !self.ino2inode.contains_key(&ino) {
+                self.retired.insert((parent, name), (ino, generation));
             }
-            _ => {}
         }
     }
 
-    fn ino2inode_mut(self: Pin<&mut Self>) -> &mut HashMap<u64, Inode> {
-        unsafe { &mut self.get_unchecked_mut().ino2inode }
+    /// Generation to report with the entry for `ino`.
+    fn generation(&self, ino: u64) -> u64 {
+        self.ino2inode.get(&ino).map_or(0, |inode| inode.generation)
+    }
+
+    /// Cache timeout and generation that an entry reply for `attr` carries.
+    fn entry_reply(&self, attr: &FileAttr) -> (Duration, u64) {
+        (self.options.entry_ttl, self.generation(attr.ino))
+    }
+
+    fn registered_children(&self, ino: u64) -> Vec<(String, u64)> {
+        self.name2ino
+            .range((ino, String::new())..(ino + 1, String::new()))
+            .map(|((_, name), &child)| (name.clone(), child))
+            .collect()
+    }
+
+    fn ino_of(&self, parent: u64, name: &str) -> Result<u64, c_int> {
+        self.name2ino
+            .get(&(parent, name.to_string()))
+            .copied()
+            .ok_or(ENOENT)
+    }
+
+    /// Names from the root down to `ino`.
+    fn path_of(&self, mut ino: u64) -> Result<Vec<&str>, c_int> {
+        let mut path = vec![];
+        while ino != FUSE_ROOT_ID {
+            let inode = self.ino2inode.get(&ino).ok_or_else(|| self.missing(ino))?;
+            path.push(inode.name.as_str());
+            ino = inode.parent;
+        }
+        path.reverse();
+        Ok(path)
+    }
+
+    /// Fails with `ENAMETOOLONG` if a child of `parent`, or the deepest of
+    /// its descendants `height` levels below it, would lie deeper than
+    /// `max_depth`.
+    fn check_depth(&self, parent: u64, height: usize) -> Result<(), c_int> {
+        if self.path_of(parent)?.len() + height >= self.options.max_depth {
+            return Err(libc::ENAMETOOLONG);
+        }
+        Ok(())
+    }
+
+    /// Error for an inode that isn't registered: `ESTALE` if it was handed
+    /// out and its path has since gone, `ENOENT` if it never existed.
+    fn missing(&self, ino: u64) -> c_int {
+        if ino > STATS_INO && ino < self.next_ino {
+            libc::ESTALE
+        } else {
+            ENOENT
+        }
+    }
+
+    fn get_node(&self, ino: u64) -> Result<&Value, c_int> {
+        let pointer: String = self
+            .path_of(ino)?
+            .iter()
+            .map(|name| format!("/{}", name))
+            .collect();
+        let pointer = format!("{}{}", self.options.root_pointer, pointer);
+        resolve_pointer(&self.json, &pointer).ok_or(ENOENT)
+    }
+
+    fn get_node_mut(&mut self, ino: u64) -> Result<&mut Value, c_int> {
+        self.forget_sizes(ino);
+        let path: Vec<String> = self.path_of(ino)?.into_iter().map(String::from).collect();
+        self.value_at_mut(&path)
+    }
+
+    /// The value at `path`, names from the mounted root down.
+    fn value_at_mut(&mut self, path: &[String]) -> Result<&mut Value, c_int> {
+        let root: Vec<String> = self
+            .options
+            .root_pointer
+            .split('/')
+            .skip(1)
+            .map(String::from)
+            .collect();
+        let mut value = &mut self.json;
+        for name in root.iter().chain(path) {
+            value = lookup_children_mut(value, name).ok_or(ENOENT)?;
+        }
+        Ok(value)
+    }
+
+    /// Drops the cached sizes that a change to `ino` can make stale: its own,
+    /// the document's, its ancestors', and those of its descendants, whose inodes may now
+    /// stand for other values (as when the node is written as a whole).
+    fn forget_sizes(&mut self, ino: u64) {
+        if self.sizes.get_mut().is_empty() {
+            return;
+        }
+        let mut stale = vec![];
+        let mut pending = vec![ino];
+        while let Some(ino) = pending.pop() {
+            stale.push(ino);
+            pending.extend(
+                self.registered_children(ino)
+                    .into_iter()
+                    .map(|(_, child)| child),
+            );
+        }
+        stale.push(DOCUMENT_INO);
+        let mut up = ino;
+        while let Some(inode) = self.ino2inode.get(&up).filter(|_| up != FUSE_ROOT_ID) {
+            up = inode.parent;
+            stale.push(up);
+        }
+        let sizes = self.sizes.get_mut();
+        for ino in stale {
+            sizes.remove(&ino);
+        }
+    }
+
+    /// Length of the compact JSON of the node `ino`, holding `value`. Built
+    /// from the cached sizes of its children, computing (and caching) the
+    /// missing ones bottom-up with a work list.
+    fn serialized_size(&self, ino: u64, value: &Value) -> u64 {
+        if let Some(&size) = self.sizes.borrow().get(&ino) {
+            return size;
+        }
+        let mut pending = vec![(ino, false)];
+        while let Some((ino, expanded)) = pending.pop() {
+            if self.sizes.borrow().contains_key(&ino) {
+                continue;
+            }
+            let children = self.registered_children(ino);
+            if !expanded && !children.is_empty() {
+                pending.push((ino, true));
+                pending.extend(children.into_iter().map(|(_, child)| (child, false)));
+                continue;
+            }
+            let Ok(value) = self.get_node(ino) else {
+                continue;
+            };
+            let mut sizes = self.sizes.borrow_mut();
+            let child_size = |name: String, child: &Value| {
+                self.name2ino
+                    .get(&(ino, name))
+                    .and_then(|child| sizes.get(child))
+                    .copied()
+                    .unwrap_or_else(|| child.to_string().len() as u64)
+            };
+            // Brackets, plus a comma between each two entries.
+            let container =
+                |count: usize, content: u64| 2 + content + count.saturating_sub(1) as u64;
+            let size = match value {
+                Value::Object(map) => container(
+                    map.len(),
+                    map.iter()
+                        .map(|(key, child)| {
+                            Value::String(key.clone()).to_string().len() as u64
+                                + 1
+                                + child_size(name_of(key), child)
+                        })
+                        .sum(),
+                ),
+                Value::Array(vec) => container(
+                    vec.len(),
+                    vec.iter()
+                        .enumerate()
+                        .map(|(index, child)| child_size(index.to_string(), child))
+                        .sum(),
+                ),
+                _ => value.to_string().len() as u64,
+            };
+            sizes.insert(ino, size);
+        }
+        self.sizes
+            .borrow()
+            .get(&ino)
+            .copied()
+            .unwrap_or_else(|| value.to_string().len() as u64)
     }
 
     fn create_attr(&self, ino: u64, value: &Value) -> FileAttr {
-        let kind = match value {
-            Value::Object(_) | Value::Array(_) => FileType::Directory,
-            _ => FileType::RegularFile,
-        };
+        let kind = self.node_kind(ino, value);
 
         let size = match value {
             Value::String(s) => s.len() as u64,
+            Value::Number(n) => self.number_text(n).len() as u64,
+            Value::Array(vec) if kind == FileType::RegularFile => lines_text(vec).len() as u64,
+            _ if kind == FileType::Directory => self.serialized_size(ino, value),
             _ => value.to_string().len() as u64,
         };
 
+        // A directory is linked from its parent and its own ".", plus the ".." of each subdirectory.
+        let is_subdir = |v: &Value| self.kind(v) == FileType::Directory;
+        let nlink = match value {
+            Value::Object(map) if kind == FileType::Directory => {
+                2 + map.values().filter(|v| is_subdir(v)).count() as u32
+            }
+            Value::Array(vec) if kind == FileType::Directory => {
+                2 + vec.iter().filter(|v| is_subdir(v)).count() as u32
+            }
+            _ => 1,
+        };
+
+        let meta = self.meta.get(&ino).copied().unwrap_or_default();
+
         FileAttr {
             ino,
             size,
-            blocks: 1,
-            atime: UNIX_EPOCH,
-            mtime: UNIX_EPOCH,
-            ctime: UNIX_EPOCH,
-            crtime: UNIX_EPOCH,
+            // 512-byte units, as `du` counts them; directories use their
+            // serialized size.
+            blocks: size.div_ceil(512),
+            atime: meta.atime.unwrap_or(self.mount_time),
+            mtime: meta.mtime.unwrap_or(self.mount_time),
+            ctime: meta.ctime.unwrap_or(self.mount_time),
+            crtime: meta.crtime.unwrap_or(self.mount_time),
             kind,
-            perm: 0o644,
-            nlink: 1,
-            uid: 0,
-            gid: 0,
+            perm: meta.perm.unwrap_or(match kind {
+                FileType::Directory => 0o755,
+                _ => 0o644,
+            }),
+            nlink,
+            uid: meta.uid.unwrap_or(self.options.uid),
+            gid: meta.gid.unwrap_or(self.options.gid),
             rdev: 0,
             flags: 0,
             blksize: 512,
         }
     }
 
-    fn myflush(self: Pin<&mut Self>) {
-        eprintln!("Saving JSON data flushing");
-        let json_str = serde_json::to_string_pretty(&self.json).unwrap();
-        fs::write(self.json_path.as_ref(), json_str).unwrap();
-        eprintln!("JSON data saved successfully.");
-    }
-}
-
-fn lookup_children<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
-    match value {
-        Value::Object(map) => map.get(name),
-        Value::Array(vec) => {
-            debug!(slog_scope::logger(), "lookup_children"; "name" => name);
-            vec.get(name.parse::<usize>().ok()?)
+    /// How `value` is presented, taking `array_as_lines` into account.
+    fn kind(&self, value: &Value) -> FileType {
+        if self.options.array_as_lines && is_lines(value) {
+            FileType::RegularFile
+        } else {
+            get_value_type(value)
         }
-        _ => None,
-    }
-}
-
-fn get_value_type(value: &Value) -> FileType {
-    match value {
-        Value::Object(_) | Value::Array(_) => FileType::Directory,
-        _ => FileType::RegularFile,
     }
-}
-
-impl Filesystem for Pin<Box<JsonFS>> {
-    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "lookup", "io"=> "in", "parent" => parent, "name" => name.to_str().unwrap());
-
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&parent) {
-            let parent_value = unsafe {
-                let v2 = *value;
-                &mut *v2
-            };
 
-            if let Some(value) = lookup_children(parent_value, name.to_str().unwrap()) {
-                let child_ino = value as *const Value as u64;
-                let attr = self.create_attr(child_ino, value);
-
-                debug!(slog_scope::logger(), "Filesystem func"; 
-                    "op" => "lookup", "io"=> "out", "attr" => format!("{:?}", attr));
-                reply.entry(&Duration::new(1, 0), &attr, 0);
-            } else {
-                reply.error(ENOENT);
-            }
+    /// `kind` of the node `ino`, which also knows an array of lines emptied by
+    /// truncation.
+    fn node_kind(&self, ino: u64, value: &Value) -> FileType {
+        let emptied = self.meta.get(&ino).is_some_and(|meta| meta.lines);
+        if emptied && value.as_array().is_some_and(Vec::is_empty) {
+            FileType::RegularFile
         } else {
-            reply.error(ENOENT);
+            self.kind(value)
         }
     }
 
-    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "getattr", "io"=> "in", "ino" => ino);
-        let json = unsafe { &mut self.as_mut().get_unchecked_mut().json as *mut Value };
-
-        if let Some(Inode { value, ino }) = self.ino2inode.get(&ino) {
-            let inov = *ino;
-            let value = unsafe {
-                let v1 = *value;
-                debug!(slog_scope::logger(), "getattr"; "value" => v1 as u64, "ino" => inov, "json" => json as u64);
+    /// What `ino` is shown as, in `readdir` as in the `kind` of its
+    /// `getattr`: taken from the node as it is now, never from what it was
+    /// registered as, so an empty container always lists as a directory.
+    fn file_type_of(&self, ino: u64) -> Result<FileType, c_int> {
+        if ino == DOCUMENT_INO || is_synthetic(ino) {
+            return Ok(FileType::RegularFile);
+        }
+        Ok(self.node_kind(ino, self.get_node(ino)?))
+    }
 
-                &mut *v1
-            };
-            debug!(slog_scope::logger(), "getattr"; "value type" => format!("{:?}", get_value_type(value)));
-            let attr = self.create_attr(inov, value);
-            debug!(slog_scope::logger(), "Filesystem func"; 
-                "op" => "getattr", "io"=> "out", "attr" => format!("{:?}", attr));
-            reply.attr(&Duration::new(1, 0), &attr);
-        } else {
-            reply.error(ENOENT);
+    /// Finds `name` under `parent`: `ENOTDIR` if `parent` is shown as a file
+    /// (a scalar, or an array of lines), `ENOENT` if it is a directory without
+    /// such a child.
+    fn lookup_entry(&self, parent: u64, name: &str) -> Result<FileAttr, c_int> {
+        if parent == FUSE_ROOT_ID && name == DOCUMENT_NAME {
+            return Ok(self.document_attr());
+        }
+        if parent == FUSE_ROOT_ID && name == STATS_NAME {
+            return Ok(self.stats_attr());
         }
+        if name == LEN_NAME {
+            return self.len_attr(parent);
+        }
+        let parent_value = self.get_node(parent)?;
+        if self.node_kind(parent, parent_value) != FileType::Directory {
+            return Err(libc::ENOTDIR);
+        }
+        self.check_depth(parent, 0)?;
+        let key = key_of(name);
+        let name = match parent_value {
+            Value::Object(map) if self.options.ignore_case => {
+                name_of(lookup_key_ignore_case(map, &key).ok_or(ENOENT)?)
+            }
+            _ => name_of(&key),
+        };
+        self.get_attr(self.ino_of(parent, &name)?)
     }
-    fn read(
-        &mut self,
-        _req: &Request,
+
+    /// Feeds the entries of directory `ino` after `offset` to `add`, each with
+    /// the offset of the entry after it, and stops as soon as `add` reports the
+    /// reply is full. Resuming at an array index is constant time, so listing
+    /// a huge array over many `readdir` calls stays linear. With a `snapshot`
+    /// of the same kind of directory, only the children in it are listed;
+    /// those since removed are left out, and the rest keep their offsets.
+    fn dir_entries(
+        &self,
         ino: u64,
-        _fh: u64,
         offset: i64,
-        size: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyData,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "read", "io"=> "in", 
-            "ino" => ino, "fh" => _fh, "offset" => offset, "size" => size, 
-            "flags" => _flags, "lock_owner" => _lock_owner);
-
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&ino) {
-            let value = unsafe {
-                let v1 = *value;
-                &mut *v1
-            };
-            match value {
-                Value::Null => {
-                    debug!(slog_scope::logger(), "Filesystem func";
-                        "op" => "read", "io"=> "out", "content" => "null");
-                    reply.data(&[]);
-                    return;
-                }
-                Value::Bool(b) => {
-                    debug!(slog_scope::logger(), "Filesystem func";
-                        "op" => "read", "io"=> "out", "content" => format!("{}", b));
-                    reply.data(&[if *b { 1 } else { 0 }]);
-                    return;
-                }
-                Value::Number(n) => {
-                    debug!(slog_scope::logger(), "Filesystem func";
-                        "op" => "read", "io"=> "out", "content" => format!("{}", n));
-                    reply.data(&n.to_string().as_bytes());
-                    return;
+        snapshot: Option<&DirSnapshot>,
+        mut add: impl FnMut(u64, i64, FileType, &str) -> bool,
+    ) -> Result<(), c_int> {
+        let parent = self.ino2inode.get(&ino).ok_or(ENOENT)?.parent;
+        let value = self.get_node(ino)?;
+        let mut fixed = vec![
+            (ino, FileType::Directory, "."),
+            (parent, FileType::Directory, ".."),
+        ];
+        if ino == FUSE_ROOT_ID {
+            fixed.push((DOCUMENT_INO, FileType::RegularFile, DOCUMENT_NAME));
+        }
+        if self.options.show_meta && self.dir_len(ino).is_ok() {
+            fixed.push((ino | LEN_INO, FileType::RegularFile, LEN_NAME));
+        }
+        if self.options.show_meta && ino == FUSE_ROOT_ID {
+            fixed.push((STATS_INO, FileType::RegularFile, STATS_NAME));
+        }
+        let fixed_entries = fixed.len();
+        for (index, (entry_ino, kind, entry_name)) in
+            fixed.into_iter().enumerate().skip(offset as usize)
+        {
+            if add(entry_ino, index as i64 + 1, kind, entry_name) {
+                return Ok(());
+            }
+        }
+        let offset = (offset as usize).saturating_sub(fixed_entries);
+        match value {
+            // serde_json's Map is a BTreeMap, so keys already come sorted.
+            Value::Object(map) => {
+                let children: Box<dyn Iterator<Item = (usize, (&String, &Value))>> = match snapshot
+                {
+                    Some(DirSnapshot::Keys(keys)) => Box::new(
+                        keys.iter()
+                            .enumerate()
+                            .skip(offset)
+                            .filter_map(|(i, key)| Some((i, map.get_key_value(key)?))),
+                    ),
+                    _ => Box::new(map.iter().enumerate().skip(offset)),
+                };
+                for (child_index, (child_key, child_value)) in children {
+                    let Ok(child_ino) = self.ino_of(ino, &name_of(child_key)) else {
+                        continue;
+                    };
+                    let Some(child_name) = self.file_name(child_key) else {
+                        continue;
+                    };
+                    if self
+                        .options
+                        .hide
+                        .iter()
+                        .any(|glob| glob_match(glob, &child_name))
+                    {
+                        continue;
+                    }
+                    let child_index = child_index + fixed_entries + 1;
+                    debug!(slog_scope::logger(), "Filesystem func map";
+                        "op" => "readdir", "io"=> "out", "ino" => child_ino, "offset" => offset, "child_key" => child_key,
+                        "child_value" => format!("{:?}", get_value_type(child_value)), "child_index" => child_index);
+                    if add(
+                        child_ino,
+                        child_index as i64,
+                        self.file_type_of(child_ino)?,
+                        &child_name,
+                    ) {
+                        break;
+                    }
                 }
-                Value::String(s) => {
-                    let start = offset as usize;
-                    let end = (offset as usize + size as usize).min(s.len());
-                    debug!(slog_scope::logger(), "Filesystem func";
-                    "op" => "read", "io"=> "out", "content" => s.as_str());
-                    reply.data(&s.as_bytes()[start..end]);
-                    return;
+            }
+            Value::Array(vec) => {
+                let len = match snapshot {
+                    Some(&DirSnapshot::Len(len)) => len.min(vec.len()),
+                    _ => vec.len(),
+                };
+                for (child_index, child_value) in vec[..len].iter().enumerate().skip(offset) {
+                    let Ok(child_ino) = self.ino_of(ino, &child_index.to_string()) else {
+                        continue;
+                    };
+                    debug!(slog_scope::logger(), "Filesystem func vec";
+                        "op" => "readdir", "io"=> "out", "ino" => child_ino, "offset" => offset, "child_index" => child_index,
+                        "child_value" => format!("{:?}", get_value_type(child_value)));
+                    if add(
+                        child_ino,
+                        (child_index + fixed_entries + 1) as i64,
+                        self.file_type_of(child_ino)?,
+                        &child_index.to_string(),
+                    ) {
+                        break;
+                    }
                 }
-                _ => (),
             }
+            _ => {}
+        }
+        Ok(())
+    }
 
-            if let Value::String(s) = value {
-                let content_bytes = s.as_bytes();
-                let start = offset as usize;
-                let end = (offset as usize + size as usize).min(content_bytes.len());
-
-                debug!(slog_scope::logger(), "Filesystem func";
-                    "op" => "read", "io"=> "out", "content" => &s[start..end]);
-
-                reply.data(&content_bytes[start..end]);
-                return;
-            }
+    /// Opens the directory `ino`, noting which children it has for `read_dir`
+    /// to list until the handle is released, so entries added meanwhile don't
+    /// shift the pages. Only the key order is copied, or for an array just its
+    /// length; each page is still made as it is read. Returns the new handle.
+    fn open_dir(&mut self, ino: u64) -> Result<u64, c_int> {
+        if self.file_type_of(ino)? != FileType::Directory {
+            return Err(libc::ENOTDIR);
         }
-        reply.error(libc::ENOENT);
+        let snapshot = match self.get_node(ino)? {
+            Value::Object(map) => DirSnapshot::Keys(map.keys().cloned().collect()),
+            value => DirSnapshot::Len(value.as_array().map_or(0, Vec::len)),
+        };
+        self.next_fh += 1;
+        self.dir_handles.insert(self.next_fh, snapshot);
+        Ok(self.next_fh)
     }
 
-    fn readdir(
-        &mut self,
-        _req: &Request,
+    /// Like `dir_entries`, but of the children `fh` had when it was opened,
+    /// if it was.
+    fn read_dir(
+        &self,
         ino: u64,
-        _fh: u64,
+        fh: u64,
         offset: i64,
-        mut reply: ReplyDirectory,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func";
-            "op" => "readdir", "io"=> "in", "ino" => ino, "fh" => _fh, "offset" => offset);
+        add: impl FnMut(u64, i64, FileType, &str) -> bool,
+    ) -> Result<(), c_int> {
+        self.dir_entries(ino, offset, self.dir_handles.get(&fh), add)
+    }
 
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&ino) {
-            let value = unsafe {
-                let v1 = *value;
-                &mut *v1
-            };
-            match value {
-                Value::Object(map) => {
-                    let mut values: Vec<_> = map.iter().collect();
-                    values.sort_by(|a, b| a.0.cmp(&b.0));
+    /// File name listed for the object key `key`. Without `escape_keys` a key
+    /// holding NUL has no file name and is left out, with a warning.
+    fn file_name(&self, key: &str) -> Option<String> {
+        if self.options.escape_keys {
+            return Some(percent_encode(key));
+        }
+        if key.contains('\0') {
+            warn!(slog_scope::logger(), "key can't be a file name, use --escape-keys";
+                "key" => key);
+            return None;
+        }
+        Some(name_of(key))
+    }
 
-                    for (child_index, (child_key, child_value)) in
-                        values.into_iter().enumerate().skip(offset as usize)
-                    {
-                        let child_ino = child_value as *const Value as u64;
-                        let child_index = child_index + 1;
-                        debug!(slog_scope::logger(), "Filesystem func map";
-                            "op" => "readdir", "io"=> "out", "ino" => child_ino, "fh" => _fh, "offset" => offset, "child_key" => child_key,
-                            "child_value" => format!("{:?}", get_value_type(child_value)), "child_index" => child_index);
-                        let _ = reply.add(
-                            child_ino,
-                            child_index.try_into().unwrap(),
-                            get_value_type(child_value),
-                            child_key,
-                        );
-                    }
-                }
-                Value::Array(vec) => {
-                    for (child_index, child_value) in vec.iter().enumerate().skip(offset as usize) {
-                        let child_ino = child_value as *const Value as u64;
-                        debug!(slog_scope::logger(), "Filesystem func vec";
-                            "op" => "readdir", "io"=> "out", "ino" => child_ino, "fh" => _fh, "offset" => offset, "child_index" => child_index,
-                            "child_value" => format!("{:?}", get_value_type(child_value)));
-                        let _ = reply.add(
-                            child_ino,
-                            (child_index + 1).try_into().unwrap(),
-                            get_value_type(child_value),
-                            child_index.to_string(),
-                        );
-                    }
-                }
-                _ => {}
+    /// Name the tree functions take (see `name_of`) for a file name from the
+    /// kernel, in directory `parent`. Under `ignore_case` it is the key of
+    /// `parent` that matches, if one does, so every operation finds the node
+    /// that `lookup` did.
+    fn entry_name(&self, parent: u64, file_name: &str) -> String {
+        let name = if self.options.escape_keys {
+            name_of(&percent_decode(file_name))
+        } else {
+            file_name.to_string()
+        };
+        match self.get_node(parent) {
+            Ok(Value::Object(map)) if self.options.ignore_case => {
+                lookup_key_ignore_case(map, &key_of(&name)).map_or(name, name_of)
             }
+            _ => name,
         }
-        reply.ok();
     }
 
-    fn mkdir(
-        &mut self,
-        _req: &Request<'_>,
-        parent: u64,
-        name: &OsStr,
-        _mode: u32,
-        _umask: u32,
-        reply: ReplyEntry,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func";
-            "op" => "mkdir", "io"=> "in", "parent" => parent, "name" => name.to_str().unwrap());
+    /// Fails with `EACCES` unless the caller `uid`/`gid` may access `ino` for
+    /// `mask` (of `R_OK`, `W_OK` and `X_OK`) by its mode; root always may. Only
+    /// the caller's primary group is known, so other groups it is in don't
+    /// count. Writing is `EROFS` for anyone on a read-only mount.
+    fn check_access(&self, ino: u64, uid: u32, gid: u32, mask: i32) -> Result<(), c_int> {
+        let attr = self.get_attr(ino)?;
+        if mask & libc::W_OK != 0 {
+            self.check_writable()?;
+        }
+        if self.options.no_perm_check || uid == 0 {
+            return Ok(());
+        }
+        let shift = if uid == attr.uid {
+            6
+        } else if gid == attr.gid {
+            3
+        } else {
+            0
+        };
+        let granted = (attr.perm >> shift) as i32 & 0o7;
+        if granted & mask == mask {
+            Ok(())
+        } else {
+            Err(libc::EACCES)
+        }
+    }
 
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&parent) {
-            let parent = unsafe { value.as_mut().unwrap() };
-            match parent {
-                Value::Object(map) => {
-                    map.iter()
-                        .map(|(_, v)| v as *const Value as u64)
-                        .for_each(|ino| {
-                            self.as_mut().ino2inode_mut().remove(&ino);
-                        });
-
-                    let value = map
-                        .entry(name.to_str().unwrap().to_string())
-                        .or_insert(serde_json::json!({}));
-                    let attr = self.create_attr(value as *mut Value as u64, value);
-                    map.iter_mut().for_each(|(_, v)| {
-                        let ino = v as *const Value as u64;
-                        self.as_mut().ino2inode_mut().insert(
-                            ino,
-                            Inode {
-                                ino,
-                                value: v as *mut Value,
-                            },
-                        );
-                    });
-                    reply.entry(&Duration::new(1, 0), &attr, 0);
-                }
-                Value::Array(vec) => {
-                    vec.iter()
-                        .map(|v| v as *const Value as u64)
-                        .for_each(|ino| {
-                            self.as_mut().ino2inode_mut().remove(&ino);
-                        });
-                    vec.push(serde_json::json!({}));
-                    let value = vec.last_mut().unwrap();
-                    let attr = self.create_attr(value as *mut Value as u64, value);
-                    vec.iter_mut().for_each(|v| {
-                        let ino = v as *const Value as u64;
-                        self.as_mut().ino2inode_mut().insert(
-                            ino,
-                            Inode {
-                                ino,
-                                value: v as *mut Value,
-                            },
-                        );
-                    });
-                    reply.entry(&Duration::new(1, 0), &attr, 0);
-                }
-                _ => {}
-            }
+    /// Fails with `EPERM` unless the caller `uid` owns `ino` or is root, as
+    /// changing its mode or owner needs.
+    fn check_owner(&self, ino: u64, uid: u32) -> Result<(), c_int> {
+        let attr = self.get_attr(ino)?;
+        self.check_writable()?;
+        if self.options.no_perm_check || uid == 0 || uid == attr.uid {
+            Ok(())
+        } else {
+            Err(libc::EPERM)
+        }
+    }
+
+    /// Fails with `EROFS` on a `read_only` mount, which must not change the
+    /// document it can't save.
+    fn check_writable(&self) -> Result<(), c_int> {
+        if self.read_only {
+            Err(libc::EROFS)
         } else {
-            warn!(slog_scope::logger(), "Filesystem func not found inode of parent"; "op" => "mkdir", "io"=> "in", "parent" => parent, "name" => name.to_str().unwrap());
-            reply.error(libc::ENOENT);
+            Ok(())
+        }
+    }
+
+    fn get_attr(&self, ino: u64) -> Result<FileAttr, c_int> {
+        if ino == DOCUMENT_INO {
+            return Ok(self.document_attr());
+        }
+        if let Some(dir) = len_dir(ino) {
+            return self.len_attr(dir);
+        }
+        if ino == STATS_INO {
+            return Ok(self.stats_attr());
+        }
+        Ok(self.create_attr(ino, self.get_node(ino)?))
+    }
+
+    /// The content of the `.stats` file, counted again only after a change.
+    fn stats_text(&self) -> String {
+        let mut stats = self.stats.borrow_mut();
+        match &*stats {
+            Some((version, text)) if *version == self.version => text.clone(),
+            _ => {
+                let root = self.get_node(FUSE_ROOT_ID).unwrap_or(&Value::Null);
+                let text = format!("{}\n", Summary::of(root));
+                *stats = Some((self.version, text.clone()));
+                text
+            }
+        }
+    }
+
+    fn stats_attr(&self) -> FileAttr {
+        let mut attr = self.create_attr(STATS_INO, &Value::String(self.stats_text()));
+        attr.perm = 0o444;
+        attr
+    }
+
+    /// Number of children of the directory `dir`, the content of its `.len`
+    /// file; `ENOTDIR` for a node shown as a file.
+    fn dir_len(&self, dir: u64) -> Result<usize, c_int> {
+        let value = self.get_node(dir)?;
+        match value {
+            _ if self.node_kind(dir, value) != FileType::Directory => Err(libc::ENOTDIR),
+            Value::Object(map) => Ok(map.len()),
+            Value::Array(vec) => Ok(vec.len()),
+            _ => Err(libc::ENOTDIR),
+        }
+    }
+
+    fn len_attr(&self, dir: u64) -> Result<FileAttr, c_int> {
+        let count = Value::String(self.dir_len(dir)?.to_string());
+        let mut attr = self.create_attr(dir | LEN_INO, &count);
+        attr.perm = 0o444;
+        Ok(attr)
+    }
+
+    /// The mounted subtree as shown by the document file.
+    fn render(&self) -> String {
+        self.pretty(self.saved(self.get_node(FUSE_ROOT_ID).unwrap_or(&Value::Null)))
+    }
+
+    /// What is saved for the whole document `json`: the bare scalar while a
+    /// wrapped document still holds nothing but it.
+    fn saved<'a>(&self, json: &'a Value) -> &'a Value {
+        match json {
+            Value::Object(map) if self.wrapped && map.len() == 1 => {
+                map.get(SCALAR_KEY).unwrap_or(json)
+            }
+            _ => json,
+        }
+    }
+
+    fn pretty(&self, value: &Value) -> String {
+        let formatter = PrettyFormatter::with_indent(self.options.indent.as_bytes());
+        let mut out = vec![];
+        value
+            .serialize(&mut serde_json::Serializer::with_formatter(
+                &mut out, formatter,
+            ))
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Content of the document file: pending writes if any, else the live tree.
+    fn document_bytes(&self) -> Vec<u8> {
+        match &self.document {
+            Some(pending) => pending.clone(),
+            None => self.render().into_bytes(),
+        }
+    }
+
+    /// Attributes of the document file. The size of the rendered tree is
+    /// cached along with the node sizes, under `DOCUMENT_INO`, and dropped
+    /// with them by any change.
+    fn document_attr(&self) -> FileAttr {
+        let cached = self.sizes.borrow().get(&DOCUMENT_INO).copied();
+        let size = match &self.document {
+            Some(pending) => pending.len() as u64,
+            None => cached.unwrap_or_else(|| {
+                let size = self.render().len() as u64;
+                self.sizes.borrow_mut().insert(DOCUMENT_INO, size);
+                size
+            }),
+        };
+        let mut attr = self.create_attr(DOCUMENT_INO, &Value::String(String::new()));
+        attr.size = size;
+        attr.blocks = size.div_ceil(512);
+        attr
+    }
+
+    /// Splices `data` into the document file. The kernel splits large writes,
+    /// and a prefix of the new document may well parse, so the bytes are only
+    /// buffered; `commit_document` parses them once, on `flush`.
+    fn write_document(&mut self, offset: i64, data: &[u8]) -> Result<u32, c_int> {
+        let current = self.document_bytes();
+        let offset = write_offset(offset, self.document.as_ref().unwrap_or(&current).len())?;
+        let pending = self.document.get_or_insert(current);
+        if pending.len() < offset + data.len() {
+            pending.resize(offset + data.len(), b' ');
+        }
+        pending[offset..offset + data.len()].copy_from_slice(data);
+        self.touch(DOCUMENT_INO);
+        Ok(data.len() as u32)
+    }
+
+    /// Cuts or pads (with spaces) the pending document to `size` bytes; it
+    /// grows no further than `max_value_bytes`.
+    fn truncate_document(&mut self, size: u64) -> Result<(), c_int> {
+        let size = byte_len(size)?;
+        let mut pending = self.document_bytes();
+        if pending.len() < size {
+            self.check_size(size)?;
+        }
+        pending.resize(size, b' ');
+        self.document = Some(pending);
+        Ok(())
+    }
+
+    /// Ends a round of document writes, swapping in the tree they spell, or
+    /// failing with `EINVAL` (and leaving the tree as it was) when the written
+    /// bytes aren't valid JSON that can be mounted.
+    fn commit_document(&mut self) -> Result<(), c_int> {
+        let Some(pending) = self.document.take() else {
+            return Ok(());
+        };
+        let json = serde_json::from_slice::<Value>(&pending).map_err(|_| libc::EINVAL)?;
+        wraps_scalar(&json, &self.options).ok_or(libc::EINVAL)?;
+        if *self.saved(self.get_node(FUSE_ROOT_ID)?) != json {
+            self.replace_document(json);
+        }
+        Ok(())
+    }
+
+    /// Swaps in a whole new mounted tree; nodes whose path survives keep
+    /// their inode. A scalar is only taken under `wrap_scalar`.
+    fn replace_document(&mut self, json: Value) {
+        let Some(wrapped) = wraps_scalar(&json, &self.options) else {
             return;
+        };
+        let json = if wrapped { wrap_scalar(json) } else { json };
+        self.wrapped = wrapped;
+        if let Ok(root) = self.get_node_mut(FUSE_ROOT_ID) {
+            *root = json;
         }
+        self.rebuild();
+        self.touch(FUSE_ROOT_ID);
     }
-    fn write(
-        &mut self,
-        _req: &Request<'_>,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        data: &[u8],
-        _write_flags: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        reply: ReplyWrite,
-    ) {
-        let content = String::from_utf8_lossy(data).into_owned();
-        let content_size = content.as_bytes().len();
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "write", "io"=> "in", 
-            "ino" => ino, "fh" => _fh, "offset" => offset, "content" => format!("{:?}", content), 
-            "flags" => _flags, "lock_owner" => _lock_owner, "data_size" => data.len(), "content_size" => content_size);
 
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&ino) {
-            let value = unsafe {
-                let v1 = *value;
-                &mut *v1
-            };
-            if let Ok(content_num) = content.parse::<u64>().map(|n| n as usize) {
-                *value = serde_json::json!(content_num);
-            } else {
-                match value {
-                    Value::String(s) => {
-                        s.replace_range(offset as usize.., &content);
-                    }
-                    _ => {
-                        *value = serde_json::json!(content);
-                    }
+    /// Re-reads `json_path`, with the overrides under `overlay`, and swaps it
+    /// in, dropping any half-written document or held UTF-8 tails. A file that
+    /// no longer parses leaves the mounted tree untouched and fails with
+    /// `EINVAL`, or `ENOENT` when it no longer holds the mounted subtree, or
+    /// `ENOTDIR` when that is a scalar that can't be mounted.
+    fn reload(&mut self) -> Result<(), c_int> {
+        let (json, base, original) =
+            Self::load_layers(&self.json_path, &self.options).map_err(|err| {
+                warn!(slog_scope::logger(), "reload failed"; "error" => %err);
+                match err {
+                    JsonFsError::Io { source, .. } => source.raw_os_error().unwrap_or(libc::EIO),
+                    _ => libc::EINVAL,
                 }
+            })?;
+        let root = resolve_pointer(&json, &self.options.root_pointer).ok_or(ENOENT)?;
+        self.wrapped = wraps_scalar(root, &self.options).ok_or(libc::ENOTDIR)?;
+        self.base = base;
+        self.original = original;
+        self.sizes.get_mut().clear();
+        self.json = if self.wrapped {
+            wrap_scalar(json)
+        } else {
+            json
+        };
+        self.document = None;
+        self.utf8_tails.clear();
+        self.rebuild();
+        self.touch(FUSE_ROOT_ID);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Creates the directory `name` under `parent` with permissions `mode`,
+    /// which the caller has already masked with the umask. In an array the
+    /// name is the index to insert at, as for `create_file`. The directory is
+    /// an empty object, or under `typed_extensions` an empty array for a name
+    /// ending in `.arr`, which is left out of its key as is `.obj`. A key that
+    /// already exists is `EEXIST`, whatever it holds.
+    fn make_dir(&mut self, parent: u64, name: &str, mode: u32) -> Result<FileAttr, c_int> {
+        let (name, initial) = match typed_name(name) {
+            Some((stem, initial)) if self.options.typed_extensions && is_dir(&initial) => {
+                (stem, initial)
             }
-            reply.written(data.len() as u32);
-            return;
+            _ => (name, serde_json::json!({})),
+        };
+        if is_reserved(parent, name) {
+            return Err(libc::EPERM);
+        }
+        self.check_depth(parent, 0)?;
+        let parent_value = self.get_node_mut(parent)?;
+        if parent_value.is_object() && lookup_children(parent_value, name).is_some() {
+            return Err(libc::EEXIST);
+        }
+
+        let (child_name, inserted) = match parent_value {
+            Value::Object(map) => {
+                let key = key_of(name);
+                let child_name = name_of(&key);
+                map.insert(key, initial);
+                (child_name, None)
+            }
+            Value::Array(vec) => match parse_index(name) {
+                Some(index) if index <= vec.len() => {
+                    vec.insert(index, initial);
+                    (index.to_string(), Some(index))
+                }
+                _ => return Err(libc::EINVAL),
+            },
+            _ => return Err(libc::ENOTDIR),
+        };
+        if let Some(index) = inserted {
+            self.shift_elements(parent, index, true);
         }
-        reply.error(libc::ENOENT);
+        self.refresh(parent);
+
+        let child = self.ino_of(parent, &child_name)?;
+        self.stamp_created(parent, child);
+        self.meta.entry(child).or_default().perm = Some((mode & 0o7777) as u16);
+        self.get_attr(child)
     }
 
-    fn create(
+    /// Creates the empty file `name` under `parent` with permissions `mode`,
+    /// which the caller has already masked with the umask. An existing key
+    /// is returned as it is.
+    ///
+    /// The parent keeps its kind: in an object `name` is a key, digits or not,
+    /// and only an array takes an index, the one to insert at. That is up to
+    /// its length, the elements from there on moving up one, inodes and all;
+    /// so an empty array takes only `0`, and any other name fails with
+    /// `EINVAL`. `mkdir` makes an object, or an array under `typed_extensions`;
+    /// an array is otherwise made by writing it, e.g. through `JSON_XATTR`.
+    /// Only `infer_arrays` retypes an empty parent to fit the name, which is
+    /// then the only way the kind of a container changes.
+    ///
+    /// Under `typed_extensions` the type extension of `name` picks the new
+    /// node's value instead, and is left out of its key. `.obj` and `.arr`
+    /// name directories, which `create` can't reply with: they fail with
+    /// `EISDIR` before anything changes, and are for `mkdir`.
+    fn create_file(&mut self, parent: u64, name: &str, mode: u32) -> Result<FileAttr, c_int> {
+        let (name, initial) = self.split_typed(name);
+        if is_dir(&initial) {
+            return Err(libc::EISDIR);
+        }
+        if is_reserved(parent, name) {
+            return Err(libc::EPERM);
+        }
+        self.check_depth(parent, 0)?;
+        let infer = self.options.infer_arrays;
+        let parent_value = self.get_node_mut(parent)?;
+        if parent_value.is_object() && lookup_children(parent_value, name).is_some() {
+            // Opening an existing node leaves its content alone.
+            return self.get_attr(self.ino_of(parent, name)?);
+        }
+
+        let mut inserted = None;
+        let child_name = match parent_value {
+            Value::Object(map) if infer && map.is_empty() && name.parse::<u64>() == Ok(0) => {
+                *parent_value = Value::Array(vec![initial]);
+                Ok("0".to_string())
+            }
+            Value::Object(map) => {
+                map.insert(key_of(name), initial);
+                Ok(name_of(&key_of(name)))
+            }
+            Value::Array(vec) if infer && vec.is_empty() && name.parse::<u64>() != Ok(0) => {
+                *parent_value = serde_json::json!({ key_of(name): initial });
+                Ok(name_of(&key_of(name)))
+            }
+            Value::Array(vec) => match parse_index(name) {
+                Some(index) if index <= vec.len() => {
+                    vec.insert(index, initial);
+                    inserted = Some(index);
+                    Ok(index.to_string())
+                }
+                Some(_) => Err(libc::EINVAL),
+                None => {
+                    warn!(slog_scope::logger(), "Filesystem func"; 
+                        "op" => "create", "io"=> "out", "error" => "invalid index", "name" => name);
+                    Err(libc::EINVAL)
+                }
+            },
+            _ => Err(libc::ENOSYS),
+        };
+        if let Some(index) = inserted {
+            self.shift_elements(parent, index, true);
+        }
+        self.refresh(parent);
+
+        let child = self.ino_of(parent, &child_name?)?;
+        self.stamp_created(parent, child);
+        self.meta.entry(child).or_default().perm = Some((mode & 0o7777) as u16);
+        self.get_attr(child)
+    }
+
+    /// `create` with its open `flags`: with `O_EXCL` an existing `name`
+    /// fails with `EEXIST`, otherwise it is opened as it is.
+    fn create_entry(
         &mut self,
-        _req: &Request<'_>,
         parent: u64,
-        name: &OsStr,
+        name: &str,
         mode: u32,
-        umask: u32,
         flags: i32,
-        reply: ReplyCreate,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "create", "io"=> "in", 
-            "parent" => parent, "name" => name.to_str(), "mode" => mode, "umask" => umask, "flags" => flags);
+    ) -> Result<FileAttr, c_int> {
+        let key = self.split_typed(name).0;
+        if flags & libc::O_EXCL != 0 && self.lookup_entry(parent, key).is_ok() {
+            return Err(libc::EEXIST);
+        }
+        self.create_file(parent, name, mode)
+    }
 
-        if let Some(Inode { value, .. }) = self.ino2inode.get(&parent) {
-            let parent_value = unsafe {
-                let v1 = *value;
-                &mut *v1
-            };
-            match parent_value {
-                Value::Object(map) => {
-                    if map.len() == 0 && name.to_str().unwrap().parse::<u64>() == Ok(0) {
-                        *parent_value = serde_json::json!([""]);
-                        let child = parent_value.as_array_mut().unwrap().last_mut().unwrap();
-                        let child_ino = child as *mut Value as u64;
-                        self.as_mut().ino2inode_mut().insert(
-                            child_ino,
-                            Inode {
-                                value: child as *mut Value,
-                                ino: child_ino,
-                            },
-                        );
-                        let attr = self.create_attr(child as *mut Value as u64, child);
-                        reply.created(&Duration::new(1, 0), &attr, 0, 0, 0);
-
-                        return;
-                    }
+    /// The name `create` stores `name` under, and the node's initial value:
+    /// an empty string, unless `typed_extensions` and `typed_name` say else.
+    fn split_typed<'a>(&self, name: &'a str) -> (&'a str, Value) {
+        match typed_name(name) {
+            Some(typed) if self.options.typed_extensions => typed,
+            _ => (name, Value::String(String::new())),
+        }
+    }
 
-                    map.iter()
-                        .map(|(_, v)| v as *const Value as u64)
-                        .for_each(|ino| {
-                            self.as_mut().ino2inode_mut().remove(&ino);
-                        });
-
-                    let attr = {
-                        let new_child_value = map
-                            .entry(name.to_str().unwrap())
-                            .or_insert(serde_json::json!(""));
-
-                        let value_ptr = new_child_value as *mut Value;
-                        let child_ino = value_ptr as u64;
-                        self.create_attr(child_ino, &new_child_value)
-                    };
+    /// Links `ino` as `newname` under `newparent`. A JSON tree can't share a
+    /// node between two parents, so the new entry is a deep copy of the source
+    /// value: later edits to either side don't affect the other and both keep
+    /// `nlink` 1.
+    fn link_entry(&mut self, ino: u64, newparent: u64, newname: &str) -> Result<FileAttr, c_int> {
+        if is_reserved(newparent, newname) {
+            return Err(libc::EPERM);
+        }
+        self.check_depth(newparent, 0)?;
+        let source = self.get_node(ino)?.clone();
+        let parent_value = self.get_node_mut(newparent)?;
 
-                    map.iter_mut().for_each(|(_, v)| {
-                        let ino = v as *const Value as u64;
-                        self.as_mut().ino2inode_mut().insert(
-                            ino,
-                            Inode {
-                                value: v as *mut Value,
-                                ino,
-                            },
-                        );
-                    });
-
-                    debug!(slog_scope::logger(), "Filesystem func"; 
-                        "op" => "create", "io"=> "out", "attr" => format!("{:?}", attr), "name" => name.to_str());
-                    reply.created(&Duration::new(1, 0), &attr, 0, 0, 0);
-
-                    return;
+        let child_name = match parent_value {
+            Value::Object(map) if map.contains_key(&key_of(newname)) => Err(libc::EEXIST),
+            Value::Object(map) => {
+                map.insert(key_of(newname), source);
+                Ok(name_of(&key_of(newname)))
+            }
+            Value::Array(vec) => match newname.parse::<usize>() {
+                Ok(index) if index == vec.len() => {
+                    vec.push(source);
+                    Ok(index.to_string())
                 }
-                Value::Array(vec) => {
-                    if vec.len() == 0 && name.to_str().unwrap().parse::<u64>() != Ok(0) {
-                        *parent_value = serde_json::json!({name.to_str().unwrap():""});
-                        let child = parent_value
-                            .as_object_mut()
-                            .unwrap()
-                            .get_mut(name.to_str().unwrap())
-                            .unwrap();
-                        let child_ino = child as *mut Value as u64;
-                        self.as_mut().ino2inode_mut().insert(
-                            child_ino,
-                            Inode {
-                                value: child as *mut Value,
-                                ino: child_ino,
-                            },
-                        );
-                        let attr = self.create_attr(child_ino, child);
-                        reply.created(&Duration::new(1, 0), &attr, 0, 0, 0);
-                        return;
-                    }
+                Ok(index) if index < vec.len() => Err(libc::EEXIST),
+                _ => Err(libc::EINVAL),
+            },
+            _ => Err(libc::ENOTDIR),
+        }?;
+        self.refresh(newparent);
 
-                    let index = name.to_str().unwrap().parse::<usize>();
-                    if index.is_err() {
-                        warn!(slog_scope::logger(), "Filesystem func"; 
-                            "op" => "create", "io"=> "out", "error" => "invalid index", "name" => name.to_str());
+        let child = self.ino_of(newparent, &child_name)?;
+        self.stamp_created(newparent, child);
+        self.get_attr(child)
+    }
 
-                        reply.error(libc::EINVAL);
-                        return;
-                    }
-                    let index = index.unwrap();
-                    if index == vec.len() {
-                        vec.iter()
-                            .map(|v| v as *const Value as u64)
-                            .for_each(|ino| {
-                                self.as_mut().ino2inode_mut().remove(&ino);
-                            });
-
-                        vec.push(serde_json::json!(""));
-                        let child_ptr = vec.last_mut().unwrap() as *mut Value;
-
-                        let child_ino = child_ptr as u64;
-
-                        let attr = self.create_attr(child_ino, vec.last().unwrap());
-
-                        vec.iter_mut().for_each(|v| {
-                            let v1 = v as *mut Value;
-                            let ino = v1 as u64;
-                            let value = v1;
-
-                            self.as_mut()
-                                .ino2inode_mut()
-                                .insert(ino, Inode { ino, value });
-                        });
-                        debug!(slog_scope::logger(), "Filesystem func"; 
-                            "op" => "create", "io"=> "out", "attr" => format!("{:?}", attr), "name" => name.to_str());
-                        reply.created(&Duration::new(1, 0), &attr, 0, 0, 0);
-                        return;
-                    }
+    /// Removes `name` from `parent`: a key, or an element, which moves the
+    /// ones after it down, inodes and all. A missing key or index fails with
+    /// `ENOENT`, and leaves the document clean.
+    fn remove_entry(&mut self, parent: u64, name: &str) -> Result<(), c_int> {
+        if is_reserved(parent, name) {
+            return Err(libc::EPERM);
+        }
+        let parent_value = self.get_node_mut(parent)?;
+
+        let removed = match parent_value {
+            Value::Object(map) => map.remove(&key_of(name)).map(|_| None).ok_or(ENOENT),
+            Value::Array(vec) => match parse_index(name) {
+                Some(index) if index < vec.len() => {
+                    vec.remove(index);
+                    Ok(Some(index))
                 }
-                _ => {}
+                Some(_) => Err(ENOENT),
+                None => Err(libc::EINVAL),
+            },
+            _ => Err(libc::ENOSYS),
+        }?;
+        if let Some(index) = removed {
+            if let Ok(ino) = self.ino_of(parent, name) {
+                self.unregister(ino);
             }
+            self.shift_elements(parent, index + 1, false);
         }
-        reply.error(libc::ENOSYS);
+        self.refresh(parent);
+        self.touch(parent);
+        Ok(())
     }
-    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "unlink", "io"=> "in", 
-            "parent" => parent, "name" => name.to_str());
 
-        if let Some(Inode { value, ino }) = self.ino2inode.get(&parent) {
-            assert!(ino == &parent);
-            let parent_value = unsafe {
-                let v1 = *value;
-                &mut *v1
-            };
-            match parent_value {
-                Value::Object(map) => {
-                    map.iter()
-                        .map(|(_, v)| v as *const Value as u64)
-                        .for_each(|ino| {
-                            self.as_mut().ino2inode_mut().remove(&ino);
-                        });
-
-                    map.remove(name.to_str().unwrap());
-
-                    map.iter_mut().for_each(|(_, v)| {
-                        let ino = v as *const Value as u64;
-
-                        self.as_mut().ino2inode_mut().insert(
-                            ino,
-                            Inode {
-                                value: v as *mut Value,
-                                ino,
-                            },
-                        );
-                    });
-
-                    self.as_mut().myflush();
-
-                    reply.ok();
-                    return;
-
-                    /*                     if let Some(child_value) = map.get(name.to_str().unwrap()) {
-                        let child_value_ptr = child_value as *const Value;
-                        let child_ino = child_value_ptr as u64;
-                        debug!(slog_scope::logger(), "Filesystem func";
-                            "op" => "unlink", "io"=> "out",
-                            "child_ino" => child_ino, "name" => name.to_str(), "parent" => parent);
-                        self.as_mut().ino2inode_mut().remove(&child_ino);
-                        map.remove(name.to_str().unwrap());
-
-                        self.as_mut().myflush();
-
-                        reply.ok();
-                        return;
-                    } */
-                }
-                Value::Array(vec) => {
-                    if let Some(child_value) =
-                        vec.get(name.to_str().unwrap().parse::<usize>().unwrap())
-                    {
-                        let child_value_ptr = child_value as *const Value;
-                        let child_ino = child_value_ptr as u64;
+    /// Moves `name` from `parent` to `newname` under `newparent`, keeping its
+    /// inode. An object destination is keyed by `newname`, replacing what was
+    /// there as `rename` does; an array destination takes `newname` as the
+    /// index to insert at, up to its length, moving the elements from there
+    /// on up. Leaving an array moves the elements after it down.
+    fn rename_entry(
+        &mut self,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+        newname: &str,
+        flags: u32,
+    ) -> Result<(), c_int> {
+        if is_reserved(parent, name) || is_reserved(newparent, newname) {
+            return Err(libc::EPERM);
+        }
+        if flags & libc::RENAME_EXCHANGE != 0 {
+            return Err(libc::EINVAL);
+        }
+        let moved = self.ino_of(parent, name)?;
+        if parent == newparent && name == newname {
+            return Ok(());
+        }
+        // A node can't be moved into itself.
+        let mut ancestor = newparent;
+        while ancestor != FUSE_ROOT_ID {
+            if ancestor == moved {
+                return Err(libc::EINVAL);
+            }
+            ancestor = self.ino2inode.get(&ancestor).ok_or(ENOENT)?.parent;
+        }
+        // The whole subtree moves down with it.
+        self.check_depth(newparent, Summary::of(self.get_node(moved)?).max_depth)?;
 
-                        self.as_mut().ino2inode_mut().remove(&child_ino);
-                        vec.remove(name.to_str().unwrap().parse::<usize>().unwrap());
-                        self.as_mut().myflush();
+        let source_is_dir = is_dir(self.get_node(moved)?);
+        let index = parse_index(name);
 
-                        reply.ok();
-                        return;
+        let child_name = match self.get_node(newparent)? {
+            Value::Object(map) => {
+                let key = key_of(newname);
+                if let Some(existing) = map.get(&key) {
+                    if flags & libc::RENAME_NOREPLACE != 0 {
+                        return Err(libc::EEXIST);
+                    }
+                    match (is_dir(existing), source_is_dir) {
+                        (true, false) => return Err(libc::EISDIR),
+                        (false, true) => return Err(libc::ENOTDIR),
+                        (true, true) if !child_names(existing).is_empty() => {
+                            return Err(libc::ENOTEMPTY)
+                        }
+                        _ => {}
                     }
                 }
-                _ => {}
+                name_of(&key)
+            }
+            Value::Array(vec) => {
+                let len = vec.len() - usize::from(newparent == parent);
+                match parse_index(newname) {
+                    Some(index) if index <= len => index.to_string(),
+                    _ => return Err(libc::EINVAL),
+                }
+            }
+            _ => return Err(libc::ENOTDIR),
+        };
+
+        self.forget_sizes(moved);
+        let (value, removed) = match self.get_node_mut(parent)? {
+            Value::Object(map) => (map.remove(&key_of(name)).ok_or(ENOENT)?, None),
+            Value::Array(vec) => {
+                let index = index.filter(|&i| i < vec.len()).ok_or(ENOENT)?;
+                (vec.remove(index), Some(index))
+            }
+            _ => return Err(libc::ENOTDIR),
+        };
+        // The moved inode leaves the registry until it has its new place; its
+        // descendants follow it, as they are registered under it. Every other
+        // inode stays with its node, so `newparent` is still the destination
+        // even if it was an element after the one that left.
+        if let Some(inode) = self.ino2inode.get(&moved) {
+            self.name2ino.remove(&(inode.parent, inode.name.clone()));
+        }
+        if let Some(index) = removed {
+            self.shift_elements(parent, index + 1, false);
+        }
+        let inserted = match self.get_node_mut(newparent)? {
+            Value::Object(map) => {
+                map.insert(key_of(&child_name), value);
+                None
+            }
+            Value::Array(vec) => {
+                let index = child_name.parse().unwrap_or(vec.len());
+                vec.insert(index, value);
+                Some(index)
+            }
+            // Checked to be a container above; this is an error, not a
+            // panic, should that ever change.
+            _ => return Err(libc::ENOTDIR),
+        };
+        match inserted {
+            Some(index) => self.shift_elements(newparent, index, true),
+            None => {
+                if let Ok(replaced) = self.ino_of(newparent, &child_name) {
+                    self.unregister(replaced);
+                }
             }
         }
+        if let Some(inode) = self.ino2inode.get_mut(&moved) {
+            inode.parent = newparent;
+            inode.name = child_name.clone();
+        }
+        self.name2ino.insert((newparent, child_name), moved);
+        self.refresh(parent);
+        self.refresh(newparent);
+        self.forget_sizes(newparent);
 
-        reply.error(libc::ENOSYS);
+        self.meta.entry(moved).or_default().ctime = Some(SystemTime::now());
+        self.touch(parent);
+        self.touch(newparent);
+        Ok(())
     }
-    fn setattr(
+
+    /// Sets the change and creation times `setattr` names explicitly, over
+    /// what `set_attr` stamped. Only macOS sends a creation time.
+    fn set_change_times(
+        &mut self,
+        ino: u64,
+        ctime: Option<SystemTime>,
+        crtime: Option<SystemTime>,
+    ) -> Result<FileAttr, c_int> {
+        if ctime.is_some() || crtime.is_some() {
+            let meta = self.meta.entry(ino).or_default();
+            meta.ctime = ctime.or(meta.ctime);
+            meta.crtime = crtime.or(meta.crtime);
+        }
+        self.get_attr(ino)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn set_attr(
         &mut self,
-        _req: &Request<'_>,
         ino: u64,
         mode: Option<u32>,
         uid: Option<u32>,
         gid: Option<u32>,
         size: Option<u64>,
-        _atime: Option<fuser::TimeOrNow>,
-        _mtime: Option<fuser::TimeOrNow>,
-        _ctime: Option<std::time::SystemTime>,
-        fh: Option<u64>,
-        _crtime: Option<std::time::SystemTime>,
-        _chgtime: Option<std::time::SystemTime>,
-        _bkuptime: Option<std::time::SystemTime>,
-        flags: Option<u32>,
-        reply: ReplyAttr,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "setattr", "io"=> "in", 
-            "ino" => ino, "mode" => mode, "uid" => uid, "gid" => gid, "size" => size, 
-            "fh" => fh, "flags" => flags);
-        let attr = self.create_attr(ino, &Value::String("".to_string()));
-        reply.attr(&Duration::new(1, 0), &attr);
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+    ) -> Result<FileAttr, c_int> {
+        self.get_attr(ino)?;
+        if is_synthetic(ino) {
+            return Err(libc::EROFS);
+        }
+        match (ino, size) {
+            (DOCUMENT_INO, Some(size)) => self.truncate_document(size)?,
+            (_, Some(size)) => self.truncate_node(ino, size)?,
+            _ => {}
+        }
+
+        let meta = self.meta.entry(ino).or_default();
+        meta.ctime = Some(SystemTime::now());
+        if let Some(atime) = atime {
+            meta.atime = Some(resolve_time(atime));
+        }
+        if let Some(mtime) = mtime {
+            meta.mtime = Some(resolve_time(mtime));
+        }
+        if let Some(mode) = mode {
+            meta.perm = Some((mode & 0o7777) as u16);
+        }
+        if uid.is_some() {
+            meta.uid = uid;
+        }
+        if gid.is_some() {
+            meta.gid = gid;
+        }
+
+        self.get_attr(ino)
     }
-    fn flush(
+
+    /// Reads up to `size` bytes of `ino` from `offset`. Reads at or past the
+    /// end, which the kernel can legitimately issue, return nothing. Reads are
+    /// byte-oriented: a range may start or end inside a multibyte character,
+    /// and the caller reassembles the bytes. A directory can't be read as its
+    /// JSON: the kernel fails `read(2)` on one with `EISDIR` before it gets
+    /// here, so that is the answer here too, and the subtree's JSON is served
+    /// through `JSON_XATTR` instead.
+    fn read_data(&self, ino: u64, offset: i64, size: u32) -> Result<Vec<u8>, c_int> {
+        let content = if ino == DOCUMENT_INO {
+            self.document_bytes()
+        } else if let Some(dir) = len_dir(ino) {
+            self.dir_len(dir)?.to_string().into_bytes()
+        } else if ino == STATS_INO {
+            self.stats_text().into_bytes()
+        } else {
+            let value = self.get_node(ino)?;
+            match value {
+                Value::Array(vec) if self.node_kind(ino, value) == FileType::RegularFile => {
+                    lines_text(vec).into_bytes()
+                }
+                Value::Object(_) | Value::Array(_) => return Err(libc::EISDIR),
+                _ => self.scalar_text(value).into_bytes(),
+            }
+        };
+
+        let start = (offset.max(0) as usize).min(content.len());
+        let end = start.saturating_add(size as usize).min(content.len());
+        Ok(content[start..end].to_vec())
+    }
+
+    /// Copies `len` rendered bytes from `ino_in` into `ino_out`. Copying a whole
+    /// value from the start onto the start of another clones it, keeping its
+    /// JSON type, which under `strict_types` has to be the target's own;
+    /// partial ranges are spliced in like a `write`. Only scalar-to-scalar
+    /// copies are supported.
+    fn copy_range(
         &mut self,
-        _req: &Request<'_>,
-        _ino: u64,
-        _fh: u64,
-        _lock_owner: u64,
-        reply: fuser::ReplyEmpty,
-    ) {
-        debug!(slog_scope::logger(), "Filesystem func"; 
-            "op" => "flush", "io"=> "in", 
-            "fh" => _fh, "lock_owner" => _lock_owner);
+        ino_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        offset_out: i64,
+        len: u64,
+    ) -> Result<u32, c_int> {
+        if is_synthetic(ino_out) {
+            return Err(libc::EROFS);
+        }
+        let source = self.get_node(ino_in)?.clone();
+        if is_dir(&source) || is_dir(self.get_node(ino_out)?) {
+            return Err(libc::EINVAL);
+        }
 
-        self.as_mut().myflush();
+        let size = len.min(u32::MAX as u64) as u32;
+        let whole = self.read_data(ino_in, 0, u32::MAX)?;
+        if offset_in == 0 && offset_out == 0 && len >= whole.len() as u64 {
+            if !keeps_type(self.strict_type(ino_out, self.get_node(ino_out)?), &source) {
+                return Err(libc::EINVAL);
+            }
+            *self.get_node_mut(ino_out)? = source;
+            if let Some(meta) = self.meta.get_mut(&ino_out) {
+                meta.strict_type = None;
+            }
+            self.utf8_tails.remove(&ino_out);
+            self.touch(ino_out);
+            return Ok(whole.len() as u32);
+        }
 
-        reply.ok();
-        debug!(slog_scope::logger(), "Filesystem func"; "op" => "flush", "io"=> "out");
+        let data = self.read_data(ino_in, offset_in, size)?;
+        self.write_data(ino_out, offset_out, &data)
     }
-}
 
-mod tests {
-    use super::*;
+    /// Grows the scalar `ino` to at least `offset + length` bytes. The node
+    /// becomes a string of its rendered text padded with spaces, since NUL
+    /// bytes would leave a string no other JSON tool wants to read. With
+    /// `FALLOC_FL_KEEP_SIZE` nothing changes: there is no storage to reserve.
+    fn allocate(&mut self, ino: u64, offset: i64, length: i64, mode: i32) -> Result<(), c_int> {
+        if mode & !libc::FALLOC_FL_KEEP_SIZE != 0 {
+            return Err(libc::EOPNOTSUPP);
+        }
+        if offset < 0 || length <= 0 {
+            return Err(libc::EINVAL);
+        }
+        let end = byte_len(offset.checked_add(length).ok_or(libc::EFBIG)? as u64)?;
 
-    #[test]
-    fn test() {}
+        if is_synthetic(ino) {
+            return Err(libc::EROFS);
+        }
+        if ino == DOCUMENT_INO {
+            if mode & libc::FALLOC_FL_KEEP_SIZE == 0 && self.document_bytes().len() < end {
+                self.truncate_document(end as u64)?;
+            }
+            return Ok(());
+        }
+        if is_dir(self.get_node(ino)?) {
+            return Err(libc::EOPNOTSUPP);
+        }
+        if mode & libc::FALLOC_FL_KEEP_SIZE != 0 {
+            return Ok(());
+        }
+
+        let content = self.read_data(ino, 0, u32::MAX)?;
+        if content.len() < end {
+            self.check_size(end)?;
+            let mut padded = String::from_utf8_lossy(&content).into_owned();
+            padded.push_str(&" ".repeat(end - content.len()));
+            *self.get_node_mut(ino)? = Value::String(padded);
+            self.touch(ino);
+        }
+        Ok(())
+    }
+
+    /// Answers `SEEK_DATA`/`SEEK_HOLE` probes; the kernel handles the other
+    /// whences itself. A file's rendered content is a single data region
+    /// followed by the implicit hole at end of file.
+    fn seek(&self, ino: u64, offset: i64, whence: i32) -> Result<i64, c_int> {
+        let attr = self.get_attr(ino)?;
+        if attr.kind == FileType::Directory {
+            return Err(libc::EINVAL);
+        }
+        let size = attr.size as i64;
+        if offset < 0 {
+            return Err(libc::EINVAL);
+        }
+        match whence {
+            libc::SEEK_DATA if offset < size => Ok(offset),
+            libc::SEEK_HOLE if offset < size => Ok(size),
+            libc::SEEK_DATA | libc::SEEK_HOLE => Err(libc::ENXIO),
+            _ => Err(libc::EINVAL),
+        }
+    }
+
+    /// Writes `data` into the node `ino` at `offset`, overwriting exactly
+    /// that range (see `write_offset` for offsets outside the content), so the
+    /// chunks of a large write land where they belong. JSON strings can't carry arbitrary bytes,
+    /// so data that leaves the content invalid UTF-8 is rejected with `EINVAL`
+    /// instead of being lossily replaced; only a character cut in two at the
+    /// end of a chunk is held back for the next one. Content that is a JSON
+    /// number (negative, float or beyond 64 bits) becomes a number node keeping
+    /// its exact text.
+    fn write_data(&mut self, ino: u64, offset: i64, data: &[u8]) -> Result<u32, c_int> {
+        if ino == DOCUMENT_INO {
+            return self.write_document(offset, data);
+        }
+        if is_synthetic(ino) {
+            return Err(libc::EROFS);
+        }
+
+        let value = self.get_node(ino)?;
+        // An array of lines is spliced as its text, like a scalar.
+        let (lines, current) = match value {
+            Value::Array(vec) if self.node_kind(ino, value) == FileType::RegularFile => {
+                (true, lines_text(vec))
+            }
+            // Objects and arrays are directories; never clobber a whole subtree with a scalar.
+            _ if is_dir(value) => return Err(libc::EISDIR),
+            _ => (false, self.scalar_text(value)),
+        };
+
+        let strict_type = self.strict_type(ino, value);
+        let mut bytes = current.into_bytes();
+        bytes.extend(self.utf8_tails.get(&ino).into_iter().flatten());
+        let offset = write_offset(offset, bytes.len())?;
+        let end = offset + data.len();
+        if bytes.len() < end {
+            self.check_size(end)?;
+            bytes.resize(end, b' ');
+        }
+        bytes[offset..end].copy_from_slice(data);
+
+        let (text, tail) = match String::from_utf8(bytes) {
+            Ok(text) => (text, vec![]),
+            Err(err) if err.utf8_error().error_len().is_none() => {
+                let valid = err.utf8_error().valid_up_to();
+                let mut bytes = err.into_bytes();
+                let tail = bytes.split_off(valid);
+                (String::from_utf8(bytes).unwrap(), tail)
+            }
+            Err(_) => return Err(libc::EINVAL),
+        };
+        if lines {
+            *self.get_node_mut(ino)? = Value::Array(parse_lines(&text));
+            self.refresh(ino);
+        } else {
+            *self.get_node_mut(ino)? =
+                typed_scalar(strict_type, self.ungrouped(strict_type, text))?;
+            if let Some(meta) = self.meta.get_mut(&ino) {
+                meta.strict_type = None;
+            }
+        }
+        if tail.is_empty() {
+            self.utf8_tails.remove(&ino);
+        } else {
+            self.utf8_tails.insert(ino, tail);
+        }
+        self.touch(ino);
+        Ok(data.len() as u32)
+    }
+
+    /// Cuts or pads (with spaces) the content of `ino` to `size` bytes, as
+    /// `truncate` and `O_TRUNC` opens ask through `setattr`.
+    fn truncate_node(&mut self, ino: u64, size: u64) -> Result<(), c_int> {
+        let value = self.get_node(ino)?;
+        let strict_type = self.strict_type(ino, value);
+        let mut text = match value {
+            Value::Array(vec) if self.node_kind(ino, value) == FileType::RegularFile => {
+                lines_text(vec)
+            }
+            _ if is_dir(value) => return Err(libc::EISDIR),
+            _ => self.scalar_text(value),
+        }
+        .into_bytes();
+        let size = byte_len(size)?;
+        if text.len() < size {
+            self.check_size(size)?;
+        }
+        text.resize(size, b' ');
+        let text = String::from_utf8(text).map_err(|_| libc::EINVAL)?;
+        let text = self.ungrouped(strict_type, text);
+
+        let value = self.get_node_mut(ino)?;
+        if value.is_array() {
+            *value = Value::Array(parse_lines(&text));
+            self.meta.entry(ino).or_default().lines = true;
+            self.refresh(ino);
+        } else {
+            // A strict number emptied by `O_TRUNC` holds its text until the
+            // write that follows makes it a number again.
+            match typed_scalar(strict_type, text.clone()) {
+                Ok(typed) => *value = typed,
+                Err(_) => {
+                    *value = Value::String(text);
+                    self.meta.entry(ino).or_default().strict_type = strict_type;
+                }
+            }
+        }
+        self.utf8_tails.remove(&ino);
+        self.touch(ino);
+        Ok(())
+    }
+
+    /// Text of a number file: grouped in thousands under `humanize`.
+    fn number_text(&self, n: &serde_json::Number) -> String {
+        let text = n.to_string();
+        if self.options.humanize {
+            group_thousands(&text)
+        } else {
+            text
+        }
+    }
+
+    /// Text a scalar file is edited as, as `read_data` shows it.
+    fn scalar_text(&self, value: &Value) -> String {
+        match value {
+            Value::Number(n) => self.number_text(n),
+            _ => scalar_text(value),
+        }
+    }
+
+    /// Edited text with the separators of a grouped number dropped under
+    /// `humanize`, so it is stored as the plain number; any other text, or a
+    /// node that has to stay a string, is left alone.
+    fn ungrouped(&self, strict_type: Option<StrictType>, text: String) -> String {
+        if !self.options.humanize || strict_type == Some(StrictType::String) {
+            return text;
+        }
+        let number = text.trim_end();
+        match ungroup_thousands(number) {
+            Some(plain) => plain + &text[number.len()..],
+            None => text,
+        }
+    }
+
+    /// Value of the extended attribute `name` on `ino`.
+    fn xattr(&self, ino: u64, name: &str) -> Result<Vec<u8>, c_int> {
+        if ino == FUSE_ROOT_ID || ino == DOCUMENT_INO {
+            match name {
+                VERSION_XATTR => return Ok(self.version.to_string().into_bytes()),
+                ETAG_XATTR => return Ok(self.etag().into_bytes()),
+                _ => {}
+            }
+        }
+        if ino == DOCUMENT_INO || is_synthetic(ino) {
+            return Err(libc::ENODATA);
+        }
+        match self.get_node(ino)? {
+            Value::Number(n) if name == NUMTYPE_XATTR => Ok(number_type(n).into()),
+            value if name == JSON_XATTR && self.options.read_pretty => {
+                Ok(self.pretty(value).into_bytes())
+            }
+            value if name == JSON_XATTR => Ok(value.to_string().into_bytes()),
+            _ => Err(libc::ENODATA),
+        }
+    }
+
+    /// Sets the extended attribute `name` on `ino`; only `JSON_XATTR` can be
+    /// set, and only to valid JSON (`EINVAL` leaves the node as it was).
+    fn set_xattr(&mut self, ino: u64, name: &str, value: &[u8], flags: i32) -> Result<(), c_int> {
+        if ino == DOCUMENT_INO || is_synthetic(ino) {
+            return Err(libc::EPERM);
+        }
+        self.get_node(ino)?;
+        match name {
+            JSON_XATTR if flags & libc::XATTR_CREATE != 0 => Err(libc::EEXIST),
+            JSON_XATTR => {
+                let json = serde_json::from_slice(value).map_err(|_| libc::EINVAL)?;
+                self.set_value(ino, json)
+            }
+            NUMTYPE_XATTR | VERSION_XATTR | ETAG_XATTR => Err(libc::EPERM),
+            _ => Err(libc::ENOTSUP),
+        }
+    }
+
+    /// Replaces the node `ino` with `json`, registering its new children.
+    /// The replacement is held to the limits of any other edit: it must fit
+    /// within `max_depth`, each file in it within `max_value_bytes`, and under
+    /// `strict_types` a scalar must keep its type, or `EINVAL`.
+    fn set_value(&mut self, ino: u64, json: Value) -> Result<(), c_int> {
+        self.check_sizes(&json)?;
+        if ino == FUSE_ROOT_ID {
+            wraps_scalar(&json, &self.options).ok_or(libc::EINVAL)?;
+            self.replace_document(json);
+            return Ok(());
+        }
+        let parent = self.ino2inode.get(&ino).ok_or(ENOENT)?.parent;
+        self.check_depth(parent, Summary::of(&json).max_depth)?;
+        if !keeps_type(self.strict_type(ino, self.get_node(ino)?), &json) {
+            return Err(libc::EINVAL);
+        }
+        *self.get_node_mut(ino)? = json;
+        let meta = self.meta.entry(ino).or_default();
+        meta.strict_type = None;
+        meta.lines = false;
+        // Refreshed from the parent, so a change of kind is noticed.
+        self.refresh(parent);
+        self.touch(ino);
+        self.touch(parent);
+        Ok(())
+    }
+
+    /// Names of the extended attributes of `ino`, each NUL-terminated.
+    fn xattr_names(&self, ino: u64) -> Result<Vec<u8>, c_int> {
+        if ino == FUSE_ROOT_ID || ino == DOCUMENT_INO {
+            return Ok(format!("{}\0{}\0", VERSION_XATTR, ETAG_XATTR).into_bytes());
+        }
+        if is_synthetic(ino) {
+            return Ok(vec![]);
+        }
+        match self.get_node(ino)? {
+            Value::Number(_) => Ok(format!("{}\0", NUMTYPE_XATTR).into_bytes()),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Hash of the document's text, as hex. It changes with the document,
+    /// but is only comparable within one jsonfs build.
+    fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.saved(&self.json).to_string().hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Type `ino` has to keep under `strict_types`; `None` when not strict or
+    /// for `null`, which holds no text to edit.
+    fn strict_type(&self, ino: u64, value: &Value) -> Option<StrictType> {
+        if !self.options.strict_types {
+            return None;
+        }
+        let kept = self.meta.get(&ino).and_then(|meta| meta.strict_type);
+        kept.or(match value {
+            Value::String(_) => Some(StrictType::String),
+            Value::Number(_) => Some(StrictType::Number),
+            Value::Bool(_) => Some(StrictType::Bool),
+            _ => None,
+        })
+    }
+
+    /// Fails with `EFBIG` when `len` bytes would exceed `max_value_bytes`.
+    fn check_size(&self, len: usize) -> Result<(), c_int> {
+        match self.options.max_value_bytes {
+            Some(max) if len > max => Err(libc::EFBIG),
+            _ => Ok(()),
+        }
+    }
+
+    /// `check_size` for every file in the subtree `value`.
+    fn check_sizes(&self, value: &Value) -> Result<(), c_int> {
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::Array(vec) if self.kind(value) == FileType::RegularFile => {
+                    self.check_size(lines_text(vec).len())?
+                }
+                Value::Object(map) => pending.extend(map.values()),
+                Value::Array(vec) => pending.extend(vec),
+                _ => self.check_size(self.scalar_text(value).len())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a read of `ino` in its atime, as `options.atime` asks.
+    fn note_access(&mut self, ino: u64) {
+        if self.options.atime == Atime::Noatime {
+            return;
+        }
+        let now = SystemTime::now();
+        let meta = self.meta.entry(ino).or_default();
+        let atime = meta.atime.unwrap_or(self.mount_time);
+        let stale = self.options.atime == Atime::Strict
+            || atime <= meta.mtime.unwrap_or(self.mount_time)
+            || atime <= meta.ctime.unwrap_or(self.mount_time)
+            || now
+                .duration_since(atime)
+                .is_ok_and(|age| age >= RELATIME_INTERVAL);
+        if stale {
+            meta.atime = Some(now);
+        }
+    }
+
+    /// Records a content change of `ino`, which also leaves the tree dirty
+    /// and wakes anyone polling `ino`.
+    fn touch(&mut self, ino: u64) {
+        let now = SystemTime::now();
+        let meta = self.meta.entry(ino).or_default();
+        meta.mtime = Some(now);
+        meta.ctime = Some(now);
+        self.dirty = true;
+        self.version += 1;
+
+        if let (Some(handles), Some(notify)) = (self.polls.remove(&ino), &self.poll_notify) {
+            handles.into_iter().for_each(notify);
+        }
+    }
+
+    /// Sets how poll wakeups reach the kernel, typically through the
+    /// session's `Notifier`.
+    pub(crate) fn set_poll_notify(&mut self, notify: PollNotify) {
+        self.poll_notify = Some(notify);
+    }
+
+    /// Sets how the kernel is told to drop cached entries, typically through
+    /// the session's `Notifier`.
+    pub(crate) fn set_inval_notify(&mut self, notify: InvalNotify) {
+        self.inval_notify = Some(notify);
+    }
+
+    /// Sets what to call once the kernel has initialized the mount, when
+    /// requests start to be served.
+    pub(crate) fn set_ready_notify(&mut self, notify: ReadyNotify) {
+        self.ready_notify = Some(notify);
+    }
+
+    /// Drops the kernel's cache of `name` (see `name_of`) under `parent`,
+    /// which is inode `ino`, rather than leaving it stale until the TTL runs out.
+    fn invalidate(&self, parent: u64, ino: u64, name: &str) {
+        let Some(notify) = &self.inval_notify else {
+            return;
+        };
+        if let Some(file_name) = self.file_name(&key_of(name)) {
+            debug!(slog_scope::logger(), "invalidate"; "parent" => parent, "ino" => ino, "name" => &file_name);
+            notify(parent, ino, &file_name);
+        }
+    }
+
+    /// Answers a poll of `ino`: nodes are always ready for reading and
+    /// writing, and when the kernel asks to be told of changes, `kh` is woken
+    /// by the next one.
+    fn poll_events(&mut self, ino: u64, kh: u64, flags: u32) -> Result<u32, c_int> {
+        if ino != DOCUMENT_INO {
+            self.get_attr(ino)?;
+        }
+        if flags & fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY != 0 {
+            self.polls.entry(ino).or_default().push(kh);
+        }
+        Ok((libc::POLLIN | libc::POLLOUT) as u32)
+    }
+
+    /// Stamps every time of the freshly created `ino` and updates its parent.
+    fn stamp_created(&mut self, parent: u64, ino: u64) {
+        let now = SystemTime::now();
+        let meta = self.meta.entry(ino).or_default();
+        meta.atime = Some(now);
+        meta.mtime = Some(now);
+        meta.ctime = Some(now);
+        meta.crtime = Some(now);
+        self.touch(parent);
+    }
+
+    /// Replaces the backing file with `data`, gzipped if it is compressed. The
+    /// data goes to a temporary file beside it that is renamed over it, so a
+    /// failed save leaves the old document whole. A symlink is followed,
+    /// and the file keeps its permissions.
+    fn save(&self, data: &[u8]) -> io::Result<()> {
+        let path = self.saved_path();
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let temp = sidecar(&target, "tmp");
+
+        let file = fs::File::create(&temp)?;
+        let written = if self.gzip {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(data).and_then(|()| encoder.finish())
+        } else {
+            let mut file = file;
+            file.write_all(data).map(|()| file)
+        }
+        .and_then(|file| file.sync_all());
+        let renamed = written.and_then(|()| {
+            if let Ok(meta) = fs::metadata(&target) {
+                fs::set_permissions(&temp, meta.permissions())?;
+            }
+            fs::rename(&temp, &target)
+        });
+        if renamed.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        renamed
+    }
+
+    /// Copies the backing file to `<file>.bak` beside it, following a
+    /// symlink as `save` does. A file that doesn't exist yet has nothing to
+    /// keep.
+    fn back_up(&self) -> io::Result<()> {
+        let path = self.saved_path();
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !target.exists() {
+            return Ok(());
+        }
+        let mut backup = target.clone().into_os_string();
+        backup.push(".bak");
+        fs::copy(&target, backup).map(|_| ())
+    }
+
+    /// A copy of the whole document as it stands in memory, saved or not,
+    /// for a host embedding the filesystem. The binary itself doesn't need it.
+    #[allow(dead_code)]
+    pub(crate) fn snapshot(&self) -> Value {
+        self.saved(&self.json).clone()
+    }
+
+    /// The whole document as JSON text, indented as it is saved if `pretty`.
+    #[allow(dead_code)]
+    pub(crate) fn export_string(&self, pretty: bool) -> String {
+        let json = self.saved(&self.json);
+        if pretty {
+            self.pretty(json)
+        } else {
+            json.to_string()
+        }
+    }
+
+    /// Fails with `EINVAL` if the document doesn't match `Options::schema`,
+    /// logging each mismatch.
+    fn check_schema(&self) -> Result<(), c_int> {
+        let Some(schema) = &self.schema else {
+            return Ok(());
+        };
+        let mut valid = true;
+        for err in schema.iter_errors(self.saved(&self.json)) {
+            warn!(slog_scope::logger(), "document doesn't match the schema";
+                "file" => %self.saved_path().display(), "at" => %err.instance_path, "error" => %err);
+            valid = false;
+        }
+        if valid {
+            Ok(())
+        } else {
+            Err(libc::EINVAL)
+        }
+    }
+
+    /// Saves as `myflush` does where nobody can be told it failed: `op`, the
+    /// handler or thread that saves, only warns, and the changes stay pending.
+    fn flush_for(&mut self, op: &'static str) {
+        if let Err(err) = self.myflush() {
+            warn!(slog_scope::logger(), "changes not saved";
+                "op" => op, "file" => %self.saved_path().display(), "errno" => err);
+        }
+    }
+
+    /// Whether another mount owns the file, so this one must be read-only.
+    pub(crate) fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// The file changes are saved to: the overrides under `overlay`, else
+    /// `json_path`.
+    fn saved_path(&self) -> &Path {
+        self.options.overlay.as_deref().unwrap_or(&self.json_path)
+    }
+
+    /// Writes the whole document to `saved_path`, unless nothing changed
+    /// since the last write or the mount is read-only or `no_flush`. A failed
+    /// write keeps the changes pending and returns its errno.
+    fn myflush(&mut self) -> Result<(), c_int> {
+        if !self.dirty || self.read_only || self.options.no_flush {
+            return Ok(());
+        }
+        debug!(slog_scope::logger(), "Saving JSON data";
+            "file" => %self.saved_path().display());
+        self.check_schema()?;
+        let json = self.saved(&self.json);
+        let diff = self.base.as_ref().map(|(base, overrides)| {
+            overlay::diff(base, json, overrides)
+                .unwrap_or_else(|| Value::Object(serde_json::Map::new()))
+        });
+        let json = diff.as_ref().unwrap_or(json);
+        let text = match format_of(self.saved_path(), &self.options) {
+            Format::Json => self
+                .original
+                .as_deref()
+                .and_then(|original| inplace::splice(original, json, self.options.lenient))
+                .unwrap_or_else(|| self.pretty(json)),
+            format => match format.render(json) {
+                Ok(text) => text,
+                Err(err) => {
+                    warn!(slog_scope::logger(), "saving failed";
+                        "file" => %self.saved_path().display(), "format" => %format, "error" => err);
+                    return Err(libc::EINVAL);
+                }
+            },
+        };
+        // Only a document that is about to be written replaces the backup.
+        if self.options.backup && !self.backed_up {
+            if let Err(err) = self.back_up() {
+                warn!(slog_scope::logger(), "backup failed";
+                    "file" => %self.saved_path().display(), "error" => %err);
+                return Err(err.raw_os_error().unwrap_or(libc::EIO));
+            }
+            self.backed_up = true;
+        }
+        if let Err(err) = self.save(text.as_bytes()) {
+            warn!(slog_scope::logger(), "saving failed";
+                "file" => %self.saved_path().display(), "error" => %err);
+            return Err(err.raw_os_error().unwrap_or(libc::EIO));
+        }
+        self.dirty = false;
+        if self.original.is_some() {
+            self.original = Some(text);
+        }
+        if let (Some((_, overrides)), Some(diff)) = (&mut self.base, diff) {
+            *overrides = diff;
+        }
+        debug!(slog_scope::logger(), "JSON data saved";
+            "file" => %self.saved_path().display());
+        Ok(())
+    }
+}
+
+/// Finds the child called `name`, an escaped key (see `name_of`) or an array index.
+fn lookup_children<'a>(value: &'a Value, name: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.get(&key_of(name)),
+        Value::Array(vec) => {
+            debug!(slog_scope::logger(), "lookup_children"; "name" => name);
+            vec.get(parse_index(name)?)
+        }
+        _ => None,
+    }
+}
+
+fn lookup_children_mut<'a>(value: &'a mut Value, name: &str) -> Option<&'a mut Value> {
+    match value {
+        Value::Object(map) => map.get_mut(&key_of(name)),
+        Value::Array(vec) => vec.get_mut(parse_index(name)?),
+        _ => None,
+    }
+}
+
+/// A file name from the kernel as UTF-8, which every JSON key is; anything
+/// else can't name a node and fails with `EINVAL`.
+pub(crate) fn utf8_name(name: &OsStr) -> Result<&str, c_int> {
+    name.to_str().ok_or_else(|| {
+        warn!(slog_scope::logger(), "file name is not UTF-8"; "name" => ?name);
+        libc::EINVAL
+    })
+}
+
+/// The directory whose `.len` file is `ino`, if it is one.
+fn len_dir(ino: u64) -> Option<u64> {
+    (ino & LEN_INO != 0).then_some(ino & !LEN_INO)
+}
+
+/// Whether `ino` is a file jsonfs makes up that can't be changed: a `.len`
+/// file or `.stats`. Its content, size and attributes fail to change with
+/// `EROFS`. The document file is made up too, but writable.
+fn is_synthetic(ino: u64) -> bool {
+    ino == STATS_INO || len_dir(ino).is_some()
+}
+
+/// Whether `name` under `parent` names a made-up file, which shadows any key
+/// of that name: such an entry can't be created, linked or removed.
+fn is_reserved(parent: u64, name: &str) -> bool {
+    name == LEN_NAME || (parent == FUSE_ROOT_ID && (name == DOCUMENT_NAME || name == STATS_NAME))
+}
+
+/// Hidden file beside `target` that jsonfs uses for `purpose`:
+/// `.<name>.jsonfs-<purpose>`.
+fn sidecar(target: &Path, purpose: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(".jsonfs-");
+    name.push(purpose);
+    target.with_file_name(name)
+}
+
+/// The lock `lock_file` took, on the open sidecar at `path`.
+struct FileLock {
+    _file: fs::File,
+    path: PathBuf,
+}
+
+impl Drop for FileLock {
+    /// Removes the sidecar while still holding the lock on it, so no mount is
+    /// left behind with it; see `lock_file` for one that had it open.
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes the advisory lock that keeps two jsonfs from mounting (and saving
+/// over) the file at `path`; `None` if another one holds it. The lock is on a
+/// sidecar file, as the file itself is replaced by every save. The holder
+/// removes the sidecar when it is done, so a lock taken on a sidecar that is
+/// no longer there is let go and taken again on a new one.
+fn lock_file(path: &Path) -> io::Result<Option<FileLock>> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let lock_path = sidecar(&target, "lock");
+    loop {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            return match io::Error::last_os_error() {
+                err if err.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(None),
+                err => Err(err),
+            };
+        }
+        let (held, current) = (file.metadata()?, fs::metadata(&lock_path));
+        if current.is_ok_and(|current| (current.dev(), current.ino()) == (held.dev(), held.ino())) {
+            return Ok(Some(FileLock {
+                _file: file,
+                path: lock_path,
+            }));
+        }
+    }
+}
+
+/// Reads and compiles the JSON Schema at `path`.
+fn load_schema(path: &Path) -> Result<jsonschema::Validator, JsonFsError> {
+    let text = fs::read_to_string(path).map_err(|err| JsonFsError::io(path, err))?;
+    let schema = serde_json::from_str(&text).map_err(|err| JsonFsError::parse(path, err, &text))?;
+    jsonschema::validator_for(&schema).map_err(|err| JsonFsError::schema(path, err.to_string()))
+}
+
+/// Whether the backing file is gzip-compressed.
+fn gzipped(json_path: &Path, options: &Options) -> bool {
+    options.gzip || json_path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Syntax of the file at `path`: `options.format` if set, else its extension's.
+fn format_of(path: &Path, options: &Options) -> Format {
+    options.format.unwrap_or_else(|| Format::of_path(path))
+}
+
+/// Reads the text of the backing file. The outer error is the file's own, so
+/// a missing file can be told apart; the inner one is a bad gzip stream or
+/// text that isn't UTF-8.
+fn read_document(json_path: &Path, gzip: bool) -> io::Result<Result<String, JsonFsError>> {
+    let bytes = fs::read(json_path)?;
+    let bytes = if gzip {
+        let mut plain = vec![];
+        if let Err(err) = GzDecoder::new(&bytes[..]).read_to_end(&mut plain) {
+            return Ok(Err(JsonFsError::decompress(json_path, err)));
+        }
+        plain
+    } else {
+        bytes
+    };
+    Ok(String::from_utf8(bytes)
+        .map_err(|err| JsonFsError::io(json_path, io::Error::new(io::ErrorKind::InvalidData, err))))
+}
+
+/// Whether a document whose mounted root is `root` is mounted wrapped, as a
+/// scalar under `wrap_scalar`; `None` for a scalar that can't be mounted.
+fn wraps_scalar(root: &Value, options: &Options) -> Option<bool> {
+    match root {
+        Value::Object(_) | Value::Array(_) => Some(false),
+        _ if options.wrap_scalar && options.root_pointer.is_empty() => Some(true),
+        _ => None,
+    }
+}
+
+fn wrap_scalar(json: Value) -> Value {
+    Value::Object(serde_json::Map::from_iter([(SCALAR_KEY.to_string(), json)]))
+}
+
+/// Resolves an RFC 6901 JSON Pointer such as `/a~1b/0` against `value`.
+fn resolve_pointer<'a>(value: &'a Value, pointer: &str) -> Option<&'a Value> {
+    if pointer.is_empty() {
+        return Some(value);
+    }
+    pointer
+        .strip_prefix('/')?
+        .split('/')
+        .try_fold(value, lookup_children)
+}
+
+/// File name of an object key. A name can't contain `/`, so keys are escaped
+/// as JSON Pointer tokens: `~` becomes `~0` and `/` becomes `~1`.
+pub(crate) fn name_of(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// File name of an object key under `escape_keys`: `%`, `/`, NUL and other
+/// control characters become `%XX`.
+fn percent_encode(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            '%' | '/' | '\0'..='\x1f' | '\x7f' => name.push_str(&format!("%{:02X}", c as u32)),
+            _ => name.push(c),
+        }
+    }
+    name
+}
+
+/// Object key of a file name under `escape_keys`, undoing `percent_encode`.
+/// A `%` not followed by two hex digits is kept as it is.
+fn percent_decode(name: &str) -> String {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&first, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if first == b'%' => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(first);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The name `name` stands for without its type extension, and the empty
+/// value of that type: `.str`, `.num`, `.bool`, `.null`, `.obj` or `.arr`.
+/// `None` for any other name, or for an extension with nothing before it.
+fn typed_name(name: &str) -> Option<(&str, Value)> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    let initial = match extension {
+        "str" => Value::String(String::new()),
+        "num" => serde_json::json!(0),
+        "bool" => Value::Bool(false),
+        "null" => Value::Null,
+        "obj" => serde_json::json!({}),
+        "arr" => serde_json::json!([]),
+        _ => return None,
+    };
+    (!stem.is_empty()).then_some((stem, initial))
+}
+
+/// Object key of a file name, undoing `name_of`.
+fn key_of(name: &str) -> String {
+    name.replace("~1", "/").replace("~0", "~")
+}
+
+/// Whether `name` matches `glob`, where `*` stands for any run of characters
+/// and `?` for any one.
+fn glob_match(glob: &str, name: &str) -> bool {
+    let (glob, name): (Vec<char>, Vec<char>) = (glob.chars().collect(), name.chars().collect());
+    let (mut g, mut n) = (0, 0);
+    // Where the last `*` was, and where in `name` its run ends so far.
+    let mut star = None;
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    star = Some((star_g, star_n + 1));
+                    g = star_g + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Parses an array index the way JSON Pointer spells it: digits only, without
+/// leading zeros, so `+1` or `01` don't alias element 1.
+fn parse_index(name: &str) -> Option<usize> {
+    let canonical = name == "0" || !name.starts_with('0');
+    if canonical && !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+        name.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Names of the direct children of `value`: escaped object keys or array indices.
+fn child_names(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(map) => map.keys().map(|key| name_of(key)).collect(),
+        Value::Array(vec) => (0..vec.len()).map(|i| i.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+/// `size`, a file size the kernel asked for, as a length in memory; `EFBIG`
+/// if it can't be one.
+fn byte_len(size: u64) -> Result<usize, c_int> {
+    usize::try_from(size).map_err(|_| libc::EFBIG)
+}
+
+/// Finds the key matching `name` ignoring case, preferring an exact match and
+/// then the first key in map order. Several keys differing only in case are logged.
+fn lookup_key_ignore_case<'a>(
+    map: &'a serde_json::Map<String, Value>,
+    name: &str,
+) -> Option<&'a str> {
+    if let Some((key, _)) = map.get_key_value(name) {
+        return Some(key);
+    }
+    let lower = name.to_lowercase();
+    let mut matches = map.iter().filter(|(key, _)| key.to_lowercase() == lower);
+    let (key, _) = matches.next()?;
+    let others: Vec<&String> = matches.map(|(key, _)| key).collect();
+    if !others.is_empty() {
+        warn!(slog_scope::logger(), "ambiguous case-insensitive lookup";
+            "name" => name, "chosen" => key, "others" => format!("{:?}", others));
+    }
+    Some(key)
+}
+
+/// Whether `value` can be shown as lines: a non-empty array of scalars.
+fn is_lines(value: &Value) -> bool {
+    match value {
+        Value::Array(vec) => !vec.is_empty() && vec.iter().all(|v| !is_dir(v)),
+        _ => false,
+    }
+}
+
+/// Renders an array of scalars as one line per element, strings unquoted.
+fn lines_text(vec: &[Value]) -> String {
+    vec.iter()
+        .map(|v| match v {
+            Value::String(s) => format!("{}\n", s),
+            _ => format!("{}\n", v),
+        })
+        .collect()
+}
+
+/// Splits text back into elements. A line that is a JSON number, boolean or
+/// `null` keeps that type, anything else becomes a string.
+fn parse_lines(text: &str) -> Vec<Value> {
+    text.lines()
+        .map(|line| match serde_json::from_str::<Value>(line) {
+            Ok(value) if !value.is_string() && !is_dir(&value) => value,
+            _ => Value::String(line.to_string()),
+        })
+        .collect()
+}
+
+/// Checks a write offset against content of `len` bytes: negative offsets are
+/// `EINVAL`, offsets past the end append.
+pub(crate) fn write_offset(offset: i64, len: usize) -> Result<usize, c_int> {
+    let offset = usize::try_from(offset).map_err(|_| libc::EINVAL)?;
+    Ok(offset.min(len))
+}
+
+/// Text a scalar is edited as: a string's own content, nothing for `null`,
+/// the JSON spelling otherwise.
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        _ => value.to_string(),
+    }
+}
+
+/// `number` with commas between the thousands of its integer part.
+fn group_thousands(number: &str) -> String {
+    let digits = number.trim_start_matches('-');
+    let sign = &number[..number.len() - digits.len()];
+    let int_len = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let (int, rest) = digits.split_at(int_len);
+    let mut grouped = String::with_capacity(number.len() + int_len / 3);
+    grouped.push_str(sign);
+    for (i, digit) in int.chars().enumerate() {
+        if i > 0 && (int_len - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped.push_str(rest);
+    grouped
+}
+
+/// The plain number `group_thousands` made `text` from, if it is one: an
+/// integer part of one to three digits followed by groups of three.
+fn ungroup_thousands(text: &str) -> Option<String> {
+    let digits = text.trim_start_matches('-');
+    let int_len = digits
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .unwrap_or(digits.len());
+    let mut groups = digits[..int_len].split(',');
+    let first = groups.next()?;
+    let mut rest = groups.peekable();
+    rest.peek()?;
+    if first.is_empty()
+        || first.len() > 3
+        || !rest.all(|group| group.len() == 3)
+        || digits[int_len..].contains(',')
+    {
+        return None;
+    }
+    let plain = text.replace(',', "");
+    plain.parse::<serde_json::Number>().ok().map(|_| plain)
+}
+
+/// `integer` or `float`, by how the number is written: with numbers kept as
+/// their original text, `1e30` is a float and so is `5.0`.
+fn number_type(n: &serde_json::Number) -> &'static str {
+    if n.is_i64() || n.is_u64() {
+        "integer"
+    } else {
+        "float"
+    }
+}
+
+/// Value for edited scalar text of type `strict_type`, or `EINVAL` if the text
+/// doesn't spell one; a trailing newline, as `echo` adds, is dropped. Without
+/// a type, anything goes through `scalar_from_text`.
+fn typed_scalar(strict_type: Option<StrictType>, text: String) -> Result<Value, c_int> {
+    match strict_type {
+        None => Ok(scalar_from_text(text)),
+        Some(StrictType::String) => Ok(Value::String(text)),
+        Some(StrictType::Number) => text
+            .trim_end()
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| libc::EINVAL),
+        Some(StrictType::Bool) => match text.trim_end() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Err(libc::EINVAL),
+        },
+    }
+}
+
+/// Whether `value` may replace a node that has to keep `strict_type`.
+fn keeps_type(strict_type: Option<StrictType>, value: &Value) -> bool {
+    matches!(
+        (strict_type, value),
+        (None, _)
+            | (Some(StrictType::String), Value::String(_))
+            | (Some(StrictType::Number), Value::Number(_))
+            | (Some(StrictType::Bool), Value::Bool(_))
+    )
+}
+
+/// Value for edited scalar text: a number, boolean or `null` if it spells
+/// one, as a line of an array does, else a string.
+fn scalar_from_text(text: String) -> Value {
+    match text.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" => Value::Null,
+        _ => match text.parse::<serde_json::Number>() {
+            Ok(number) => Value::Number(number),
+            Err(_) => Value::String(text),
+        },
+    }
+}
+
+pub(crate) fn resolve_time(time: TimeOrNow) -> SystemTime {
+    match time {
+        TimeOrNow::SpecificTime(time) => time,
+        TimeOrNow::Now => SystemTime::now(),
+    }
+}
+
+fn is_dir(value: &Value) -> bool {
+    get_value_type(value) == FileType::Directory
+}
+
+fn get_value_type(value: &Value) -> FileType {
+    match value {
+        Value::Object(_) | Value::Array(_) => FileType::Directory,
+        _ => FileType::RegularFile,
+    }
+}
+
+/// Node counts of a document, as printed by `--check`.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Summary {
+    objects: usize,
+    arrays: usize,
+    strings: usize,
+    numbers: usize,
+    booleans: usize,
+    nulls: usize,
+    /// Nesting of the deepest node; the root is at depth 0.
+    max_depth: usize,
+}
+
+impl Summary {
+    pub(crate) fn of(value: &Value) -> Summary {
+        let mut summary = Summary::default();
+        let mut pending = vec![(value, 0)];
+        while let Some((value, depth)) = pending.pop() {
+            summary.max_depth = summary.max_depth.max(depth);
+            match value {
+                Value::Object(map) => {
+                    summary.objects += 1;
+                    pending.extend(map.values().map(|child| (child, depth + 1)));
+                }
+                Value::Array(vec) => {
+                    summary.arrays += 1;
+                    pending.extend(vec.iter().map(|child| (child, depth + 1)));
+                }
+                Value::String(_) => summary.strings += 1,
+                Value::Number(_) => summary.numbers += 1,
+                Value::Bool(_) => summary.booleans += 1,
+                Value::Null => summary.nulls += 1,
+            }
+        }
+        summary
+    }
+
+    fn nodes(&self) -> usize {
+        self.objects + self.arrays + self.strings + self.numbers + self.booleans + self.nulls
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "nodes: {}", self.nodes())?;
+        writeln!(f, "  objects: {}", self.objects)?;
+        writeln!(f, "  arrays: {}", self.arrays)?;
+        writeln!(f, "  strings: {}", self.strings)?;
+        writeln!(f, "  numbers: {}", self.numbers)?;
+        writeln!(f, "  booleans: {}", self.booleans)?;
+        writeln!(f, "  nulls: {}", self.nulls)?;
+        write!(f, "max depth: {}", self.max_depth)
+    }
+}
+
+impl Filesystem for JsonFS {
+    fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        let timer = OpTimer::start("init");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "init", "io"=> "in", "config" => format!("{:?}", config));
+
+        // Nothing beyond the base protocol is required, so only the write size
+        // is negotiated; a kernel that can't take it gets the nearest size.
+        if let Err(nearest) = config.set_max_write(MAX_WRITE) {
+            warn!(slog_scope::logger(), "Filesystem func";
+                "op" => "init", "io"=> "out", "max_write" => nearest);
+            config.set_max_write(nearest).map_err(|_| libc::EINVAL)?;
+        }
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "init", "io"=> "out", "config" => format!("{:?}", config),
+            "dur_us" => timer.dur_us());
+        if let Some(ready) = self.ready_notify.take() {
+            ready();
+        }
+        Ok(())
+    }
+    /// Saves on unmount, so changes no program flushed aren't lost.
+    fn destroy(&mut self) {
+        let timer = OpTimer::start("destroy");
+        debug!(slog_scope::logger(), "Filesystem func"; "op" => "destroy", "io"=> "in");
+        self.flush_for("destroy");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "destroy", "io"=> "out", "dur_us" => timer.dur_us());
+    }
+
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let timer = OpTimer::start("lookup");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "lookup", "io"=> "in", "parent" => parent, "name" => ?name);
+
+        match utf8_name(name)
+            .and_then(|name| self.lookup_entry(parent, &self.entry_name(parent, name)))
+        {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func"; 
+                    "op" => "lookup", "io"=> "out", "attr" => format!("{:?}", attr), "dur_us" => timer.dur_us());
+                let (ttl, generation) = self.entry_reply(&attr);
+                reply.entry(&ttl, &attr, generation);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        let timer = OpTimer::start("getattr");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "getattr", "io"=> "in", "ino" => ino);
+        if let Some(Inode {
+            ino, parent, name, ..
+        }) = self.ino2inode.get(&ino)
+        {
+            debug!(slog_scope::logger(), "getattr"; "ino" => ino, "parent" => parent, "name" => name);
+        }
+        match self.get_attr(ino) {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func"; 
+                    "op" => "getattr", "io"=> "out", "attr" => format!("{:?}", attr), "dur_us" => timer.dur_us());
+                reply.attr(&self.options.attr_ttl, &attr);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let timer = OpTimer::start("read");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "read", "io"=> "in", 
+            "ino" => ino, "fh" => _fh, "offset" => offset, "size" => size, 
+            "flags" => _flags, "lock_owner" => _lock_owner);
+
+        match self.read_data(ino, offset, size) {
+            Ok(data) => {
+                debug!(slog_scope::logger(), "Filesystem func";
+                    "op" => "read", "io"=> "out",
+                    "content" => %String::from_utf8_lossy(&data), "dur_us" => timer.dur_us());
+                self.note_access(ino);
+                reply.data(&data)
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let _timer = OpTimer::start("readdir");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "readdir", "io"=> "in", "ino" => ino, "fh" => _fh, "offset" => offset);
+
+        let _ = self.read_dir(ino, _fh, offset, |entry_ino, next, kind, name| {
+            reply.add(entry_ino, next, kind, name)
+        });
+        reply.ok();
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
+        let _timer = OpTimer::start("opendir");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "opendir", "io"=> "in", "ino" => ino);
+
+        match self.open_dir(ino) {
+            Ok(fh) => reply.opened(fh, 0),
+            Err(err) => reply.error(err),
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _timer = OpTimer::start("releasedir");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "releasedir", "io"=> "in", "ino" => ino, "fh" => fh);
+
+        self.dir_handles.remove(&fh);
+        reply.ok();
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        let timer = OpTimer::start("mkdir");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "mkdir", "io"=> "in", "parent" => parent, "name" => ?name);
+
+        let mode = if mode & 0o7777 == 0 { 0o755 } else { mode };
+        let result = self
+            .check_access(parent, req.uid(), req.gid(), libc::W_OK | libc::X_OK)
+            .and_then(|()| utf8_name(name))
+            .and_then(|name| self.make_dir(parent, &self.entry_name(parent, name), mode & !umask));
+        match result {
+            Ok(attr) => {
+                let (ttl, generation) = self.entry_reply(&attr);
+                reply.entry(&ttl, &attr, generation);
+            }
+            Err(err) => {
+                warn!(slog_scope::logger(), "Filesystem func"; "op" => "mkdir", "io"=> "out", "parent" => parent, "name" => ?name, "error" => err, "dur_us" => timer.dur_us());
+                reply.error(err);
+            }
+        }
+    }
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        let timer = OpTimer::start("write");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "write", "io"=> "in", 
+            "ino" => ino, "fh" => _fh, "offset" => offset, "content" => format!("{:?}", String::from_utf8_lossy(data)), 
+            "flags" => _flags, "lock_owner" => _lock_owner, "data_size" => data.len());
+
+        let result = self
+            .check_access(ino, req.uid(), req.gid(), libc::W_OK)
+            .and_then(|()| self.write_data(ino, offset, data));
+        match result {
+            Ok(written) => reply.written(written),
+            Err(err) => {
+                warn!(slog_scope::logger(), "Filesystem func";
+                    "op" => "write", "io"=> "out", "ino" => ino, "error" => err, "dur_us" => timer.dur_us());
+                reply.error(err);
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        let timer = OpTimer::start("create");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "create", "io"=> "in", 
+            "parent" => parent, "name" => ?name, "mode" => mode, "umask" => umask, "flags" => flags);
+
+        let result = self
+            .check_access(parent, req.uid(), req.gid(), libc::W_OK | libc::X_OK)
+            .and_then(|()| utf8_name(name))
+            .and_then(|name| {
+                self.create_entry(parent, &self.entry_name(parent, name), mode & !umask, flags)
+            });
+        match result {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func"; 
+                    "op" => "create", "io"=> "out", "attr" => format!("{:?}", attr), "name" => ?name, "dur_us" => timer.dur_us());
+                let (ttl, generation) = self.entry_reply(&attr);
+                reply.created(&ttl, &attr, generation, 0, 0);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        let _timer = OpTimer::start("unlink");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "unlink", "io"=> "in", 
+            "parent" => parent, "name" => ?name);
+
+        let result = self
+            .check_access(parent, req.uid(), req.gid(), libc::W_OK | libc::X_OK)
+            .and_then(|()| utf8_name(name))
+            .and_then(|name| self.remove_entry(parent, &self.entry_name(parent, name)));
+        match result {
+            Ok(()) => {
+                self.flush_for("unlink");
+                reply.ok();
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+    fn link(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        let timer = OpTimer::start("link");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "link", "io"=> "in",
+            "ino" => ino, "newparent" => newparent, "newname" => ?newname);
+
+        let result = self
+            .check_access(newparent, req.uid(), req.gid(), libc::W_OK | libc::X_OK)
+            .and_then(|()| utf8_name(newname))
+            .and_then(|newname| {
+                self.link_entry(ino, newparent, &self.entry_name(newparent, newname))
+            });
+        match result {
+            Ok(attr) => {
+                debug!(slog_scope::logger(), "Filesystem func";
+                    "op" => "link", "io"=> "out", "attr" => format!("{:?}", attr), "dur_us" => timer.dur_us());
+                let (ttl, generation) = self.entry_reply(&attr);
+                reply.entry(&ttl, &attr, generation);
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _timer = OpTimer::start("rename");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "rename", "io"=> "in",
+            "parent" => parent, "name" => ?name, "newparent" => newparent,
+            "newname" => ?newname, "flags" => flags);
+
+        let write_both = libc::W_OK | libc::X_OK;
+        let result = self
+            .check_access(parent, req.uid(), req.gid(), write_both)
+            .and_then(|()| self.check_access(newparent, req.uid(), req.gid(), write_both))
+            .and_then(|()| Ok((utf8_name(name)?, utf8_name(newname)?)))
+            .and_then(|(name, newname)| {
+                self.rename_entry(
+                    parent,
+                    &self.entry_name(parent, name),
+                    newparent,
+                    &self.entry_name(newparent, newname),
+                    flags,
+                )
+            });
+        match result {
+            Ok(()) => {
+                self.flush_for("rename");
+                reply.ok();
+            }
+            Err(err) => reply.error(err),
+        }
+    }
+    fn copy_file_range(
+        &mut self,
+        req: &Request<'_>,
+        ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        let _timer = OpTimer::start("copy_file_range");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "copy_file_range", "io"=> "in",
+            "ino_in" => ino_in, "fh_in" => fh_in, "offset_in" => offset_in,
+            "ino_out" => ino_out, "fh_out" => fh_out, "offset_out" => offset_out,
+            "len" => len, "flags" => flags);
+
+        let result = self
+            .check_access(ino_out, req.uid(), req.gid(), libc::W_OK)
+            .and_then(|()| self.copy_range(ino_in, offset_in, ino_out, offset_out, len));
+        match result {
+            Ok(copied) => reply.written(copied),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn fallocate(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _timer = OpTimer::start("fallocate");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "fallocate", "io"=> "in", "ino" => ino, "fh" => fh,
+            "offset" => offset, "length" => length, "mode" => mode);
+
+        let result = self
+            .check_access(ino, req.uid(), req.gid(), libc::W_OK)
+            .and_then(|()| self.allocate(ino, offset, length, mode));
+        match result {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn getxattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        let _timer = OpTimer::start("getxattr");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "getxattr", "io"=> "in", "ino" => ino, "name" => ?name, "size" => size);
+
+        match self.xattr(ino, &name.to_string_lossy()) {
+            Ok(value) if size == 0 => reply.size(value.len() as u32),
+            Ok(value) if value.len() > size as usize => reply.error(libc::ERANGE),
+            Ok(value) => reply.data(&value),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn setxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        _position: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _timer = OpTimer::start("setxattr");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "setxattr", "io"=> "in", "ino" => ino, "name" => ?name, "size" => value.len());
+
+        let result = self
+            .check_access(ino, req.uid(), req.gid(), libc::W_OK)
+            .and_then(|()| self.set_xattr(ino, &name.to_string_lossy(), value, flags));
+        match result {
+            Ok(()) => reply.ok(),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        let _timer = OpTimer::start("listxattr");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "listxattr", "io"=> "in", "ino" => ino, "size" => size);
+
+        match self.xattr_names(ino) {
+            Ok(names) if size == 0 => reply.size(names.len() as u32),
+            Ok(names) if names.len() > size as usize => reply.error(libc::ERANGE),
+            Ok(names) => reply.data(&names),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn poll(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        kh: u64,
+        events: u32,
+        flags: u32,
+        reply: fuser::ReplyPoll,
+    ) {
+        let _timer = OpTimer::start("poll");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "poll", "io"=> "in", "ino" => ino, "fh" => fh,
+            "kh" => kh, "events" => events, "flags" => flags);
+
+        match self.poll_events(ino, kh, flags) {
+            Ok(revents) => reply.poll(revents),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn lseek(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: fuser::ReplyLseek,
+    ) {
+        let _timer = OpTimer::start("lseek");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "lseek", "io"=> "in", "ino" => ino, "fh" => fh,
+            "offset" => offset, "whence" => whence);
+
+        match self.seek(ino, offset, whence) {
+            Ok(offset) => reply.offset(offset),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn ioctl(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: u32,
+        cmd: u32,
+        _in_data: &[u8],
+        _out_size: u32,
+        reply: fuser::ReplyIoctl,
+    ) {
+        let _timer = OpTimer::start("ioctl");
+        debug!(slog_scope::logger(), "Filesystem func";
+            "op" => "ioctl", "io"=> "in", "ino" => ino, "fh" => fh,
+            "flags" => flags, "cmd" => cmd);
+
+        if cmd != JSONFS_RELOAD {
+            reply.error(libc::ENOTTY);
+            return;
+        }
+        match self.reload() {
+            Ok(()) => reply.ioctl(0, &[]),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        ctime: Option<std::time::SystemTime>,
+        fh: Option<u64>,
+        crtime: Option<std::time::SystemTime>,
+        _chgtime: Option<std::time::SystemTime>,
+        _bkuptime: Option<std::time::SystemTime>,
+        flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        let _timer = OpTimer::start("setattr");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "setattr", "io"=> "in", 
+            "ino" => ino, "mode" => mode, "uid" => uid, "gid" => gid, "size" => size, 
+            "fh" => fh, "flags" => flags);
+
+        // Times too are part of the document's metadata on a read-only mount.
+        let mut permitted = self.check_writable();
+        if size.is_some() {
+            permitted = self.check_access(ino, req.uid(), req.gid(), libc::W_OK);
+        }
+        if mode.is_some() || uid.is_some() || gid.is_some() {
+            permitted = permitted.and_then(|()| self.check_owner(ino, req.uid()));
+        }
+        let result = permitted
+            .and_then(|()| self.set_attr(ino, mode, uid, gid, size, atime, mtime))
+            .and_then(|_| self.set_change_times(ino, ctime, crtime));
+        match result {
+            Ok(attr) => reply.attr(&self.options.attr_ttl, &attr),
+            Err(err) => reply.error(err),
+        }
+    }
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let timer = OpTimer::start("flush");
+        debug!(slog_scope::logger(), "Filesystem func"; 
+            "op" => "flush", "io"=> "in", 
+            "ino" => ino, "fh" => _fh, "lock_owner" => _lock_owner);
+
+        if ino == DOCUMENT_INO {
+            if let Err(err) = self.commit_document() {
+                warn!(slog_scope::logger(), "Filesystem func";
+                    "op" => "flush", "io"=> "out", "error" => "document is not valid JSON", "dur_us" => timer.dur_us());
+                reply.error(err);
+                return;
+            }
+        }
+        if let Err(err) = self.myflush() {
+            reply.error(err);
+            return;
+        }
+
+        reply.ok();
+        debug!(slog_scope::logger(), "Filesystem func"; "op" => "flush", "io"=> "out", "dur_us" => timer.dur_us());
+    }
+}
+
+/// A `JsonFS` behind a lock, so a background thread can save it while the
+/// session owns the filesystem. With a non-zero `flush_interval` that thread
+/// writes the document whenever it changed since the last save.
+pub struct SharedFS {
+    fs: Arc<Mutex<JsonFS>>,
+    /// Dropping the sender stops the autosave thread.
+    autosave: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl SharedFS {
+    pub fn new(fs: JsonFS, flush_interval: Duration) -> SharedFS {
+        let fs = Arc::new(Mutex::new(fs));
+        let autosave = (!flush_interval.is_zero()).then(|| {
+            let (stop, stopped) = mpsc::channel::<()>();
+            let shared = Arc::clone(&fs);
+            let thread = thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(flush_interval) {
+                    lock(&shared).flush_for("autosave");
+                }
+            });
+            (stop, thread)
+        });
+        SharedFS { fs, autosave }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JsonFS> {
+        lock(&self.fs)
+    }
+
+    /// See `JsonFS::snapshot`; safe to call while the session runs.
+    #[allow(dead_code)]
+    pub(crate) fn snapshot(&self) -> Value {
+        self.lock().snapshot()
+    }
+
+    /// See `JsonFS::export_string`.
+    #[allow(dead_code)]
+    pub(crate) fn export_string(&self, pretty: bool) -> String {
+        self.lock().export_string(pretty)
+    }
+}
+
+/// A handler that panicked leaves the tree as consistent as it would be
+/// without the lock, so a poisoned lock is still used.
+fn lock(fs: &Mutex<JsonFS>) -> MutexGuard<'_, JsonFS> {
+    fs.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Filesystem for SharedFS {
+    fn init(&mut self, req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+        self.lock().init(req, config)
+    }
+
+    /// Saves once more and stops the autosave thread; nothing is written
+    /// twice, as the final save leaves the document clean.
+    fn destroy(&mut self) {
+        self.lock().destroy();
+        if let Some((stop, thread)) = self.autosave.take() {
+            drop(stop);
+            let _ = thread.join();
+        }
+    }
+
+    fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        self.lock().lookup(req, parent, name, reply)
+    }
+
+    fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
+        self.lock().getattr(req, ino, reply)
+    }
+
+    fn read(
+        &mut self,
+        req: &Request,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        self.lock()
+            .read(req, ino, fh, offset, size, flags, lock_owner, reply)
+    }
+
+    fn readdir(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+        self.lock().readdir(req, ino, fh, offset, reply)
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        self.lock().opendir(req, ino, flags, reply)
+    }
+
+    fn releasedir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.lock().releasedir(req, ino, fh, flags, reply)
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: ReplyEntry,
+    ) {
+        self.lock().mkdir(req, parent, name, mode, umask, reply)
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        write_flags: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        self.lock().write(
+            req,
+            ino,
+            fh,
+            offset,
+            data,
+            write_flags,
+            flags,
+            lock_owner,
+            reply,
+        )
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: ReplyCreate,
+    ) {
+        self.lock()
+            .create(req, parent, name, mode, umask, flags, reply)
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        self.lock().unlink(req, parent, name, reply)
+    }
+
+    fn link(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        newparent: u64,
+        newname: &OsStr,
+        reply: ReplyEntry,
+    ) {
+        self.lock().link(req, ino, newparent, newname, reply)
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.lock()
+            .rename(req, parent, name, newparent, newname, flags, reply)
+    }
+
+    fn copy_file_range(
+        &mut self,
+        req: &Request<'_>,
+        ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        flags: u32,
+        reply: ReplyWrite,
+    ) {
+        self.lock().copy_file_range(
+            req, ino_in, fh_in, offset_in, ino_out, fh_out, offset_out, len, flags, reply,
+        )
+    }
+
+    fn fallocate(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        length: i64,
+        mode: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.lock()
+            .fallocate(req, ino, fh, offset, length, mode, reply)
+    }
+
+    fn getxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        self.lock().getxattr(req, ino, name, size, reply)
+    }
+
+    fn setxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        value: &[u8],
+        flags: i32,
+        position: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.lock()
+            .setxattr(req, ino, name, value, flags, position, reply)
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        self.lock().listxattr(req, ino, size, reply)
+    }
+
+    fn poll(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        kh: u64,
+        events: u32,
+        flags: u32,
+        reply: fuser::ReplyPoll,
+    ) {
+        self.lock().poll(req, ino, fh, kh, events, flags, reply)
+    }
+
+    fn lseek(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        whence: i32,
+        reply: fuser::ReplyLseek,
+    ) {
+        self.lock().lseek(req, ino, fh, offset, whence, reply)
+    }
+
+    fn ioctl(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        out_size: u32,
+        reply: fuser::ReplyIoctl,
+    ) {
+        self.lock()
+            .ioctl(req, ino, fh, flags, cmd, in_data, out_size, reply)
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<TimeOrNow>,
+        mtime: Option<TimeOrNow>,
+        ctime: Option<std::time::SystemTime>,
+        fh: Option<u64>,
+        crtime: Option<std::time::SystemTime>,
+        chgtime: Option<std::time::SystemTime>,
+        bkuptime: Option<std::time::SystemTime>,
+        flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        self.lock().setattr(
+            req, ino, mode, uid, gid, size, atime, mtime, ctime, fh, crtime, chgtime, bkuptime,
+            flags, reply,
+        )
+    }
+
+    fn flush(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.lock().flush(req, ino, fh, lock_owner, reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {}
+
+    /// A JSON file in the temporary directory, removed again when dropped.
+    struct TempJson(PathBuf);
+
+    impl std::ops::Deref for TempJson {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempJson {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempJson {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn temp_json(name: &str, content: &str) -> TempJson {
+        let path =
+            std::env::temp_dir().join(format!("jsonfs-{}-{}.json", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        TempJson(path)
+    }
+
+    fn child_ino(fs: &JsonFS, parent: u64, name: &str) -> u64 {
+        fs.ino_of(parent, name).unwrap()
+    }
+
+    #[test]
+    fn write_rejects_non_utf8() {
+        let path = temp_json("non_utf8", r#"{"a": "hello"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let ino = child_ino(&fs, FUSE_ROOT_ID, "a");
+
+        assert_eq!(fs.write_data(ino, 0, &[0xFF, 0xFE]), Err(libc::EINVAL));
+        assert_eq!(fs.json, serde_json::json!({"a": "hello"}));
+    }
+
+    #[test]
+    fn write_to_directory_is_eisdir() {
+        let path = temp_json("write_dir", r#"{"obj": {"k": 1}, "arr": [1, 2]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        for name in ["obj", "arr"] {
+            let ino = child_ino(&fs, FUSE_ROOT_ID, name);
+            assert_eq!(fs.write_data(ino, 0, b"oops"), Err(libc::EISDIR));
+        }
+        assert_eq!(fs.write_data(FUSE_ROOT_ID, 0, b"oops"), Err(libc::EISDIR));
+        assert_eq!(fs.json, serde_json::json!({"obj": {"k": 1}, "arr": [1, 2]}));
+    }
+
+    #[test]
+    fn registry_tracks_parent_and_name() {
+        let path = temp_json("registry", r#"{"a": {"b": [1, 2]}}"#);
+        let fs = JsonFS::new(&path).unwrap();
+
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        assert_eq!(fs.ino2inode[&a].parent, FUSE_ROOT_ID);
+        assert_eq!(fs.ino2inode[&a].name, "a");
+
+        let b = child_ino(&fs, a, "b");
+        let second = child_ino(&fs, b, "1");
+        assert_eq!(fs.ino2inode[&second].parent, b);
+        assert_eq!(fs.ino2inode[&second].name, "1");
+    }
+
+    #[test]
+    fn registry_follows_mutations() {
+        let path = temp_json("registry_mutations", r#"{"arr": ["x", "y", "z"]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+
+        let dir = fs.make_dir(FUSE_ROOT_ID, "dir", 0o755).unwrap();
+        assert_eq!(fs.ino2inode[&dir.ino].parent, FUSE_ROOT_ID);
+        assert_eq!(fs.ino2inode[&dir.ino].name, "dir");
+
+        let file = fs.create_file(dir.ino, "f", 0o644).unwrap();
+        assert_eq!(fs.ino2inode[&file.ino].parent, dir.ino);
+        assert_eq!(fs.ino2inode[&file.ino].name, "f");
+
+        let (x, y, z) = (
+            child_ino(&fs, arr, "0"),
+            child_ino(&fs, arr, "1"),
+            child_ino(&fs, arr, "2"),
+        );
+        fs.set_attr(z, Some(0o600), None, None, None, None, None)
+            .unwrap();
+        fs.remove_entry(arr, "0").unwrap();
+        let first = child_ino(&fs, arr, "0");
+        assert_eq!(first, y);
+        assert_eq!(fs.ino2inode[&first].name, "0");
+        assert_eq!(fs.get_node(first).unwrap(), "y");
+        assert_eq!(fs.ino2inode.values().filter(|i| i.parent == arr).count(), 2);
+        // The removed element's inode is gone; the others keep their metadata.
+        assert_eq!(fs.read_data(x, 0, 16), Err(libc::ESTALE));
+        assert_eq!(child_ino(&fs, arr, "1"), z);
+        assert_eq!(attr_of(&fs, z).perm, 0o600);
+    }
+
+    #[test]
+    fn directory_sizes_are_cached() {
+        let path = temp_json(
+            "sizes",
+            r#"{"a": {"b": "x\"y", "c": [1, 2.50, null]}, "k\u00e9y": true, "e": {}, "f": []}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let c = child_ino(&fs, a, "c");
+        let check = |fs: &JsonFS| {
+            for ino in [FUSE_ROOT_ID, a, c] {
+                let value = fs.get_node(ino).unwrap();
+                assert_eq!(attr_of(fs, ino).size, value.to_string().len() as u64);
+            }
+            let e = child_ino(fs, FUSE_ROOT_ID, "e");
+            assert_eq!(attr_of(fs, e).size, 2);
+        };
+        check(&fs);
+
+        // Served from the cache, not serialized again.
+        fs.sizes.borrow_mut().insert(a, 12345);
+        assert_eq!(attr_of(&fs, a).size, 12345);
+
+        // Changes below, above or within a node drop what they make stale.
+        fs.write_data(child_ino(&fs, a, "b"), 0, b"longer").unwrap();
+        check(&fs);
+        fs.remove_entry(c, "0").unwrap();
+        check(&fs);
+        fs.make_dir(c, "2", 0o755).unwrap();
+        fs.create_file(FUSE_ROOT_ID, "new", 0o644).unwrap();
+        check(&fs);
+        fs.set_xattr(a, JSON_XATTR, br#"{"z": [[]]}"#, 0).unwrap();
+        assert_eq!(attr_of(&fs, a).size, 10);
+        assert_eq!(
+            attr_of(&fs, FUSE_ROOT_ID).size,
+            fs.json.to_string().len() as u64
+        );
+    }
+
+    #[test]
+    fn nodes_belong_to_the_configured_owner() {
+        let path = temp_json("owner", r#"{"a": {"b": 1}}"#);
+        let options = Options {
+            uid: 1000,
+            gid: 1001,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, a, "b");
+        for ino in [FUSE_ROOT_ID, DOCUMENT_INO, a, b, a | LEN_INO] {
+            let attr = fs.get_attr(ino).unwrap();
+            assert_eq!((attr.uid, attr.gid), (1000, 1001));
+        }
+        let created = fs.create_file(a, "c", 0o644).unwrap();
+        assert_eq!((created.uid, created.gid), (1000, 1001));
+
+        // chown still wins.
+        fs.set_attr(b, None, Some(0), None, None, None, None)
+            .unwrap();
+        assert_eq!((attr_of(&fs, b).uid, attr_of(&fs, b).gid), (0, 1001));
+
+        drop(fs);
+        let fs = JsonFS::new(&path).unwrap();
+        assert_eq!(attr_of(&fs, FUSE_ROOT_ID).uid, unsafe { libc::getuid() });
+    }
+
+    #[test]
+    fn directory_nlink_counts_subdirectories() {
+        let path = temp_json("nlink", r#"{"a": {}, "b": [], "c": 1}"#);
+        let fs = JsonFS::new(&path).unwrap();
+
+        assert_eq!(attr_of(&fs, FUSE_ROOT_ID).nlink, 4);
+        let c = child_ino(&fs, FUSE_ROOT_ID, "c");
+        assert_eq!(attr_of(&fs, c).nlink, 1);
+    }
+
+    #[test]
+    fn link_copies_value() {
+        let path = temp_json("link", r#"{"a": "hello", "d": {}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+
+        let attr = fs.link_entry(a, d, "b").unwrap();
+        assert_eq!(attr.nlink, 1);
+        assert_eq!(fs.link_entry(a, FUSE_ROOT_ID, "d"), Err(libc::EEXIST));
+
+        let b = child_ino(&fs, d, "b");
+        fs.set_attr(b, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(b, 0, b"bye").unwrap();
+        assert_eq!(
+            fs.json,
+            serde_json::json!({"a": "hello", "d": {"b": "bye"}})
+        );
+    }
+
+    #[test]
+    fn new_reports_missing_file() {
+        let path = std::env::temp_dir().join("jsonfs-does-not-exist.json");
+        match JsonFS::new(&path) {
+            Err(JsonFsError::Io { path: err_path, .. }) => assert_eq!(err_path, path),
+            _ => panic!("expected an io error"),
+        }
+    }
+
+    #[test]
+    fn new_reports_parse_position() {
+        let path = temp_json("invalid", "{\n  \"a\": 1,\n}");
+        match JsonFS::new(&path) {
+            Err(JsonFsError::Parse { line, column, .. }) => assert_eq!((line, column), (3, 1)),
+            _ => panic!("expected a parse error"),
+        }
+
+        let path = temp_json(
+            "invalid_context",
+            "{\n  \"a\": 1,\n  \"b\": [1 2],\n  \"c\": 3\n}",
+        );
+        let err = JsonFS::new(&path).err().unwrap();
+        assert!(matches!(
+            err,
+            JsonFsError::Parse {
+                line: 3,
+                column: 11,
+                ..
+            }
+        ));
+        let shown = err.to_string();
+        let (message, context) = shown.split_once('\n').unwrap();
+        assert!(
+            message.ends_with("at line 3 column 11: expected `,` or `]`"),
+            "{}",
+            message
+        );
+        assert_eq!(
+            context,
+            "1 | {\n2 |   \"a\": 1,\n3 |   \"b\": [1 2],\n  |           ^"
+        );
+    }
+
+    #[test]
+    fn scalar_documents_mount_wrapped() {
+        let path = temp_json("scalar_number", "42");
+        match JsonFS::new(&path) {
+            Err(err @ JsonFsError::ScalarRoot { .. }) => {
+                assert!(err.to_string().contains("--wrap-scalar"))
+            }
+            _ => panic!("expected a scalar root error"),
+        }
+
+        let options = Options {
+            wrap_scalar: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        assert_eq!(attr_of(&fs, FUSE_ROOT_ID).kind, FileType::Directory);
+        let value = child_ino(&fs, FUSE_ROOT_ID, "value");
+        assert_eq!(fs.read_data(value, 0, 16).unwrap(), b"42");
+        assert_eq!(fs.document_bytes(), b"42");
+        fs.write_data(value, 0, b"43").unwrap();
+        fs.myflush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "43");
+
+        let path = temp_json("scalar_string", r#""hi""#);
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let value = child_ino(&fs, FUSE_ROOT_ID, "value");
+        assert_eq!(fs.read_data(value, 0, 16).unwrap(), b"hi");
+        // Once the root holds more than the scalar, it's saved as an object.
+        fs.create_file(FUSE_ROOT_ID, "other", 0o644).unwrap();
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!({"value": "hi", "other": ""}));
+
+        let options = Options {
+            root_pointer: "/a".to_string(),
+            wrap_scalar: true,
+            ..Default::default()
+        };
+        let path = temp_json("scalar_pointer", r#"{"a": 1}"#);
+        assert!(matches!(
+            JsonFS::with_options(&path, options),
+            Err(JsonFsError::ScalarRoot { .. })
+        ));
+    }
+
+    #[test]
+    fn lenient_accepts_comments_and_trailing_commas() {
+        let data = "{\n  // the port\n  \"port\": 80, /* not \"//\" */\n  \"url\": \"http://x/*y*/\",\n  \"list\": [1, 2,],\n}\n";
+        let path = temp_json("lenient", data);
+        assert!(matches!(JsonFS::new(&path), Err(JsonFsError::Parse { .. })));
+
+        let options = Options {
+            lenient: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        assert_eq!(
+            fs.json,
+            serde_json::json!({"port": 80, "url": "http://x/*y*/", "list": [1, 2]})
+        );
+        fs.dirty = true;
+        fs.myflush().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(serde_json::from_str::<Value>(&saved).is_ok());
+
+        // Errors still point into the original text.
+        let path = temp_json("lenient_error", "{\n  // note\n  \"a\": 1,\n  \"b\": @\n}");
+        match JsonFS::with_options(&path, options) {
+            Err(JsonFsError::Parse { line, column, .. }) => assert_eq!((line, column), (4, 8)),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn gzip_documents_round_trip() {
+        let path = std::env::temp_dir().join(format!("jsonfs-{}-gzip.json.gz", std::process::id()));
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(br#"{"a": "x"}"#).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let read_back = |path: &Path| {
+            let mut text = String::new();
+            GzDecoder::new(&fs::read(path).unwrap()[..])
+                .read_to_string(&mut text)
+                .unwrap();
+            serde_json::from_str::<Value>(&text).unwrap()
+        };
+
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        assert_eq!(fs.read_data(a, 0, 16).unwrap(), b"x");
+        fs.write_data(a, 1, b"yz").unwrap();
+        fs.myflush().unwrap();
+        assert_eq!(read_back(&path), serde_json::json!({"a": "xyz"}));
+        assert!(!path
+            .with_file_name(format!(
+                ".{}.jsonfs-tmp",
+                path.file_name().unwrap().to_string_lossy()
+            ))
+            .exists());
+
+        // `--gzip` for a file whose name doesn't say so.
+        let plain = temp_json("gzip_flag", "");
+        fs::copy(&path, &plain).unwrap();
+        assert!(matches!(JsonFS::new(&plain), Err(JsonFsError::Io { .. })));
+        let options = Options {
+            gzip: true,
+            ..Default::default()
+        };
+        assert!(JsonFS::with_options(&plain, options.clone()).is_ok());
+
+        fs::write(&plain, r#"{"a": 1}"#).unwrap();
+        assert!(matches!(
+            JsonFS::with_options(&plain, options),
+            Err(JsonFsError::Decompress { .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_option_starts_from_empty_object() {
+        let path = std::env::temp_dir().join(format!("jsonfs-{}-create.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(JsonFS::new(&path).is_err());
+
+        let options = Options {
+            create: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        assert_eq!(fs.json, serde_json::json!({}));
+        assert!(!path.exists());
+
+        let attr = fs.create_file(FUSE_ROOT_ID, "name", 0o644).unwrap();
+        fs.write_data(attr.ino, 0, b"jsonfs").unwrap();
+        fs.myflush().unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!({"name": "jsonfs"}));
+    }
+
+    #[test]
+    fn destroy_saves_unflushed_changes_once() {
+        let path = temp_json("destroy", r#"{"a": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        // Nothing changed yet: unmounting leaves the file alone.
+        fs::write(&path, r#"{"untouched": true}"#).unwrap();
+        Filesystem::destroy(&mut fs);
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{"untouched": true}"#);
+
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        fs.write_data(a, 1, b"yz").unwrap();
+        Filesystem::destroy(&mut fs);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!({"a": "xyz"}));
+
+        fs::write(&path, "{}").unwrap();
+        Filesystem::destroy(&mut fs);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn flush_interval_saves_in_the_background() {
+        let path = temp_json("flush_interval", r#"{"a": "x"}"#);
+        let fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let mut shared = SharedFS::new(fs, Duration::from_millis(20));
+
+        shared.lock().write_data(a, 1, b"yz").unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!({"a": "xyz"}));
+
+        // Already saved: unmounting doesn't write again, and the thread is gone.
+        fs::write(&path, "{}").unwrap();
+        Filesystem::destroy(&mut shared);
+        assert!(shared.autosave.is_none());
+        assert_eq!(Arc::strong_count(&shared.fs), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+
+        let path = temp_json("flush_interval_zero", r#"{"a": "x"}"#);
+        let shared = SharedFS::new(JsonFS::new(&path).unwrap(), Duration::ZERO);
+        assert!(shared.autosave.is_none());
+    }
+
+    #[test]
+    fn backup_keeps_the_original_file() {
+        let original = r#"{"a": "x"}"#;
+        let path = temp_json("backup", original);
+        let backup = path.with_extension("json.bak");
+        let _ = fs::remove_file(&backup);
+        let options = Options {
+            backup: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+
+        fs.write_data(a, 0, b"y").unwrap();
+        fs.myflush().unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        assert_eq!(fs.json["a"], "y");
+        fs.write_data(a, 0, b"z").unwrap();
+        fs.myflush().unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+
+        // Without the option nothing is copied.
+        fs::remove_file(&backup).unwrap();
+        drop(fs);
+        let mut fs = JsonFS::new(&path).unwrap();
+        fs.write_data(a, 0, b"w").unwrap();
+        fs.myflush().unwrap();
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn yaml_documents_round_trip() {
+        let path = temp_json("yaml", "").with_extension("yaml");
+        fs::write(
+            &path,
+            "base: &base\n  name: x\n  tags: [a, b]\nother:\n  <<: *base\n  name: y\nnothing: null\n",
+        )
+        .unwrap();
+        let mut fs = JsonFS::new(&path).unwrap();
+        // The alias is expanded and the merge key applied.
+        assert_eq!(
+            fs.json,
+            serde_json::json!({
+                "base": {"name": "x", "tags": ["a", "b"]},
+                "other": {"name": "y", "tags": ["a", "b"]},
+                "nothing": null
+            })
+        );
+        let name = child_ino(&fs, child_ino(&fs, FUSE_ROOT_ID, "base"), "name");
+        fs.write_data(name, 0, b"z").unwrap();
+        fs.myflush().unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains('&') && !saved.contains('{'), "{}", saved);
+        assert_eq!(Format::Yaml.parse(&saved).unwrap(), fs.json);
+        drop(fs);
+        assert_eq!(JsonFS::new(&path).unwrap().json["base"]["name"], "z");
+    }
+
+    #[test]
+    fn toml_documents_round_trip() {
+        let path = temp_json("toml", "").with_extension("toml");
+        fs::write(
+            &path,
+            "title = \"x\"\nwhen = 1979-05-27T07:32:00Z\n\n[server]\nports = [80, 443]\nratio = 0.5\n",
+        )
+        .unwrap();
+        let mut fs = JsonFS::new(&path).unwrap();
+        assert_eq!(
+            fs.json,
+            serde_json::json!({
+                "title": "x",
+                "when": "1979-05-27T07:32:00Z",
+                "server": {"ports": [80, 443], "ratio": 0.5}
+            })
+        );
+        let title = child_ino(&fs, FUSE_ROOT_ID, "title");
+        fs.write_data(title, 0, b"y").unwrap();
+        // TOML has no null, so one is left out of the file.
+        fs.json["gone"] = Value::Null;
+        fs.dirty = true;
+        fs.myflush().unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("[server]"), "{}", saved);
+        let mut expected = fs.json.clone();
+        expected.as_object_mut().unwrap().remove("gone");
+        assert_eq!(Format::Toml.parse(&saved).unwrap(), expected);
+
+        // A root that isn't a table can't be saved.
+        fs.json = serde_json::json!([1]);
+        fs.dirty = true;
+        assert_eq!(fs.myflush(), Err(libc::EINVAL));
+        assert!(fs.dirty);
+    }
+
+    #[test]
+    fn format_follows_the_extension_unless_set() {
+        let options = Options::default();
+        assert_eq!(format_of(Path::new("a.json"), &options), Format::Json);
+        assert_eq!(format_of(Path::new("a.yml"), &options), Format::Yaml);
+        assert_eq!(format_of(Path::new("a.toml.gz"), &options), Format::Toml);
+        assert_eq!(format_of(Path::new("a"), &options), Format::Json);
+        let options = Options {
+            format: Some(Format::Yaml),
+            ..Default::default()
+        };
+        assert_eq!(format_of(Path::new("a.json"), &options), Format::Yaml);
+
+        let path = temp_json("bad_yaml", "").with_extension("yaml");
+        fs::write(&path, "a: [1\n").unwrap();
+        let err = JsonFS::new(&path).err().unwrap().to_string();
+        assert!(err.contains("as YAML"), "{}", err);
+    }
+
+    #[test]
+    fn navigation_stops_at_max_depth() {
+        let depth = 10;
+        let nested = format!("{}\"leaf\"{}", "[".repeat(depth), "]".repeat(depth));
+        let path = temp_json("max_depth", &nested);
+        let options = Options {
+            max_depth: 4,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let mut ino = FUSE_ROOT_ID;
+        for _ in 0..4 {
+            ino = fs.lookup_entry(ino, "0").unwrap().ino;
+        }
+        assert_eq!(fs.lookup_entry(ino, "0").err(), Some(libc::ENAMETOOLONG));
+        // Nor can the tree be grown past the limit.
+        let parent = fs.lookup_entry(FUSE_ROOT_ID, "0").unwrap().ino;
+        let parent = fs.lookup_entry(parent, "0").unwrap().ino;
+        let parent = fs.lookup_entry(parent, "0").unwrap().ino;
+        let dir = fs.make_dir(parent, "1", 0o755).unwrap().ino;
+        assert_eq!(fs.make_dir(dir, "x", 0o755).err(), Some(libc::ENAMETOOLONG));
+        assert_eq!(
+            fs.create_file(dir, "x", 0o644).err(),
+            Some(libc::ENAMETOOLONG)
+        );
+        // The limit is on lookups, not on the document.
+        assert_eq!(Summary::of(&fs.json).max_depth, depth);
+    }
+
+    #[test]
+    fn rename_checks_the_depth_of_the_whole_subtree() {
+        let path = temp_json("rename_depth", r#"{"a": {"b": {"c": {}}}, "t": {"x": 1}}"#);
+        let options = Options {
+            max_depth: 4,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, a, "b");
+        let c = child_ino(&fs, b, "c");
+
+        // `t` itself would fit under `c`, but `t/x` would not.
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "t", c, "t", 0),
+            Err(libc::ENAMETOOLONG)
+        );
+        fs.rename_entry(FUSE_ROOT_ID, "t", b, "t", 0).unwrap();
+        assert_eq!(fs.json["a"]["b"]["t"]["x"], 1);
+        drop(fs);
+
+        // A tree grown to the default limit still loads.
+        let mut fs = JsonFS::new(&path).unwrap();
+        let mut dir = FUSE_ROOT_ID;
+        let mut depth = 0;
+        while let Ok(attr) = fs.make_dir(dir, "d", 0o755) {
+            dir = attr.ino;
+            depth += 1;
+        }
+        assert_eq!(depth, DEFAULT_MAX_DEPTH);
+        fs.myflush().unwrap();
+        drop(fs);
+        JsonFS::new(&path).unwrap();
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn no_flush_keeps_changes_in_memory() {
+        let original = r#"{"a": "x"}"#;
+        let path = temp_json("no_flush", original);
+        let options = Options {
+            no_flush: true,
+            backup: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        fs.write_data(a, 0, b"y").unwrap();
+        fs.create_file(FUSE_ROOT_ID, "b", 0o644).unwrap();
+        fs.myflush().unwrap();
+
+        assert_eq!(fs.read_data(a, 0, 16).unwrap(), b"y");
+        assert!(fs.lookup_entry(FUSE_ROOT_ID, "b").is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!path.with_extension("json.bak").exists());
+        // Nor does it keep a saving mount out.
+        assert!(JsonFS::new(&path).is_ok());
+    }
+
+    #[test]
+    fn schema_gates_saving() {
+        let schema = temp_json(
+            "schema_file",
+            r#"{"type": "object", "properties": {"port": {"type": "integer", "maximum": 65535}}}"#,
+        );
+        let original = r#"{"port": 80}"#;
+        let path = temp_json("schema", original);
+        let options = Options {
+            schema: Some(schema.to_path_buf()),
+            backup: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        let port = child_ino(&fs, FUSE_ROOT_ID, "port");
+        let mut backup = fs::canonicalize(&path).unwrap().into_os_string();
+        backup.push(".bak");
+
+        // A string where an integer belongs is kept in memory, not saved, and
+        // nothing is backed up for it.
+        fs.set_attr(port, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(port, 0, b"http").unwrap();
+        assert_eq!(fs.myflush(), Err(libc::EINVAL));
+        assert!(fs.dirty);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!Path::new(&backup).exists());
+
+        fs.set_attr(port, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(port, 0, b"8080").unwrap();
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!({"port": 8080}));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        fs::remove_file(&backup).unwrap();
+
+        // Past the maximum.
+        fs.write_data(port, 0, b"99999").unwrap();
+        assert_eq!(fs.json["port"], 99999);
+        assert_eq!(fs.myflush(), Err(libc::EINVAL));
+        // Where no reply can carry the error, it is at least logged.
+        let messages = Arc::new(Mutex::new(vec![]));
+        let logger = slog::Logger::root(slog::Fuse(Messages(messages.clone())), slog::o!());
+        slog_scope::scope(&logger, || Filesystem::destroy(&mut fs));
+        assert!(messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|msg| msg == "changes not saved"));
+        drop(fs);
+
+        fs::write(&schema, r#"{"type": 12}"#).unwrap();
+        assert!(matches!(
+            JsonFS::with_options(&path, options),
+            Err(JsonFsError::Schema { .. })
+        ));
+    }
+
+    #[test]
+    fn overlay_saves_only_the_overrides() {
+        let base_text = r#"{"base": 1, "both": {"x": 1, "y": 2}, "list": [1, 2]}"#;
+        let path = temp_json("overlay_base", base_text);
+        let overrides = temp_json(
+            "overlay_overrides",
+            r#"{"over": "o", "both": {"y": 20, "z": 30}, "list": [9]}"#,
+        );
+        let options = Options {
+            overlay: Some(overrides.to_path_buf()),
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        let read =
+            |fs: &JsonFS, parent, name| fs.read_data(child_ino(fs, parent, name), 0, 64).unwrap();
+        let both = child_ino(&fs, FUSE_ROOT_ID, "both");
+
+        // Only in the base, only in the overrides, and merged from both.
+        assert_eq!(read(&fs, FUSE_ROOT_ID, "base"), b"1");
+        assert_eq!(read(&fs, FUSE_ROOT_ID, "over"), b"o");
+        assert_eq!(read(&fs, both, "x"), b"1");
+        assert_eq!(read(&fs, both, "y"), b"20");
+        assert_eq!(read(&fs, both, "z"), b"30");
+        assert_eq!(fs.json["list"], serde_json::json!([9]));
+
+        fs.write_data(child_ino(&fs, FUSE_ROOT_ID, "base"), 0, b"5")
+            .unwrap();
+        fs.remove_entry(both, "x").unwrap();
+        fs.create_file(FUSE_ROOT_ID, "new", 0o644).unwrap();
+        fs.myflush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), base_text);
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&overrides).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({
+                "base": 5,
+                "over": "o",
+                "both": {"x": null, "y": 20, "z": 30},
+                "list": [9],
+                "new": "",
+            })
+        );
+        let merged = fs.json.clone();
+        drop(fs);
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+        assert_eq!(fs.json, merged);
+
+        // An override set back to the base's value stays an override, and a
+        // `null` is not a removal.
+        let both = child_ino(&fs, FUSE_ROOT_ID, "both");
+        let y = child_ino(&fs, both, "y");
+        fs.truncate_node(y, 0).unwrap();
+        fs.write_data(y, 0, b"2").unwrap();
+        let new = child_ino(&fs, FUSE_ROOT_ID, "new");
+        fs.write_data(new, 0, b"null").unwrap();
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&overrides).unwrap()).unwrap();
+        assert_eq!(saved["both"]["y"], 2);
+        assert_eq!(saved["new"], overlay::null_value());
+        let merged = fs.json.clone();
+        drop(fs);
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        assert_eq!(fs.json, merged);
+        assert_eq!(fs.json["new"], Value::Null);
+
+        // Edits to the base show through where nothing overrides them; `y`
+        // is still overridden.
+        fs::write(
+            &path,
+            r#"{"base": 1, "both": {"x": 1, "y": 7, "w": 0}, "list": []}"#,
+        )
+        .unwrap();
+        fs.reload().unwrap();
+        assert_eq!(
+            fs.json["both"],
+            serde_json::json!({"w": 0, "y": 2, "z": 30})
+        );
+    }
+
+    #[test]
+    fn second_mount_is_refused_or_read_only() {
+        let path = temp_json("lock", r#"{"a": "x"}"#);
+        let fs = JsonFS::new(&path).unwrap();
+        assert!(!fs.read_only());
+        let err = JsonFS::new(&path).err().unwrap();
+        assert!(matches!(err, JsonFsError::Locked { .. }), "{}", err);
+
+        let options = Options {
+            shared: true,
+            ..Default::default()
+        };
+        let shared = JsonFS::with_options(&path, options.clone()).unwrap();
+        assert!(shared.read_only());
+        let a = child_ino(&shared, FUSE_ROOT_ID, "a");
+        assert_eq!(shared.check_access(a, 0, 0, libc::W_OK), Err(libc::EROFS));
+        assert_eq!(
+            shared.check_access(FUSE_ROOT_ID, 0, 0, libc::W_OK | libc::X_OK),
+            Err(libc::EROFS)
+        );
+        assert_eq!(shared.check_owner(a, 0), Err(libc::EROFS));
+        assert_eq!(shared.check_access(a, 0, 0, libc::R_OK), Ok(()));
+
+        // Unmounting releases the lock and removes its sidecar.
+        let lock = sidecar(&fs::canonicalize(&path).unwrap(), "lock");
+        assert!(lock.exists());
+        drop(fs);
+        assert!(!lock.exists());
+        drop(shared);
+        let again = JsonFS::with_options(&path, options).unwrap();
+        assert!(!again.read_only());
+        drop(again);
+        assert!(!lock.exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn saved_document_uses_the_configured_indent() {
+        let path = temp_json("indent", r#"{"a": {"b": 1}}"#);
+        for (indent, expected) in [
+            ("  ", "{\n  \"a\": {\n    \"b\": 1\n  }\n}"),
+            ("    ", "{\n    \"a\": {\n        \"b\": 1\n    }\n}"),
+            ("\t", "{\n\t\"a\": {\n\t\t\"b\": 1\n\t}\n}"),
+        ] {
+            let options = Options {
+                indent: indent.to_string(),
+                ..Default::default()
+            };
+            let mut fs = JsonFS::with_options(&path, options).unwrap();
+            assert_eq!(
+                fs.read_data(DOCUMENT_INO, 0, 4096).unwrap(),
+                expected.as_bytes()
+            );
+            fs.dirty = true;
+            fs.myflush().unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn copy_range_clones_scalar() {
+        let path = temp_json(
+            "copy_range",
+            r#"{"a": "hello", "b": "world", "n": 42, "d": {}}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, FUSE_ROOT_ID, "b");
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+
+        assert_eq!(fs.copy_range(a, 0, b, 0, 1 << 20), Ok(5));
+        assert_eq!(fs.json["b"], "hello");
+
+        assert_eq!(fs.copy_range(n, 0, a, 0, 1 << 20), Ok(2));
+        assert_eq!(fs.json["a"], 42);
+
+        assert_eq!(fs.copy_range(a, 0, d, 0, 2), Err(libc::EINVAL));
+        assert_eq!(fs.json["d"], serde_json::json!({}));
+
+        // Under strict types a whole copy keeps the target's type, as a
+        // write does.
+        let path = temp_json("copy_range_strict", r#"{"s": "x", "n": 1, "m": 2}"#);
+        let options = Options {
+            strict_types: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        let m = child_ino(&fs, FUSE_ROOT_ID, "m");
+
+        assert_eq!(fs.copy_range(s, 0, n, 0, 1 << 20), Err(libc::EINVAL));
+        assert_eq!(fs.json["n"], 1);
+        // A number emptied by `O_TRUNC` takes a number again.
+        fs.truncate_node(n, 0).unwrap();
+        assert_eq!(fs.copy_range(m, 0, n, 0, 1 << 20), Ok(1));
+        assert_eq!(fs.json["n"], 2);
+        assert_eq!(fs.strict_type(n, &fs.json["n"]), Some(StrictType::Number));
+    }
+
+    #[test]
+    fn chmod_and_chown_stick() {
+        let path = temp_json("chmod", r#"{"a": "x", "d": {"f": 1}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+        let f = child_ino(&fs, d, "f");
+
+        let attr = fs
+            .set_attr(a, Some(0o100600), Some(1000), None, None, None, None)
+            .unwrap();
+        assert_eq!(
+            (attr.perm, attr.uid, attr.gid),
+            (0o600, 1000, fs.options.gid)
+        );
+        fs.set_attr(f, Some(0o640), None, None, None, None, None)
+            .unwrap();
+
+        // Survives the registry being refreshed when siblings change.
+        fs.create_file(FUSE_ROOT_ID, "b", 0o644).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let f = child_ino(&fs, child_ino(&fs, FUSE_ROOT_ID, "d"), "f");
+        assert_eq!(attr_of(&fs, a).perm, 0o600);
+        assert_eq!(attr_of(&fs, f).perm, 0o640);
+
+        // A removed node doesn't hand its metadata to a new one.
+        fs.remove_entry(FUSE_ROOT_ID, "a").unwrap();
+        let attr = fs.create_file(FUSE_ROOT_ID, "a", 0o644).unwrap();
+        assert_eq!(attr.perm, 0o644);
+    }
+
+    #[test]
+    fn relatime_updates_atime_only_when_stale() {
+        let path = temp_json("relatime", r#"{"a": "x"}"#);
+        let options = Options {
+            atime: Atime::Relatime,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let atime = |fs: &JsonFS| attr_of(fs, a).atime;
+        let pause = || std::thread::sleep(Duration::from_millis(10));
+
+        // Not later than the mtime yet: the first read counts, the next not.
+        pause();
+        fs.note_access(a);
+        let first = atime(&fs);
+        assert!(first > fs.mount_time);
+        pause();
+        fs.note_access(a);
+        assert_eq!(atime(&fs), first);
+        assert!(!fs.dirty);
+
+        // A write makes it stale again.
+        pause();
+        fs.write_data(a, 0, b"y").unwrap();
+        pause();
+        fs.note_access(a);
+        assert!(atime(&fs) > attr_of(&fs, a).mtime);
+
+        // So does a day passing, even after the last change.
+        let old = SystemTime::now() - RELATIME_INTERVAL * 2;
+        fs.set_attr(
+            a,
+            None,
+            None,
+            None,
+            None,
+            Some(TimeOrNow::SpecificTime(old + RELATIME_INTERVAL / 2)),
+            Some(TimeOrNow::SpecificTime(old)),
+        )
+        .unwrap();
+        fs.meta.get_mut(&a).unwrap().ctime = Some(old);
+        fs.note_access(a);
+        assert!(atime(&fs) > old + RELATIME_INTERVAL);
+
+        // The default never touches it; strict always does.
+        drop(fs);
+        let mut fs = JsonFS::new(&path).unwrap();
+        fs.note_access(a);
+        assert_eq!(atime(&fs), fs.mount_time);
+        fs.options.atime = Atime::Strict;
+        fs.note_access(a);
+        let first = atime(&fs);
+        pause();
+        fs.note_access(a);
+        assert!(atime(&fs) > first);
+    }
+
+    fn attr_of(fs: &JsonFS, ino: u64) -> FileAttr {
+        fs.get_attr(ino).unwrap()
+    }
+
+    #[test]
+    fn write_advances_mtime() {
+        let path = temp_json("mtime", r#"{"a": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let before = attr_of(&fs, a);
+        assert_eq!(before.mtime, fs.mount_time);
+
+        std::thread::sleep(Duration::from_millis(10));
+        fs.write_data(a, 0, b"y").unwrap();
+        let after = attr_of(&fs, a);
+        assert!(after.mtime > before.mtime);
+        assert_eq!(after.atime, before.atime);
+
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let attr = fs
+            .set_attr(
+                a,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(TimeOrNow::SpecificTime(past)),
+            )
+            .unwrap();
+        assert_eq!(attr.mtime, past);
+        let attr = fs.set_change_times(a, Some(past), Some(past)).unwrap();
+        assert_eq!((attr.ctime, attr.crtime), (past, past));
+        // Unnamed times are left as they were.
+        let attr = fs.set_change_times(a, None, None).unwrap();
+        assert_eq!((attr.ctime, attr.crtime, attr.mtime), (past, past, past));
+    }
+
+    #[test]
+    fn create_stamps_new_node_and_parent() {
+        let path = temp_json("crtime", r#"{"d": {}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+
+        std::thread::sleep(Duration::from_millis(10));
+        let attr = fs.create_file(d, "f", 0o644).unwrap();
+        assert!(attr.crtime > fs.mount_time);
+        assert!(attr_of(&fs, d).mtime >= attr.mtime);
+    }
+
+    #[test]
+    fn document_file_round_trip() {
+        let path = temp_json("document", r#"{"a": {"b": 1}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let attr = fs.lookup_entry(FUSE_ROOT_ID, DOCUMENT_NAME).unwrap();
+        let content = fs.read_data(attr.ino, 0, 4096).unwrap();
+        assert_eq!(attr.size, content.len() as u64);
+        let parsed: Value = serde_json::from_slice(&content).unwrap();
+        assert_eq!(parsed, fs.json);
+
+        // Like `cp new.json /mnt/.json`: truncate, write in chunks, close.
+        let new_doc = br#"{"x": [true, "y"]}"#;
+        fs.set_attr(DOCUMENT_INO, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(DOCUMENT_INO, 0, &new_doc[..5]).unwrap();
+        fs.write_data(DOCUMENT_INO, 5, &new_doc[5..]).unwrap();
+        assert_eq!(attr_of(&fs, DOCUMENT_INO).size, new_doc.len() as u64);
+        // Nothing changes before the flush.
+        assert_eq!(fs.json, serde_json::json!({"a": {"b": 1}}));
+        fs.commit_document().unwrap();
+
+        assert_eq!(fs.json, serde_json::json!({"x": [true, "y"]}));
+        let x = fs.lookup_entry(FUSE_ROOT_ID, "x").unwrap();
+        assert_eq!(
+            fs.read_data(child_ino(&fs, x.ino, "1"), 0, 16).unwrap(),
+            b"y"
+        );
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "a"), Err(ENOENT));
+
+        // The rendered size is cached until the tree changes.
+        let size = attr_of(&fs, DOCUMENT_INO).size;
+        assert_eq!(fs.sizes.borrow().get(&DOCUMENT_INO), Some(&size));
+        fs.write_data(child_ino(&fs, x.ino, "1"), 0, b"longer")
+            .unwrap();
+        assert_eq!(attr_of(&fs, DOCUMENT_INO).size, fs.render().len() as u64);
+        assert!(attr_of(&fs, DOCUMENT_INO).size > size);
+    }
+
+    #[test]
+    fn reload_picks_up_out_of_band_edits() {
+        let path = temp_json("reload", r#"{"a": {"b": 1}, "c": 2}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, a, "b");
+
+        fs::write(&path, r#"{"a": {"b": 10}, "d": true}"#).unwrap();
+        fs.reload().unwrap();
+        assert_eq!(child_ino(&fs, FUSE_ROOT_ID, "a"), a);
+        assert_eq!(fs.read_data(b, 0, 16).unwrap(), b"10");
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "c"), Err(ENOENT));
+        assert!(fs.lookup_entry(FUSE_ROOT_ID, "d").is_ok());
+
+        // A broken file is reported and the mounted tree is kept.
+        fs::write(&path, r#"{"a": "#).unwrap();
+        assert_eq!(fs.reload(), Err(libc::EINVAL));
+        assert_eq!(fs.json, serde_json::json!({"a": {"b": 10}, "d": true}));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn handles_survive_rebuilds() {
+        let path = temp_json("rebuild", r#"{"a": {"b": 1}, "c": 2}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, a, "b");
+
+        fs::write(&path, r#"{"c": 3}"#).unwrap();
+        fs.reload().unwrap();
+        assert_eq!(fs.get_attr(b), Err(libc::ESTALE));
+        assert_eq!(fs.get_attr(a), Err(libc::ESTALE));
+        assert_eq!(fs.get_attr(fs.next_ino), Err(ENOENT));
+
+        // Restoring the path brings back the inode the kernel still holds.
+        fs.replace_document(serde_json::json!({"a": {"b": 4}}));
+        assert_eq!(child_ino(&fs, FUSE_ROOT_ID, "a"), a);
+        assert_eq!(fs.get_attr(b).unwrap().ino, b);
+        assert_eq!(fs.read_data(b, 0, 16).unwrap(), b"4");
+    }
+
+    #[test]
+    fn reused_inodes_get_a_new_generation() {
+        let path = temp_json("generation", r#"{"a": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        assert_eq!(fs.generation(a), 0);
+
+        // Reloaded away and back: the same node, the same generation.
+        fs.replace_document(serde_json::json!({}));
+        fs.replace_document(serde_json::json!({"a": "x"}));
+        assert_eq!(child_ino(&fs, FUSE_ROOT_ID, "a"), a);
+        assert_eq!(fs.generation(a), 0);
+
+        // Created anew at the retired path: same id, new generation.
+        fs.replace_document(serde_json::json!({}));
+        let attr = fs.create_file(FUSE_ROOT_ID, "a", 0o644).unwrap();
+        assert_eq!(attr.ino, a);
+        assert_eq!(fs.generation(a), 1);
+        fs.replace_document(serde_json::json!({}));
+        fs.make_dir(FUSE_ROOT_ID, "a", 0o755).unwrap();
+        assert_eq!(fs.generation(a), 2);
+    }
+
+    #[test]
+    fn root_pointer_mounts_a_subtree() {
+        let path = temp_json(
+            "root_pointer",
+            r#"{"services": {"web": {"port": 80}, "db": {"port": 5432}}}"#,
+        );
+        let options = Options {
+            root_pointer: "/services/web".to_string(),
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options.clone()).unwrap();
+
+        let port = fs.lookup_entry(FUSE_ROOT_ID, "port").unwrap();
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "db"), Err(ENOENT));
+        fs.set_attr(port.ino, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(port.ino, 0, b"8080").unwrap();
+        let document = fs.read_data(DOCUMENT_INO, 0, 4096).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&document).unwrap(),
+            serde_json::json!({"port": 8080})
+        );
+
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({"services": {"web": {"port": 8080}, "db": {"port": 5432}}})
+        );
+
+        let options = Options {
+            root_pointer: "/services/mail".to_string(),
+            ..options
+        };
+        drop(fs);
+        let err = JsonFS::with_options(&path, options).err().unwrap();
+        assert!(err.to_string().contains("/services/mail"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn empty_containers_keep_their_kind() {
+        let path = temp_json(
+            "empty_containers",
+            r#"{"o": {}, "p": {}, "a": [], "b": []}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let o = child_ino(&fs, FUSE_ROOT_ID, "o");
+        let p = child_ino(&fs, FUSE_ROOT_ID, "p");
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, FUSE_ROOT_ID, "b");
+
+        fs.create_file(o, "name", 0o644).unwrap();
+        fs.create_file(p, "0", 0o644).unwrap();
+        assert_eq!(fs.create_file(b, "1", 0o644), Err(libc::EINVAL));
+        fs.create_file(a, "0", 0o644).unwrap();
+        assert_eq!(fs.create_file(a, "2", 0o644), Err(libc::EINVAL));
+        assert_eq!(fs.create_file(b, "name", 0o644), Err(libc::EINVAL));
+        assert_eq!(
+            fs.json,
+            serde_json::json!({"o": {"name": ""}, "p": {"0": ""}, "a": [""], "b": []})
+        );
+        // Nor does mkdir retype anything; an array is chosen by writing one.
+        let d = fs.make_dir(FUSE_ROOT_ID, "d", 0o755).unwrap().ino;
+        fs.make_dir(d, "0", 0o755).unwrap();
+        assert_eq!(fs.make_dir(b, "x", 0o755), Err(libc::EINVAL));
+        fs.set_xattr(d, JSON_XATTR, b"[]", 0).unwrap();
+        fs.make_dir(d, "0", 0o755).unwrap();
+        assert_eq!(fs.json["d"], serde_json::json!([{}]));
+        assert_eq!(fs.json["b"], serde_json::json!([]));
+
+        let options = Options {
+            infer_arrays: true,
+            ..Default::default()
+        };
+        drop(fs);
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        fs.create_file(child_ino(&fs, FUSE_ROOT_ID, "p"), "0", 0o644)
+            .unwrap();
+        fs.create_file(child_ino(&fs, FUSE_ROOT_ID, "b"), "name", 0o644)
+            .unwrap();
+        assert_eq!(fs.json["p"], serde_json::json!([""]));
+        assert_eq!(fs.json["b"], serde_json::json!({"name": ""}));
+    }
+
+    #[test]
+    fn malformed_document_write_is_rejected() {
+        let path = temp_json("document_bad", r#"{"a": 1}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        fs.set_attr(DOCUMENT_INO, None, None, None, Some(0), None, None)
+            .unwrap();
+        // A first chunk that parses on its own replaces nothing.
+        fs.write_data(DOCUMENT_INO, 0, b"[1]").unwrap();
+        assert_eq!(fs.json, serde_json::json!({"a": 1}));
+        fs.write_data(DOCUMENT_INO, 3, b", 2]").unwrap();
+        assert_eq!(fs.commit_document(), Err(libc::EINVAL));
+
+        assert_eq!(fs.json, serde_json::json!({"a": 1}));
+        let content = fs.read_data(DOCUMENT_INO, 0, 4096).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&content).unwrap(), fs.json);
+    }
+
+    #[test]
+    fn numbers_keep_their_text() {
+        let path = temp_json(
+            "numbers",
+            r#"{"float": 1.0, "big": 123456789012345678901, "n": 0}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let float = child_ino(&fs, FUSE_ROOT_ID, "float");
+        assert_eq!(fs.read_data(float, 0, 64).unwrap(), b"1.0");
+        let big = child_ino(&fs, FUSE_ROOT_ID, "big");
+        assert_eq!(fs.read_data(big, 0, 64).unwrap(), b"123456789012345678901");
+
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        for text in ["-5", "1.0", "98765432109876543210"] {
+            fs.write_data(n, 0, text.as_bytes()).unwrap();
+            assert!(fs.json["n"].is_number(), "{} should stay a number", text);
+            assert_eq!(fs.read_data(n, 0, 64).unwrap(), text.as_bytes());
+        }
+        assert!(fs.render().contains("\"n\": 98765432109876543210"));
+    }
+
+    #[test]
+    fn humanized_numbers_round_trip() {
+        let path = temp_json(
+            "humanize",
+            r#"{"big": 1000000, "neg": -1234.5, "small": 12, "s": "1,000"}"#,
+        );
+        let options = Options {
+            humanize: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let read = |fs: &JsonFS, name| {
+            let ino = child_ino(fs, FUSE_ROOT_ID, name);
+            String::from_utf8(fs.read_data(ino, 0, 64).unwrap()).unwrap()
+        };
+        let big = child_ino(&fs, FUSE_ROOT_ID, "big");
+
+        assert_eq!(read(&fs, "big"), "1,000,000");
+        assert_eq!(attr_of(&fs, big).size, 9);
+        assert_eq!(read(&fs, "neg"), "-1,234.5");
+        assert_eq!(read(&fs, "small"), "12");
+        assert_eq!(read(&fs, "s"), "1,000");
+
+        // Like `printf 2,500,000 > big`.
+        fs.truncate_node(big, 0).unwrap();
+        fs.write_data(big, 0, b"2,500,000").unwrap();
+        assert_eq!(fs.json["big"], serde_json::json!(2500000));
+        // Editing in place, within the shown text.
+        fs.write_data(big, 0, b"3").unwrap();
+        assert_eq!(fs.json["big"], serde_json::json!(3500000));
+        let neg = child_ino(&fs, FUSE_ROOT_ID, "neg");
+        fs.write_data(neg, 1, b"9").unwrap();
+        assert_eq!(fs.json["neg"].to_string(), "-9234.5");
+
+        // Text that isn't a grouped number is kept as written.
+        for text in ["1,00", "1234,567", "1,000.5,5", ",100"] {
+            fs.truncate_node(big, 0).unwrap();
+            fs.write_data(big, 0, text.as_bytes()).unwrap();
+            assert_eq!(fs.json["big"], serde_json::json!(text));
+        }
+
+        fs.myflush().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("-9234.5"));
+
+        drop(fs);
+        let plain = JsonFS::new(&path).unwrap();
+        assert_eq!(read(&plain, "neg"), "-9234.5");
+    }
+
+    #[test]
+    fn strict_types_keep_scalar_types() {
+        let path = temp_json("strict", r#"{"n": 5, "b": true, "s": "x"}"#);
+        let options = Options {
+            strict_types: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        let b = child_ino(&fs, FUSE_ROOT_ID, "b");
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+
+        assert_eq!(fs.write_data(n, 0, b"abc"), Err(libc::EINVAL));
+        assert_eq!(fs.write_data(b, 0, b"yes  "), Err(libc::EINVAL));
+        fs.write_data(s, 0, b"42").unwrap();
+        assert_eq!(fs.json, serde_json::json!({"n": 5, "b": true, "s": "42"}));
+
+        // `echo 42 > n`: truncate, then write with a newline.
+        fs.set_attr(n, None, None, None, Some(0), None, None)
+            .unwrap();
+        fs.write_data(n, 0, b"42\n").unwrap();
+        assert_eq!(fs.json["n"], serde_json::json!(42));
+        fs.set_attr(b, None, None, None, Some(0), None, None)
+            .unwrap();
+        assert_eq!(fs.write_data(b, 0, b"1"), Err(libc::EINVAL));
+        fs.write_data(b, 0, b"false").unwrap();
+        assert_eq!(fs.json["b"], serde_json::json!(false));
+
+        drop(fs);
+        let mut lax = JsonFS::new(&path).unwrap();
+        let n = child_ino(&lax, FUSE_ROOT_ID, "n");
+        lax.write_data(n, 0, b"abc").unwrap();
+        assert_eq!(lax.json["n"], serde_json::json!("abc"));
+    }
+
+    #[test]
+    fn poll_wakes_on_change() {
+        let path = temp_json("poll", r#"{"a": "x", "b": "y"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let woken = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = woken.clone();
+        fs.set_poll_notify(Box::new(move |kh| sink.lock().unwrap().push(kh)));
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, FUSE_ROOT_ID, "b");
+
+        let schedule = fuser::consts::FUSE_POLL_SCHEDULE_NOTIFY;
+        let ready = fs.poll_events(a, 7, schedule).unwrap();
+        assert_ne!(ready & libc::POLLIN as u32, 0);
+        fs.poll_events(b, 8, 0).unwrap();
+
+        fs.write_data(b, 0, b"z").unwrap();
+        assert!(woken.lock().unwrap().is_empty());
+        fs.write_data(a, 0, b"z").unwrap();
+        assert_eq!(*woken.lock().unwrap(), [7]);
+        // A wakeup is one-shot; the kernel polls again to re-arm it.
+        fs.write_data(a, 0, b"w").unwrap();
+        assert_eq!(*woken.lock().unwrap(), [7]);
+    }
+
+    #[test]
+    fn kind_changes_invalidate_the_kernel_cache() {
+        let path = temp_json("invalidate", r#"{"a": {"b": 1}, "c": 2, "d": []}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let dropped = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = dropped.clone();
+        fs.set_inval_notify(Box::new(move |parent, ino, name: &str| {
+            sink.lock().unwrap().push((parent, ino, name.to_string()))
+        }));
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let c = child_ino(&fs, FUSE_ROOT_ID, "c");
+
+        // Same kinds, new values: the TTL is good enough.
+        fs::write(&path, r#"{"a": {"b": 2}, "c": "x", "d": {}}"#).unwrap();
+        fs.reload().unwrap();
+        assert!(dropped.lock().unwrap().is_empty());
+
+        fs::write(&path, r#"{"a": "now a file", "c": {"e": 1}, "d": {}}"#).unwrap();
+        fs.reload().unwrap();
+        assert_eq!(
+            *dropped.lock().unwrap(),
+            [
+                (FUSE_ROOT_ID, a, "a".to_string()),
+                (FUSE_ROOT_ID, c, "c".to_string())
+            ]
+        );
+        let attr = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap();
+        assert_eq!((attr.ino, attr.kind), (a, FileType::RegularFile));
+        assert_eq!(attr_of(&fs, c).kind, FileType::Directory);
+    }
+
+    #[test]
+    fn escaped_keys_are_navigable() {
+        let path = temp_json(
+            "escape_keys",
+            r#"{"a/b": 1, "50%": 2, "nul\u0000key": 3, "tab\tkey": 4, "a~1b": 5}"#,
+        );
+        let options = Options {
+            escape_keys: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+
+        let listed: Vec<_> = ["a/b", "50%", "nul\0key", "tab\tkey", "a~1b"]
+            .iter()
+            .map(|key| fs.file_name(key).unwrap())
+            .collect();
+        assert_eq!(listed, ["a%2Fb", "50%25", "nul%00key", "tab%09key", "a~1b"]);
+        for (file_name, content) in listed.iter().zip(["1", "2", "3", "4", "5"]) {
+            let attr = fs
+                .lookup_entry(FUSE_ROOT_ID, &fs.entry_name(FUSE_ROOT_ID, file_name))
+                .unwrap();
+            assert_eq!(fs.read_data(attr.ino, 0, 16).unwrap(), content.as_bytes());
+        }
+
+        let name = fs.entry_name(FUSE_ROOT_ID, "c%2Fd");
+        fs.create_file(FUSE_ROOT_ID, &name, 0o644).unwrap();
+        assert_eq!(fs.json["c/d"], "");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+
+        drop(fs);
+        let fs = JsonFS::new(&path).unwrap();
+        assert_eq!(fs.file_name("a/b").unwrap(), "a~1b");
+        assert_eq!(fs.file_name("nul\0key"), None);
+    }
+
+    #[test]
+    fn readdir_pages_through_large_arrays() {
+        let len = 100_000;
+        let path = temp_json(
+            "readdir_pages",
+            &format!("{{\"a\": [{}0]}}", "0,".repeat(len - 1)),
+        );
+        let fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+
+        let page = 200;
+        let (mut listed, mut offered, mut offset) = (vec![], 0, 0);
+        loop {
+            let before = listed.len();
+            fs.dir_entries(a, offset, None, |_, next, _, name| {
+                offered += 1;
+                if listed.len() - before == page {
+                    return true;
+                }
+                listed.push(name.to_string());
+                offset = next;
+                false
+            })
+            .unwrap();
+            if listed.len() == before {
+                break;
+            }
+        }
+        assert_eq!(listed.len(), len + 2);
+        assert_eq!(listed[..3], [".", "..", "0"]);
+        assert_eq!(listed.last().unwrap(), &(len - 1).to_string());
+        // Each call stops at the first entry that doesn't fit.
+        assert_eq!(offered, len + 2 + (len + 2) / page);
+    }
+
+    #[test]
+    fn len_file_counts_children() {
+        let path = temp_json(
+            "len",
+            r#"{"o": {"a": 1, "b": 2, "c": 3}, "arr": [1, 2], "s": "x"}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let o = child_ino(&fs, FUSE_ROOT_ID, "o");
+
+        let len = fs.lookup_entry(o, LEN_NAME).unwrap();
+        assert_eq!((len.kind, len.perm), (FileType::RegularFile, 0o444));
+        assert_eq!(fs.read_data(len.ino, 0, 16).unwrap(), b"3");
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+        let arr_len = fs.lookup_entry(arr, LEN_NAME).unwrap().ino;
+        assert_eq!(fs.read_data(arr_len, 0, 16).unwrap(), b"2");
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        assert_eq!(fs.lookup_entry(s, LEN_NAME), Err(libc::ENOTDIR));
+
+        assert_eq!(fs.write_data(len.ino, 0, b"9"), Err(libc::EROFS));
+        assert_eq!(
+            fs.set_attr(len.ino, None, None, None, Some(0), None, None),
+            Err(libc::EROFS)
+        );
+        fs.create_file(o, "d", 0o644).unwrap();
+        assert_eq!(fs.read_data(len.ino, 0, 16).unwrap(), b"4");
+
+        let listed = |fs: &JsonFS| {
+            let mut names = vec![];
+            fs.dir_entries(o, 0, None, |_, _, _, name| {
+                names.push(name.to_string());
+                false
+            })
+            .unwrap();
+            names
+        };
+        assert!(!listed(&fs).contains(&LEN_NAME.to_string()));
+        fs.options.show_meta = true;
+        assert!(listed(&fs).contains(&LEN_NAME.to_string()));
+    }
+
+    #[test]
+    fn hidden_keys_are_not_listed() {
+        let path = temp_json(
+            "hide",
+            r#"{"_internal": "x", "name": "y", ".meta": {}, "list": ["a", "b"]}"#,
+        );
+        let options = Options {
+            hide: vec!["_*".to_string(), ".m?ta".to_string()],
+            ..Default::default()
+        };
+        let fs = JsonFS::with_options(&path, options).unwrap();
+        let listed = |fs: &JsonFS, ino| {
+            let mut names = vec![];
+            fs.dir_entries(ino, 0, None, |_, _, _, name| {
+                names.push(name.to_string());
+                false
+            })
+            .unwrap();
+            names
+        };
+        assert_eq!(
+            listed(&fs, FUSE_ROOT_ID),
+            [".", "..", DOCUMENT_NAME, "list", "name"]
+        );
+        let list = child_ino(&fs, FUSE_ROOT_ID, "list");
+        assert_eq!(listed(&fs, list), [".", "..", "0", "1"]);
+
+        let internal = fs.lookup_entry(FUSE_ROOT_ID, "_internal").unwrap();
+        assert_eq!(fs.read_data(internal.ino, 0, 16).unwrap(), b"x");
+        assert!(fs.lookup_entry(FUSE_ROOT_ID, ".meta").is_ok());
+
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "axxbyybc"));
+        assert!(!glob_match("a*b", "ab_"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn snapshot_reflects_unsaved_changes() {
+        let path = temp_json("snapshot", r#"{"a": "x", "n": [1]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        fs.write_data(a, 0, b"y").unwrap();
+        fs.create_file(FUSE_ROOT_ID, "b", 0o644).unwrap();
+        let expected = serde_json::json!({"a": "y", "b": "", "n": [1]});
+        assert_eq!(fs.snapshot(), expected);
+        assert_eq!(fs.export_string(false), r#"{"a":"y","b":"","n":[1]}"#);
+        assert_eq!(fs.export_string(true), fs.pretty(&expected));
+        // Nothing was written to get them.
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"{"a": "x", "n": [1]}"#
+        );
+
+        let shared = SharedFS::new(fs, Duration::ZERO);
+        assert_eq!(shared.snapshot(), expected);
+        assert_eq!(shared.export_string(false), r#"{"a":"y","b":"","n":[1]}"#);
+    }
+
+    #[test]
+    fn synthetic_files_refuse_changes() {
+        let path = temp_json(
+            "synthetic",
+            r#"{"o": {"k": "v", ".len": "shadowed"}, ".json": 1}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let o = child_ino(&fs, FUSE_ROOT_ID, "o");
+        let k = child_ino(&fs, o, "k");
+        let len = fs.lookup_entry(o, LEN_NAME).unwrap().ino;
+        assert!(is_synthetic(len) && !is_synthetic(DOCUMENT_INO) && !is_synthetic(k));
+
+        assert_eq!(fs.write_data(len, 0, b"9"), Err(libc::EROFS));
+        assert_eq!(
+            fs.set_attr(len, None, None, None, Some(0), None, None),
+            Err(libc::EROFS)
+        );
+        assert_eq!(fs.allocate(len, 0, 8, 0), Err(libc::EROFS));
+        assert_eq!(fs.copy_range(k, 0, len, 0, 1), Err(libc::EROFS));
+        assert_eq!(fs.set_xattr(len, JSON_XATTR, b"1", 0), Err(libc::EPERM));
+
+        // Neither name turns into a key, nor does the key it shadows go.
+        let before = fs.json.clone();
+        assert_eq!(fs.remove_entry(o, LEN_NAME), Err(libc::EPERM));
+        assert_eq!(
+            fs.remove_entry(FUSE_ROOT_ID, DOCUMENT_NAME),
+            Err(libc::EPERM)
+        );
+        assert_eq!(fs.create_file(o, LEN_NAME, 0o644).err(), Some(libc::EPERM));
+        assert_eq!(
+            fs.make_dir(FUSE_ROOT_ID, LEN_NAME, 0o755).err(),
+            Some(libc::EPERM)
+        );
+        assert_eq!(
+            fs.link_entry(k, FUSE_ROOT_ID, DOCUMENT_NAME).err(),
+            Some(libc::EPERM)
+        );
+        assert_eq!(fs.json, before);
+        assert_eq!(fs.read_data(len, 0, 16).unwrap(), b"2");
+        // Elsewhere `.json` is an ordinary name.
+        assert!(fs.create_file(o, DOCUMENT_NAME, 0o644).is_ok());
+    }
+
+    #[test]
+    fn non_owners_are_refused() {
+        let path = temp_json("perm_check", r#"{"a": "x", "d": {}}"#);
+        // Not root, which may do anything.
+        let (owner, group) = (1000, 1000);
+        let (other, other_group) = (owner + 1, group + 1);
+        let options = Options {
+            uid: owner,
+            gid: group,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+
+        // A file is 0644 and a directory 0755: only the owner may write.
+        assert_eq!(fs.check_access(a, owner, group, libc::W_OK), Ok(()));
+        assert_eq!(
+            fs.check_access(a, other, other_group, libc::W_OK),
+            Err(libc::EACCES)
+        );
+        assert_eq!(fs.check_access(a, other, other_group, libc::R_OK), Ok(()));
+        assert_eq!(
+            fs.check_access(d, other, group, libc::W_OK | libc::X_OK),
+            Err(libc::EACCES)
+        );
+        assert_eq!(fs.check_access(a, 0, 0, libc::W_OK), Ok(()));
+        assert_eq!(fs.check_owner(a, other), Err(libc::EPERM));
+        assert_eq!(fs.check_owner(a, owner), Ok(()));
+
+        // The group bits apply to the group, and a chown hands the node over.
+        fs.set_attr(a, Some(0o664), None, None, None, None, None)
+            .unwrap();
+        assert_eq!(fs.check_access(a, other, group, libc::W_OK), Ok(()));
+        fs.set_attr(a, None, Some(other), None, None, None, None)
+            .unwrap();
+        assert_eq!(fs.check_access(a, other, other_group, libc::W_OK), Ok(()));
+        assert_eq!(
+            fs.check_access(a, owner, other_group, libc::W_OK),
+            Err(libc::EACCES)
+        );
+
+        fs.options.no_perm_check = true;
+        assert_eq!(fs.check_access(d, other, other_group, libc::W_OK), Ok(()));
+        assert_eq!(fs.check_owner(d, other), Ok(()));
+    }
+
+    #[test]
+    fn version_and_etag_track_changes() {
+        let path = temp_json("version", r#"{"a": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let version = |fs: &JsonFS| fs.xattr(FUSE_ROOT_ID, VERSION_XATTR).unwrap();
+        let etag = |fs: &JsonFS| fs.xattr(DOCUMENT_INO, ETAG_XATTR).unwrap();
+        assert_eq!(version(&fs), b"0");
+        let (first, unchanged) = (etag(&fs), fs.xattr(FUSE_ROOT_ID, ETAG_XATTR).unwrap());
+        assert_eq!(first, unchanged);
+
+        fs.write_data(a, 0, b"y").unwrap();
+        assert_eq!(version(&fs), b"1");
+        assert_ne!(etag(&fs), first);
+        fs.create_file(FUSE_ROOT_ID, "b", 0o644).unwrap();
+        assert_eq!(version(&fs), b"2");
+        // Putting the old text back brings the old etag, but not the version.
+        fs.remove_entry(FUSE_ROOT_ID, "b").unwrap();
+        fs.write_data(a, 0, b"x").unwrap();
+        assert_eq!(etag(&fs), first);
+        assert_eq!(version(&fs), b"4");
+
+        assert_eq!(
+            fs.xattr_names(FUSE_ROOT_ID).unwrap(),
+            b"user.jsonfs.version\0user.jsonfs.etag\0"
+        );
+        assert_eq!(
+            fs.set_xattr(FUSE_ROOT_ID, VERSION_XATTR, b"0", 0),
+            Err(libc::EPERM)
+        );
+        assert_eq!(fs.xattr(a, VERSION_XATTR), Err(libc::ENODATA));
+    }
+
+    #[test]
+    fn directories_read_through_the_json_xattr() {
+        let path = temp_json("readdir-json", r#"{"a": {"b": [1, "x"], "c": null}}"#);
+        let fs = JsonFS::new(&path).unwrap();
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+
+        assert_eq!(fs.read_data(a, 0, 4096), Err(libc::EISDIR));
+        let content = fs.xattr(a, JSON_XATTR).unwrap();
+        let parsed: Value = serde_json::from_slice(&content).unwrap();
+        assert_eq!(parsed, serde_json::json!({"b": [1, "x"], "c": null}));
+        // It is still listed as a directory.
+        let mut names = vec![];
+        fs.dir_entries(a, 0, None, |_, _, _, name| {
+            names.push(name.to_string());
+            false
+        })
+        .unwrap();
+        assert!(names.contains(&"b".to_string()));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn subtree_json_xattr_parses_back() {
+        let document = serde_json::json!({
+            "a": {"b": [1, "x", {"c": [true, null]}], "d": {}},
+            "e": [[2.5], []]
+        });
+        let path = temp_json("subtree-xattr", &document.to_string());
+        let fs = JsonFS::new(&path).unwrap();
+
+        for pointer in [
+            "/a", "/a/b", "/a/b/2", "/a/b/2/c", "/a/d", "/e", "/e/0", "/e/1",
+        ] {
+            let mut ino = FUSE_ROOT_ID;
+            for name in pointer.split('/').skip(1) {
+                ino = fs.lookup_entry(ino, name).unwrap().ino;
+            }
+            let content = fs.xattr(ino, JSON_XATTR).unwrap();
+            let parsed: Value = serde_json::from_slice(&content).unwrap();
+            assert_eq!(Some(&parsed), document.pointer(pointer), "{}", pointer);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Collects each log record's key-value pairs as `key=value` text.
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl slog::Drain for Capture {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> Result<(), slog::Never> {
+            struct Pairs(String);
+            impl slog::Serializer for Pairs {
+                fn emit_arguments(
+                    &mut self,
+                    key: slog::Key,
+                    val: &std::fmt::Arguments,
+                ) -> slog::Result {
+                    self.0.push_str(&format!("{}={} ", key, val));
+                    Ok(())
+                }
+            }
+            let mut pairs = Pairs(String::new());
+            slog::KV::serialize(&record.kv(), record, &mut pairs).unwrap();
+            self.0.lock().unwrap().push(pairs.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn handlers_log_their_duration() {
+        let path = temp_json("latency", r#"{"a": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+
+        fs.write_data(a, 0, b"y").unwrap();
+
+        let records = Arc::new(Mutex::new(vec![]));
+        let logger = slog::Logger::root(Capture(Arc::clone(&records)), slog::o!());
+        slog_scope::scope(&logger, || Filesystem::destroy(&mut fs));
+        let records = records.lock().unwrap();
+        let out: Vec<_> = records.iter().filter(|r| r.contains("io=out")).collect();
+        assert_eq!(out.len(), 1, "{:?}", records);
+        assert!(out[0].contains("op=destroy"), "{}", out[0]);
+        assert!(out[0].contains("dur_us="), "{}", out[0]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn typed_extensions_pick_the_type() {
+        let path = temp_json("typed", r#"{}"#);
+        let options = Options {
+            typed_extensions: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        for (name, key, expected) in [
+            ("s.str", "s", serde_json::json!("")),
+            ("count.num", "count", serde_json::json!(0)),
+            ("flag.bool", "flag", serde_json::json!(false)),
+            ("nothing.null", "nothing", Value::Null),
+            // Only the last extension counts, and only a known one.
+            ("v.1.num", "v.1", serde_json::json!(0)),
+            ("notes.txt", "notes.txt", serde_json::json!("")),
+            ("plain", "plain", serde_json::json!("")),
+            (".num", ".num", serde_json::json!("")),
+        ] {
+            let attr = fs.create_entry(FUSE_ROOT_ID, name, 0o644, 0).unwrap();
+            assert_eq!(fs.json[key], expected, "{}", name);
+            assert_eq!(
+                attr.kind == FileType::Directory,
+                is_dir(&expected),
+                "{}",
+                name
+            );
+        }
+        // The extension is matched against the stripped key.
+        assert_eq!(
+            fs.create_entry(FUSE_ROOT_ID, "count.str", 0o644, libc::O_EXCL),
+            Err(libc::EEXIST)
+        );
+
+        // Containers are made by `mkdir`; `create` leaves the tree alone.
+        let before = fs.json.clone();
+        for name in ["o.obj", "a.arr"] {
+            assert_eq!(
+                fs.create_entry(FUSE_ROOT_ID, name, 0o644, 0),
+                Err(libc::EISDIR)
+            );
+        }
+        assert_eq!(fs.json, before);
+        for (name, key, expected) in [
+            ("o.obj", "o", serde_json::json!({})),
+            ("a.arr", "a", serde_json::json!([])),
+            ("d.str", "d.str", serde_json::json!({})),
+        ] {
+            let attr = fs.make_dir(FUSE_ROOT_ID, name, 0o755).unwrap();
+            assert_eq!(attr.kind, FileType::Directory);
+            assert_eq!(fs.json[key], expected, "{}", name);
+        }
+        drop(fs);
+
+        // Without the option the name is kept whole.
+        let mut fs = JsonFS::new(&path).unwrap();
+        fs.create_entry(FUSE_ROOT_ID, "flag.bool", 0o644, 0)
+            .unwrap();
+        assert_eq!(fs.json["flag.bool"], "");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn removing_a_missing_name_fails() {
+        let path = temp_json("unlink-missing", r#"{"a": "x", "arr": ["y"]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let arr = fs.lookup_entry(FUSE_ROOT_ID, "arr").unwrap().ino;
+
+        assert_eq!(fs.remove_entry(FUSE_ROOT_ID, "missing"), Err(ENOENT));
+        assert_eq!(fs.remove_entry(arr, "1"), Err(ENOENT));
+        assert_eq!(
+            fs.remove_entry(arr, "18446744073709551616"),
+            Err(libc::EINVAL)
+        );
+        assert!(!fs.dirty);
+        assert_eq!(fs.json, serde_json::json!({"a": "x", "arr": ["y"]}));
+
+        fs.remove_entry(FUSE_ROOT_ID, "a").unwrap();
+        assert_eq!(fs.remove_entry(FUSE_ROOT_ID, "a"), Err(ENOENT));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn read_pretty_indents_the_json_xattr() {
+        let path = temp_json("read-pretty", r#"{"a": {"b": [1]}}"#);
+        let options = Options {
+            read_pretty: true,
+            ..Default::default()
+        };
+        let fs = JsonFS::with_options(&path, options).unwrap();
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+
+        let expected = "{\n  \"b\": [\n    1\n  ]\n}";
+        assert_eq!(fs.xattr(a, JSON_XATTR).unwrap(), expected.as_bytes());
+        let b = fs.lookup_entry(a, "b").unwrap().ino;
+        assert_eq!(fs.xattr(b, JSON_XATTR).unwrap(), b"[\n  1\n]");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rename_moves_between_objects_and_arrays() {
+        let path = temp_json(
+            "rename",
+            r#"{"o": {"k": "v", "d": {"x": 1}}, "a": ["a0", "a1", "a2"]}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let o = fs.lookup_entry(FUSE_ROOT_ID, "o").unwrap().ino;
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+        let k = fs.lookup_entry(o, "k").unwrap().ino;
+
+        // Object to array: inserted at the index, the rest moving up.
+        fs.rename_entry(o, "k", a, "1", 0).unwrap();
+        assert_eq!(fs.json["a"], serde_json::json!(["a0", "v", "a1", "a2"]));
+        assert!(fs.json["o"].get("k").is_none());
+        assert_eq!(fs.lookup_entry(a, "1").unwrap().ino, k);
+        assert_eq!(fs.read_data(k, 0, 16).unwrap(), b"v");
+
+        // Array to object: keyed by the new name, the rest moving down with
+        // their inodes.
+        let a1 = fs.lookup_entry(a, "2").unwrap().ino;
+        fs.rename_entry(a, "0", o, "first", 0).unwrap();
+        assert_eq!(fs.json["a"], serde_json::json!(["v", "a1", "a2"]));
+        assert_eq!(fs.json["o"]["first"], "a0");
+        assert_eq!(fs.lookup_entry(a, "0").unwrap().ino, k);
+        assert_eq!(fs.lookup_entry(a, "1").unwrap().ino, a1);
+        assert_eq!(fs.read_data(a1, 0, 16).unwrap(), b"a1");
+        assert_eq!(fs.lookup_entry(a, "3").err(), Some(ENOENT));
+
+        // A directory keeps its inode, and its children theirs.
+        let d = fs.lookup_entry(o, "d").unwrap().ino;
+        let x = fs.lookup_entry(d, "x").unwrap().ino;
+        fs.rename_entry(o, "d", a, "3", 0).unwrap();
+        assert_eq!(fs.json["a"][3], serde_json::json!({"x": 1}));
+        assert_eq!(fs.lookup_entry(a, "3").unwrap().ino, d);
+        assert_eq!(fs.lookup_entry(d, "x").unwrap().ino, x);
+
+        // Within one array, and into an element that shifts.
+        fs.rename_entry(a, "0", a, "2", 0).unwrap();
+        assert_eq!(fs.json["a"], serde_json::json!(["a1", "a2", "v", {"x": 1}]));
+        fs.rename_entry(a, "0", d, "moved", 0).unwrap();
+        assert_eq!(fs.json["a"][2], serde_json::json!({"x": 1, "moved": "a1"}));
+
+        // An array name must be an index no further than the end.
+        for bad in ["x", "01", "9"] {
+            assert_eq!(fs.rename_entry(o, "first", a, bad, 0), Err(libc::EINVAL));
+        }
+        assert_eq!(fs.json["o"]["first"], "a0");
+        assert_eq!(fs.rename_entry(o, "missing", a, "0", 0), Err(ENOENT));
+        // `d` kept its inode through the shifts.
+        assert_eq!(fs.lookup_entry(a, "2").unwrap().ino, d);
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "a", d, "inside", 0),
+            Err(libc::EINVAL)
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rename_replaces_like_posix() {
+        let path = temp_json(
+            "rename-replace",
+            r#"{"f": "1", "g": "2", "d": {}, "e": {"x": 1}}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "f", FUSE_ROOT_ID, "g", libc::RENAME_NOREPLACE),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "f", FUSE_ROOT_ID, "d", 0),
+            Err(libc::EISDIR)
+        );
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "d", FUSE_ROOT_ID, "f", 0),
+            Err(libc::ENOTDIR)
+        );
+        assert_eq!(
+            fs.rename_entry(FUSE_ROOT_ID, "d", FUSE_ROOT_ID, "e", 0),
+            Err(libc::ENOTEMPTY)
+        );
+        fs.rename_entry(FUSE_ROOT_ID, "f", FUSE_ROOT_ID, "g", 0)
+            .unwrap();
+        fs.rename_entry(FUSE_ROOT_ID, "e", FUSE_ROOT_ID, "d", 0)
+            .unwrap();
+        assert_eq!(fs.json, serde_json::json!({"g": "1", "d": {"x": 1}}));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn allow_nan_reads_non_finite_numbers_as_strings() {
+        let data = r#"{"inf": Infinity, "ninf": [-Infinity, NaN], "s": "NaN Infinity", "NaNa": 1}"#;
+        let path = temp_json("allow_nan", data);
+        assert!(matches!(
+            JsonFS::new(&path).err(),
+            Some(JsonFsError::Parse { .. })
+        ));
+
+        let options = Options {
+            allow_nan: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        assert_eq!(
+            fs.json,
+            serde_json::json!({
+                "inf": "Infinity",
+                "ninf": ["-Infinity", "NaN"],
+                "s": "NaN Infinity",
+                "NaNa": 1
+            })
+        );
+        // They are saved as the strings they were read as.
+        fs.dirty = true;
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["inf"], "Infinity");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stats_file_counts_the_tree() {
+        let path = temp_json(
+            "stats",
+            r#"{"a": [1, 2.5, "x"], "b": {"c": true, "d": null}}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+        let stats = fs.lookup_entry(FUSE_ROOT_ID, STATS_NAME).unwrap();
+        assert_eq!(stats.ino, STATS_INO);
+        assert_eq!(stats.perm, 0o444);
+        let expected = "nodes: 8\n  objects: 2\n  arrays: 1\n  strings: 1\n  numbers: 2\n  \
+                        booleans: 1\n  nulls: 1\nmax depth: 2\n";
+        assert_eq!(
+            fs.read_data(STATS_INO, 0, 4096).unwrap(),
+            expected.as_bytes()
+        );
+        assert_eq!(stats.size, expected.len() as u64);
+
+        // A change is counted on the next read.
+        let b = fs.lookup_entry(FUSE_ROOT_ID, "b").unwrap().ino;
+        fs.remove_entry(b, "d").unwrap();
+        let text = String::from_utf8(fs.read_data(STATS_INO, 0, 4096).unwrap()).unwrap();
+        assert!(text.starts_with("nodes: 7\n"), "{}", text);
+        assert!(text.contains("  nulls: 0\n"), "{}", text);
+
+        assert_eq!(fs.write_data(STATS_INO, 0, b"x"), Err(libc::EROFS));
+        assert_eq!(
+            fs.create_file(FUSE_ROOT_ID, STATS_NAME, 0o644).err(),
+            Some(libc::EPERM)
+        );
+        let listed = |fs: &JsonFS| {
+            let mut names = vec![];
+            fs.dir_entries(FUSE_ROOT_ID, 0, None, |_, _, _, name| {
+                names.push(name.to_string());
+                false
+            })
+            .unwrap();
+            names
+        };
+        assert!(!listed(&fs).contains(&STATS_NAME.to_string()));
+        fs.options.show_meta = true;
+        assert!(listed(&fs).contains(&STATS_NAME.to_string()));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn in_place_edit_changes_only_the_edited_line() {
+        let data = "{\n  // settings\n  \"name\" : \"old\",\n  \"ports\": [80, 443],\n  \"tls\": {\"on\": true,},\n  \"n\": 1.50\n}\n";
+        let path = temp_json("in_place_edit", data);
+        let options = Options {
+            in_place_edit: true,
+            lenient: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let name = fs.lookup_entry(FUSE_ROOT_ID, "name").unwrap().ino;
+        fs.set_value(name, serde_json::json!("new \"one\""))
+            .unwrap();
+        fs.myflush().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let changed: Vec<_> = data
+            .lines()
+            .zip(saved.lines())
+            .filter(|(old, new)| old != new)
+            .collect();
+        assert_eq!(
+            changed,
+            [("  \"name\" : \"old\",", "  \"name\" : \"new \\\"one\\\"\",")]
+        );
+        assert_eq!(saved.len(), data.len() + 8);
+
+        // Later saves splice into what was saved.
+        let tls = fs.lookup_entry(FUSE_ROOT_ID, "tls").unwrap().ino;
+        let on = fs.lookup_entry(tls, "on").unwrap().ino;
+        fs.set_value(on, serde_json::json!(false)).unwrap();
+        fs.myflush().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("\"tls\": {\"on\": false,},"));
+        assert!(saved.contains("// settings"));
+
+        // A key added is a change of shape, saved as a whole new file.
+        fs.create_file(FUSE_ROOT_ID, "added", 0o644).unwrap();
+        fs.myflush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), fs.pretty(&fs.json));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn readdir_and_getattr_agree_on_empty_containers() {
+        let path = temp_json("file_type_of", r#"{"a": {"b": 1}, "s": "x"}"#);
+        let options = Options {
+            typed_extensions: true,
+            show_meta: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap().ino;
+        let e = fs.make_dir(FUSE_ROOT_ID, "e.obj", 0o755).unwrap().ino;
+        let n = fs.make_dir(a, "n.arr", 0o755).unwrap().ino;
+        fs.remove_entry(a, "b").unwrap();
+
+        for dir in [FUSE_ROOT_ID, a] {
+            let mut entries = vec![];
+            fs.dir_entries(dir, 0, None, |ino, _, kind, name| {
+                entries.push((ino, kind, name.to_string()));
+                false
+            })
+            .unwrap();
+            for (ino, kind, name) in entries.into_iter().skip(2) {
+                assert_eq!(fs.get_attr(ino).unwrap().kind, kind, "{}", name);
+            }
+        }
+        for ino in [a, e, n] {
+            assert_eq!(fs.file_type_of(ino), Ok(FileType::Directory));
+            assert_eq!(fs.get_attr(ino).unwrap().kind, FileType::Directory);
+        }
+        assert_eq!(
+            fs.json,
+            serde_json::json!({"a": {"n": []}, "e": {}, "s": "x"})
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_inserts_into_arrays() {
+        let path = temp_json("insert", r#"{"arr": ["a", "b", {"c": "d"}]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+        let b = child_ino(&fs, arr, "1");
+        let obj = child_ino(&fs, arr, "2");
+        let c = child_ino(&fs, obj, "c");
+
+        let middle = fs.create_file(arr, "1", 0o644).unwrap().ino;
+        assert_eq!(
+            fs.json["arr"],
+            serde_json::json!(["a", "", "b", {"c": "d"}])
+        );
+        assert!(![b, obj, c].contains(&middle));
+        // The moved elements keep their inodes.
+        assert_eq!(child_ino(&fs, arr, "2"), b);
+        assert_eq!(fs.read_data(b, 0, 16).unwrap(), b"b");
+        assert_eq!(child_ino(&fs, arr, "3"), obj);
+        assert_eq!(fs.read_data(c, 0, 16).unwrap(), b"d");
+
+        let first = fs.make_dir(arr, "0", 0o755).unwrap().ino;
+        assert_eq!(
+            fs.json["arr"],
+            serde_json::json!([{}, "a", "", "b", {"c": "d"}])
+        );
+        assert_eq!(child_ino(&fs, arr, "0"), first);
+        assert_eq!(child_ino(&fs, arr, "2"), middle);
+        assert_eq!(fs.path_of(c).unwrap(), ["arr", "4", "c"]);
+
+        // Appending still works; past the end does not.
+        fs.create_file(arr, "5", 0o644).unwrap();
+        assert_eq!(fs.create_file(arr, "7", 0o644), Err(libc::EINVAL));
+        assert_eq!(fs.make_dir(arr, "7", 0o755), Err(libc::EINVAL));
+        assert_eq!(fs.json["arr"].as_array().unwrap().len(), 6);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn written_text_takes_the_type_it_spells() {
+        let path = temp_json("spelled", r#"{"b": true, "s": "x"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let b = child_ino(&fs, FUSE_ROOT_ID, "b");
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+
+        for (text, value) in [
+            ("false", serde_json::json!(false)),
+            ("true", serde_json::json!(true)),
+            ("null", serde_json::json!(null)),
+            ("12", serde_json::json!(12)),
+            ("True", serde_json::json!("True")),
+            ("nullx", serde_json::json!("nullx")),
+        ] {
+            for ino in [b, s] {
+                fs.truncate_node(ino, 0).unwrap();
+                fs.write_data(ino, 0, text.as_bytes()).unwrap();
+                assert_eq!(fs.get_node(ino).unwrap(), &value, "{}", text);
+            }
+        }
+
+        // A bool reads back as the text it was written as, `st_size` long.
+        fs.truncate_node(b, 0).unwrap();
+        fs.write_data(b, 0, b"true").unwrap();
+        assert_eq!(fs.read_data(b, 0, 64).unwrap(), b"true");
+        assert_eq!(fs.get_attr(b).unwrap().size, 4);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn entry_replies_use_the_configured_ttl() {
+        let path = temp_json("ttl", r#"{"a": 1}"#);
+        let options = Options {
+            entry_ttl: Duration::from_millis(250),
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+
+        let a = fs.lookup_entry(FUSE_ROOT_ID, "a").unwrap();
+        assert_eq!(
+            fs.entry_reply(&a),
+            (Duration::from_millis(250), fs.generation(a.ino))
+        );
+        let b = fs.create_entry(FUSE_ROOT_ID, "b", 0o644, 0).unwrap();
+        assert_eq!(fs.entry_reply(&b).0, Duration::from_millis(250));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_respects_o_excl() {
+        let path = temp_json("create_excl", r#"{"a": "keep", "arr": ["x"]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+        let excl = libc::O_CREAT | libc::O_EXCL;
+
+        assert_eq!(
+            fs.create_entry(FUSE_ROOT_ID, "a", 0o644, excl),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(fs.create_entry(arr, "0", 0o644, excl), Err(libc::EEXIST));
+
+        let attr = fs
+            .create_entry(FUSE_ROOT_ID, "a", 0o600, libc::O_CREAT)
+            .unwrap();
+        assert_eq!((attr.ino, attr.perm), (a, 0o644));
+        assert_eq!(fs.json["a"], "keep");
+        // An index is where to insert, not an element to open.
+        let x = child_ino(&fs, arr, "0");
+        let attr = fs.create_entry(arr, "0", 0o644, libc::O_CREAT).unwrap();
+        assert_eq!(fs.json["arr"], serde_json::json!(["", "x"]));
+        assert_ne!(attr.ino, x);
+        assert_eq!(child_ino(&fs, arr, "1"), x);
+
+        let attr = fs.create_entry(FUSE_ROOT_ID, "b", 0o644, excl).unwrap();
+        assert_eq!(fs.get_node(attr.ino).unwrap(), "");
+
+        // `mkdir` never opens what is there, file or directory.
+        assert_eq!(fs.make_dir(FUSE_ROOT_ID, "a", 0o755), Err(libc::EEXIST));
+        assert_eq!(fs.make_dir(FUSE_ROOT_ID, "arr", 0o755), Err(libc::EEXIST));
+        assert_eq!(fs.json["a"], "keep");
+    }
+
+    #[test]
+    fn malformed_names_fail_without_panicking() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = temp_json("malformed_names", r#"{"o": {"k": 1}, "arr": ["x", "y"]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let o = child_ino(&fs, FUSE_ROOT_ID, "o");
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+
+        assert_eq!(utf8_name(OsStr::from_bytes(b"bad\xff")), Err(libc::EINVAL));
+        let names = [
+            "",
+            ".",
+            "..",
+            "-1",
+            "+1",
+            " 1",
+            "01",
+            "1.0",
+            "1e3",
+            "x",
+            "~",
+            "~2",
+            "\0",
+            "99999999999999999999999",
+            "\u{fffd}",
+        ];
+        for name in names {
+            for parent in [o, arr] {
+                let _ = fs.lookup_entry(parent, name);
+                let _ = fs.remove_entry(parent, name);
+                let _ = fs.create_entry(parent, name, 0o644, libc::O_EXCL);
+                let _ = fs.make_dir(parent, name, 0o755);
+            }
+        }
+        assert_eq!(fs.remove_entry(arr, "x"), Err(libc::EINVAL));
+        assert_eq!(fs.remove_entry(arr, "9"), Err(ENOENT));
+        assert_eq!(fs.json["arr"], serde_json::json!(["x", "y"]));
+    }
+
+    #[test]
+    fn open_directories_list_a_snapshot() {
+        let path = temp_json("opendir", r#"{"a": 1, "c": 3}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let page = |fs: &JsonFS, fh, offset| {
+            let mut names = vec![];
+            fs.read_dir(FUSE_ROOT_ID, fh, offset, |_, next, _, name| {
+                if names.len() == 2 {
+                    return true;
+                }
+                names.push((next, name.to_string()));
+                false
+            })
+            .unwrap();
+            names
+        };
+
+        let fh = fs.open_dir(FUSE_ROOT_ID).unwrap();
+        let first = page(&fs, fh, 0);
+        assert_eq!(first[1], (2, "..".to_string()));
+        fs.create_file(FUSE_ROOT_ID, "b", 0o644).unwrap();
+        fs.remove_entry(FUSE_ROOT_ID, "c").unwrap();
+        let rest: Vec<_> = page(&fs, fh, 2).into_iter().map(|(_, n)| n).collect();
+        assert_eq!(rest, [DOCUMENT_NAME, "a"]);
+        // "c" is gone, and "b" waits for the next open.
+        assert!(page(&fs, fh, 4).is_empty());
+
+        // A handle opened now sees the changes.
+        let again = fs.open_dir(FUSE_ROOT_ID).unwrap();
+        let names: Vec<_> = [0, 2, 4]
+            .iter()
+            .flat_map(|&offset| page(&fs, again, offset))
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(names, [".", "..", DOCUMENT_NAME, "a", "b"]);
+        assert_ne!(fh, again);
+
+        let s = child_ino(&fs, FUSE_ROOT_ID, "a");
+        assert_eq!(fs.open_dir(s), Err(libc::ENOTDIR));
+
+        // An array keeps its length, whatever is pushed meanwhile.
+        fs.make_dir(FUSE_ROOT_ID, "arr", 0o755).unwrap();
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+        fs.set_value(arr, serde_json::json!([1, 2])).unwrap();
+        let fh = fs.open_dir(arr).unwrap();
+        fs.create_file(arr, "2", 0o644).unwrap();
+        let mut names = vec![];
+        fs.read_dir(arr, fh, 2, |_, _, _, name| {
+            names.push(name.to_string());
+            false
+        })
+        .unwrap();
+        assert_eq!(names, ["0", "1"]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn fallocate_pads_scalar() {
+        let path = temp_json("fallocate", r#"{"s": "abc", "n": 7, "d": {}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        fs.allocate(s, 0, 10, 0).unwrap();
+        assert_eq!(attr_of(&fs, s).size, 10);
+        assert_eq!(fs.json["s"], "abc       ");
+
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        fs.allocate(n, 0, 10, libc::FALLOC_FL_KEEP_SIZE).unwrap();
+        assert_eq!(attr_of(&fs, n).size, 1);
+
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+        assert_eq!(fs.allocate(d, 0, 10, 0), Err(libc::EOPNOTSUPP));
+    }
+
+    /// Collects the messages of everything logged while it is the scope logger.
+    struct Messages(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl slog::Drain for Messages {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> Result<(), slog::Never> {
+            self.0.lock().unwrap().push(record.msg().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ignore_case_lookup() {
+        let path = temp_json(
+            "ignore_case",
+            r#"{"Name": 1, "key": 2, "KEY": 3, "arr": [4]}"#,
+        );
+        let options = Options {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let fs = JsonFS::with_options(&path, options).unwrap();
+
+        let name = fs.lookup_entry(FUSE_ROOT_ID, "name").unwrap();
+        assert_eq!(fs.read_data(name.ino, 0, 16).unwrap(), b"1");
+        let arr = fs.lookup_entry(FUSE_ROOT_ID, "ARR").unwrap();
+        assert!(fs.lookup_entry(arr.ino, "0").is_ok());
+
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let logger = slog::Logger::root(slog::Fuse(Messages(messages.clone())), slog::o!());
+        let key = slog_scope::scope(&logger, || fs.lookup_entry(FUSE_ROOT_ID, "Key").unwrap());
+        assert_eq!(fs.read_data(key.ino, 0, 16).unwrap(), b"3");
+        assert!(messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|msg| msg.contains("ambiguous")));
+
+        drop(fs);
+        let exact = JsonFS::new(&path).unwrap();
+        assert_eq!(exact.lookup_entry(FUSE_ROOT_ID, "name"), Err(ENOENT));
+        assert_eq!(exact.entry_name(FUSE_ROOT_ID, "name"), "name");
+    }
+
+    #[test]
+    fn ignore_case_applies_to_changes() {
+        let path = temp_json("ignore_case_changes", r#"{"Name": 1, "Old": 2, "Gone": 3}"#);
+        let options = Options {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+
+        let name = fs.entry_name(FUSE_ROOT_ID, "NAME");
+        assert_eq!(name, "Name");
+        fs.create_entry(FUSE_ROOT_ID, &name, 0o644, 0).unwrap();
+        assert_eq!(
+            fs.create_entry(FUSE_ROOT_ID, &name, 0o644, libc::O_EXCL),
+            Err(libc::EEXIST)
+        );
+        fs.remove_entry(FUSE_ROOT_ID, &fs.entry_name(FUSE_ROOT_ID, "gone"))
+            .unwrap();
+        let (old, new) = (
+            fs.entry_name(FUSE_ROOT_ID, "old"),
+            fs.entry_name(FUSE_ROOT_ID, "new"),
+        );
+        fs.rename_entry(FUSE_ROOT_ID, &old, FUSE_ROOT_ID, &new, 0)
+            .unwrap();
+        assert_eq!(fs.json, serde_json::json!({"Name": 1, "new": 2}));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn duplicate_keys_are_reported() {
+        let path = temp_json(
+            "duplicates",
+            r#"{"a": 1, "b": {"c": 1, "c": [{"d": 1, "d": 2}]}, "a": 2, "e/f": 0, "e/f": 1}"#,
+        );
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let logger = slog::Logger::root(slog::Fuse(Messages(messages.clone())), slog::o!());
+        let fs = slog_scope::scope(&logger, || JsonFS::new(&path).unwrap());
+
+        assert_eq!(fs.json["a"], serde_json::json!(2));
+        let warnings = messages.lock().unwrap();
+        assert_eq!(
+            warnings
+                .iter()
+                .filter(|msg| msg.contains("duplicate key"))
+                .count(),
+            4
+        );
+        assert_eq!(
+            duplicate_keys(&fs::read_to_string(&path).unwrap()),
+            ["/b/c", "/b/c/0/d", "/a", "/e~1f"]
+        );
+    }
+
+    #[test]
+    fn array_as_lines_round_trip() {
+        let path = temp_json(
+            "array_as_lines",
+            r#"{"tags": ["a", "b", 3, true], "mixed": [1, {}]}"#,
+        );
+        let options = Options {
+            array_as_lines: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+
+        let tags = fs.lookup_entry(FUSE_ROOT_ID, "tags").unwrap();
+        assert_eq!(tags.kind, FileType::RegularFile);
+        assert_eq!(tags.size, 11);
+        assert_eq!(fs.read_data(tags.ino, 0, 64).unwrap(), b"a\nb\n3\ntrue\n");
+        assert_eq!(fs.lookup_entry(tags.ino, "0"), Err(libc::ENOTDIR));
+
+        fs.write_data(tags.ino, 0, b"x\n-4.5\nfalse\nnull\ny z\n")
+            .unwrap();
+        assert_eq!(
+            fs.json["tags"],
+            serde_json::json!(["x", -4.5, false, null, "y z"])
+        );
+        assert_eq!(
+            fs.read_data(tags.ino, 0, 64).unwrap(),
+            b"x\n-4.5\nfalse\nnull\ny z\n"
+        );
+
+        // `printf 'a\nb\n' > tags`: an `O_TRUNC` to nothing, then a write.
+        fs.truncate_node(tags.ino, 0).unwrap();
+        assert_eq!(fs.json["tags"], serde_json::json!([]));
+        let emptied = fs.get_attr(tags.ino).unwrap();
+        assert_eq!((emptied.kind, emptied.size), (FileType::RegularFile, 0));
+        fs.write_data(tags.ino, 0, b"a\nb\n").unwrap();
+        assert_eq!(fs.json["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(fs.read_data(tags.ino, 0, 64).unwrap(), b"a\nb\n");
+
+        let mixed = fs.lookup_entry(FUSE_ROOT_ID, "mixed").unwrap();
+        assert_eq!(mixed.kind, FileType::Directory);
+    }
+
+    #[test]
+    fn lines_writes_splice_into_the_text() {
+        let path = temp_json("lines_splice", r#"{"l": ["one", "two", "three"]}"#);
+        let options = Options {
+            array_as_lines: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let l = child_ino(&fs, FUSE_ROOT_ID, "l");
+
+        // Overwriting a line keeps the ones after it.
+        fs.write_data(l, 4, b"TWO").unwrap();
+        assert_eq!(fs.json["l"], serde_json::json!(["one", "TWO", "three"]));
+
+        // A write the kernel split in two, the first chunk ending mid-line.
+        fs.write_data(l, 0, b"ONE\nT").unwrap();
+        fs.write_data(l, 5, b"WO\nTHREE\n4\n").unwrap();
+        assert_eq!(fs.json["l"], serde_json::json!(["ONE", "TWO", "THREE", 4]));
+        assert_eq!(fs.read_data(l, 0, 64).unwrap(), b"ONE\nTWO\nTHREE\n4\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seek_data_and_hole() {
+        let path = temp_json("seek", r#"{"s": "hello", "d": {}}"#);
+        let fs = JsonFS::new(&path).unwrap();
+
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        assert_eq!(fs.seek(s, 0, libc::SEEK_DATA), Ok(0));
+        assert_eq!(fs.seek(s, 3, libc::SEEK_DATA), Ok(3));
+        assert_eq!(fs.seek(s, 5, libc::SEEK_DATA), Err(libc::ENXIO));
+        assert_eq!(fs.seek(s, 2, libc::SEEK_HOLE), Ok(5));
+
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+        assert_eq!(fs.seek(d, 0, libc::SEEK_DATA), Err(libc::EINVAL));
+    }
+
+    #[test]
+    fn read_past_end_is_empty() {
+        let path = temp_json("read_past_end", r#"{"s": "abc", "n": 42}"#);
+        let fs = JsonFS::new(&path).unwrap();
+
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        assert_eq!(fs.read_data(s, 1, 16).unwrap(), b"bc");
+        assert!(fs.read_data(s, 3, 16).unwrap().is_empty());
+        assert!(fs.read_data(s, 100, 16).unwrap().is_empty());
+
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+        assert_eq!(fs.read_data(n, 1, 16).unwrap(), b"2");
+        assert!(fs.read_data(n, 100, 16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_may_split_characters() {
+        let path = temp_json("read_split", r#"{"s": "héllo"}"#);
+        let fs = JsonFS::new(&path).unwrap();
+
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        let head = fs.read_data(s, 0, 2).unwrap();
+        let tail = fs.read_data(s, 2, 16).unwrap();
+        assert_eq!(head, b"h\xc3");
+        assert_eq!([head, tail].concat(), "héllo".as_bytes());
+    }
+
+    #[test]
+    fn writes_past_the_size_limit_fail() {
+        let path = temp_json("max_value_bytes", r#"{"s": "abc", "lines": ["a"]}"#);
+        let options = Options {
+            array_as_lines: true,
+            max_value_bytes: Some(4),
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        let lines = child_ino(&fs, FUSE_ROOT_ID, "lines");
+
+        assert_eq!(fs.write_data(s, 3, b"de"), Err(libc::EFBIG));
+        assert_eq!(fs.allocate(s, 0, 5, 0), Err(libc::EFBIG));
+        assert_eq!(
+            fs.set_attr(s, None, None, None, Some(5), None, None),
+            Err(libc::EFBIG)
+        );
+        assert_eq!(fs.write_data(lines, 2, b"bc\n"), Err(libc::EFBIG));
+        assert_eq!(fs.allocate(s, i64::MAX, 1, 0), Err(libc::EFBIG));
+        // The document is one file too, however big it already is.
+        let document = fs.document_bytes();
+        assert_eq!(
+            fs.set_attr(DOCUMENT_INO, None, None, None, Some(1 << 40), None, None),
+            Err(libc::EFBIG)
+        );
+        assert_eq!(fs.allocate(DOCUMENT_INO, 0, 1 << 40, 0), Err(libc::EFBIG));
+        assert_eq!(fs.document_bytes(), document);
+        assert_eq!(fs.json, serde_json::json!({"s": "abc", "lines": ["a"]}));
+
+        fs.write_data(s, 3, b"d").unwrap();
+        fs.write_data(s, 0, b"ABCD").unwrap();
+        assert_eq!(fs.json["s"], serde_json::json!("ABCD"));
+    }
+
+    #[test]
+    fn deep_documents_mount_or_fail_cleanly() {
+        // serde_json refuses input nested past 128 levels, so a degenerate
+        // document is a parse error rather than a stack overflow.
+        let deep = format!("{}{}", "[".repeat(50_000), "]".repeat(50_000));
+        let path = temp_json("deep", &deep);
+        let err = JsonFS::new(&path).err().unwrap();
+        assert!(err.to_string().contains("recursion limit"));
+
+        let depth = 120;
+        let nested = format!("{}\"leaf\"{}", "[".repeat(depth), "]".repeat(depth));
+        fs::write(&path, nested).unwrap();
+        let mut fs = JsonFS::new(&path).unwrap();
+        assert_eq!(fs.ino2inode.len(), depth + 1);
+        let leaf = (0..depth).fold(FUSE_ROOT_ID, |ino, _| child_ino(&fs, ino, "0"));
+        assert_eq!(fs.read_data(leaf, 0, 16).unwrap(), b"leaf");
+        assert_eq!(Summary::of(&fs.json).max_depth, depth);
+
+        fs.replace_document(serde_json::json!([]));
+        assert_eq!(fs.ino2inode.len(), 1);
+    }
+
+    #[test]
+    fn blocks_follow_size() {
+        let path = temp_json("blocks", r#"{"s": "", "d": {"k": ""}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        assert_eq!(attr_of(&fs, s).blocks, 0);
+
+        fs.write_data(s, 0, &[b'x'; 2000]).unwrap();
+        let attr = attr_of(&fs, s);
+        assert_eq!((attr.size, attr.blocks, attr.blksize), (2000, 4, 512));
+
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+        assert_eq!(attr_of(&fs, d).blocks, 1);
+    }
+
+    #[test]
+    fn numtype_xattr() {
+        let path = temp_json(
+            "numtype",
+            r#"{"a": 5, "b": -5, "c": 5.0, "d": 1e3, "s": "5"}"#,
+        );
+        let fs = JsonFS::new(&path).unwrap();
+        let numtype = |name| fs.xattr(child_ino(&fs, FUSE_ROOT_ID, name), NUMTYPE_XATTR);
+
+        assert_eq!(numtype("a").unwrap(), b"integer");
+        assert_eq!(numtype("b").unwrap(), b"integer");
+        assert_eq!(numtype("c").unwrap(), b"float");
+        assert_eq!(numtype("d").unwrap(), b"float");
+        assert_eq!(numtype("s"), Err(libc::ENODATA));
+
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        assert_eq!(fs.xattr(a, "user.other"), Err(libc::ENODATA));
+        assert_eq!(fs.xattr_names(a).unwrap(), b"user.jsonfs.numtype\0");
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        assert!(fs.xattr_names(s).unwrap().is_empty());
+    }
+
+    #[test]
+    fn json_xattr_replaces_nodes() {
+        let path = temp_json("json_xattr", r#"{"a": "x", "d": {"old": 1}}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let d = child_ino(&fs, FUSE_ROOT_ID, "d");
+
+        fs.set_xattr(a, JSON_XATTR, br#"{"b": [1, 2]}"#, 0).unwrap();
+        assert_eq!(fs.json["a"], serde_json::json!({"b": [1, 2]}));
+        assert_eq!(attr_of(&fs, a).kind, FileType::Directory);
+        let b = child_ino(&fs, a, "b");
+        assert_eq!(fs.read_data(child_ino(&fs, b, "1"), 0, 16).unwrap(), b"2");
+        assert_eq!(fs.xattr(a, JSON_XATTR).unwrap(), br#"{"b":[1,2]}"#);
+
+        fs.set_xattr(d, JSON_XATTR, b"7", 0).unwrap();
+        assert_eq!(fs.lookup_entry(d, "old"), Err(libc::ENOTDIR));
+        assert_eq!(fs.read_data(d, 0, 16).unwrap(), b"7");
+
+        // Malformed JSON leaves the node alone.
+        assert_eq!(
+            fs.set_xattr(d, JSON_XATTR, b"{\"x\": ", 0),
+            Err(libc::EINVAL)
+        );
+        assert_eq!(
+            fs.set_xattr(FUSE_ROOT_ID, JSON_XATTR, b"1", 0),
+            Err(libc::EINVAL)
+        );
+        assert_eq!(fs.json["d"], 7);
+        assert_eq!(
+            fs.set_xattr(d, JSON_XATTR, b"1", libc::XATTR_CREATE),
+            Err(libc::EEXIST)
+        );
+        assert_eq!(fs.set_xattr(d, "user.other", b"1", 0), Err(libc::ENOTSUP));
+
+        fs.set_xattr(FUSE_ROOT_ID, JSON_XATTR, br#"[true]"#, 0)
+            .unwrap();
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, serde_json::json!([true]));
+    }
+
+    #[test]
+    fn json_xattr_keeps_the_limits_of_other_edits() {
+        let path = temp_json("json_xattr_limits", r#"{"a": {}, "n": 1, "s": "x"}"#);
+        let options = Options {
+            max_value_bytes: Some(4),
+            strict_types: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let n = child_ino(&fs, FUSE_ROOT_ID, "n");
+
+        assert_eq!(
+            fs.set_xattr(a, JSON_XATTR, br#"{"b": "too long"}"#, 0),
+            Err(libc::EFBIG)
+        );
+        assert_eq!(fs.set_xattr(n, JSON_XATTR, br#""1""#, 0), Err(libc::EINVAL));
+        assert_eq!(fs.set_xattr(n, JSON_XATTR, b"[1]", 0), Err(libc::EINVAL));
+        fs.set_xattr(n, JSON_XATTR, b"2", 0).unwrap();
+        fs.set_xattr(a, JSON_XATTR, br#"{"b": "ok"}"#, 0).unwrap();
+        assert_eq!(
+            fs.json,
+            serde_json::json!({"a": {"b": "ok"}, "n": 2, "s": "x"})
+        );
+        drop(fs);
+
+        // `a` is a child of the root, so a subtree nested as deep as the
+        // limit allows still reloads, and one level more is refused.
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let nested = |levels: usize| format!("{}{}", "[".repeat(levels), "]".repeat(levels));
+        assert_eq!(
+            fs.set_xattr(a, JSON_XATTR, nested(DEFAULT_MAX_DEPTH + 1).as_bytes(), 0),
+            Err(libc::ENAMETOOLONG)
+        );
+        fs.set_xattr(a, JSON_XATTR, nested(DEFAULT_MAX_DEPTH).as_bytes(), 0)
+            .unwrap();
+        fs.myflush().unwrap();
+        drop(fs);
+        let fs = JsonFS::new(&path).unwrap();
+        assert_eq!(Summary::of(&fs.json).max_depth, DEFAULT_MAX_DEPTH);
+    }
+
+    #[test]
+    fn inodes_stay_stable_across_mutations() {
+        let path = temp_json("stable", r#"{"a": {"b": "x"}, "arr": [1, 2]}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let a = child_ino(&fs, FUSE_ROOT_ID, "a");
+        let b = child_ino(&fs, a, "b");
+        let arr = child_ino(&fs, FUSE_ROOT_ID, "arr");
+
+        fs.make_dir(FUSE_ROOT_ID, "c", 0o755).unwrap();
+        fs.create_file(arr, "2", 0o644).unwrap();
+        fs.remove_entry(FUSE_ROOT_ID, "c").unwrap();
+
+        assert_eq!(child_ino(&fs, FUSE_ROOT_ID, "a"), a);
+        assert_eq!(child_ino(&fs, a, "b"), b);
+        assert_eq!(fs.read_data(b, 0, 16).unwrap(), b"x");
+        assert_eq!(fs.get_node(arr).unwrap(), &serde_json::json!([1, 2, ""]));
+        assert_eq!(fs.ino_of(FUSE_ROOT_ID, "c"), Err(ENOENT));
+    }
+
+    #[test]
+    fn nested_paths_build_up_through_mkdir_and_create() {
+        let path = temp_json("nested", r#"{"list": []}"#);
+        let options = Options {
+            infer_arrays: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+
+        // mkdir a; mkdir a/b; echo x > a/b/c
+        let a = fs.make_dir(FUSE_ROOT_ID, "a", 0o755).unwrap().ino;
+        let b = fs.make_dir(a, "b", 0o755).unwrap().ino;
+        let c = fs.create_file(b, "c", 0o644).unwrap().ino;
+        fs.write_data(c, 0, b"x").unwrap();
+
+        // mkdir list/0; mkdir list/0/d; echo y > list/0/d/0, which turns d
+        // into an array under inodes already handed out.
+        let list = child_ino(&fs, FUSE_ROOT_ID, "list");
+        let item = fs.make_dir(list, "0", 0o755).unwrap().ino;
+        let d = fs.make_dir(item, "d", 0o755).unwrap().ino;
+        let y = fs.create_file(d, "0", 0o644).unwrap().ino;
+        fs.write_data(y, 0, b"y").unwrap();
+        let z = fs.create_file(d, "1", 0o644).unwrap().ino;
+        fs.write_data(z, 0, b"z").unwrap();
+
+        assert_eq!(child_ino(&fs, FUSE_ROOT_ID, "a"), a);
+        assert_eq!(child_ino(&fs, a, "b"), b);
+        assert_eq!(child_ino(&fs, b, "c"), c);
+        assert_eq!(child_ino(&fs, d, "0"), y);
+        assert_eq!(fs.read_data(c, 0, 16).unwrap(), b"x");
+        assert_eq!(fs.read_data(y, 0, 16).unwrap(), b"y");
+        fs.myflush().unwrap();
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            saved,
+            serde_json::json!({"a": {"b": {"c": "x"}}, "list": [{"d": ["y", "z"]}]})
+        );
+    }
+
+    #[test]
+    fn keys_with_slashes_and_tildes() {
+        let path = temp_json(
+            "pointer",
+            r#"{"a/b": {"c~d": 1}, "arr": [10, 20], "~1": 2}"#,
+        );
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let ab = fs.lookup_entry(FUSE_ROOT_ID, "a~1b").unwrap();
+        let cd = fs.lookup_entry(ab.ino, "c~0d").unwrap();
+        assert_eq!(fs.read_data(cd.ino, 0, 16).unwrap(), b"1");
+        let tilde = fs.lookup_entry(FUSE_ROOT_ID, "~01").unwrap();
+        assert_eq!(fs.read_data(tilde.ino, 0, 16).unwrap(), b"2");
+
+        fs.create_file(ab.ino, "x~1y", 0o644).unwrap();
+        assert_eq!(fs.json["a/b"]["x/y"], "");
+        fs.remove_entry(ab.ino, "c~0d").unwrap();
+        assert_eq!(fs.json["a/b"], serde_json::json!({"x/y": ""}));
+
+        assert_eq!(
+            resolve_pointer(&fs.json, "/arr/1"),
+            Some(&serde_json::json!(20))
+        );
+        assert_eq!(
+            resolve_pointer(&fs.json, "/a~1b/x~1y"),
+            Some(&serde_json::json!(""))
+        );
+        assert_eq!(resolve_pointer(&fs.json, ""), Some(&fs.json));
+        assert_eq!(resolve_pointer(&fs.json, "/arr/01"), None);
+        assert_eq!(resolve_pointer(&fs.json, "/arr/+1"), None);
+    }
+
+    #[test]
+    fn lookup_errors_distinguish_scalar_parents() {
+        let path = temp_json(
+            "lookup_errors",
+            r#"{"s": "text", "n": null, "d": {}, "a": []}"#,
+        );
+        let fs = JsonFS::new(&path).unwrap();
+
+        for scalar in ["s", "n"] {
+            let ino = child_ino(&fs, FUSE_ROOT_ID, scalar);
+            assert_eq!(fs.lookup_entry(ino, "foo"), Err(libc::ENOTDIR));
+        }
+        for container in ["d", "a"] {
+            let ino = child_ino(&fs, FUSE_ROOT_ID, container);
+            assert_eq!(fs.lookup_entry(ino, "foo"), Err(ENOENT));
+            assert_eq!(fs.lookup_entry(ino, "0"), Err(ENOENT));
+        }
+        assert_eq!(fs.lookup_entry(FUSE_ROOT_ID, "missing"), Err(ENOENT));
+    }
+
+    #[test]
+    fn chunked_large_write_splices_at_offsets() {
+        let path = temp_json("large_write", r#"{"s": "old content"}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+
+        // 200KB with multi-byte characters, so chunk borders split some of them.
+        let big: String = (0..140_000)
+            .map(|i| if i % 2 == 0 { 'é' } else { 'x' })
+            .collect();
+        assert!(big.len() > 200_000);
+        fs.set_attr(s, None, None, None, Some(0), None, None)
+            .unwrap();
+        for (i, chunk) in big.as_bytes().chunks(4096).enumerate() {
+            let written = fs.write_data(s, (i * 4096) as i64, chunk).unwrap();
+            assert_eq!(written as usize, chunk.len());
+        }
+        assert_eq!(fs.json["s"], big.as_str());
+        assert_eq!(attr_of(&fs, s).size, big.len() as u64);
+
+        // Overwriting the middle keeps both ends.
+        fs.write_data(s, 2, "Aé".as_bytes()).unwrap();
+        let stored = fs.json["s"].as_str().unwrap();
+        assert_eq!(&stored[..6], "éAéx");
+        assert_eq!(stored.len(), big.len());
+    }
+
+    #[test]
+    fn summary_counts_nodes_by_type() {
+        let path = temp_json("summary", r#"{"a": [1, "x", null, {"b": true}], "c": 2.5}"#);
+        let json = JsonFS::load(&path, &Options::default()).unwrap();
+        let summary = Summary::of(&json);
+        assert_eq!(
+            summary,
+            Summary {
+                objects: 2,
+                arrays: 1,
+                strings: 1,
+                numbers: 2,
+                booleans: 1,
+                nulls: 1,
+                max_depth: 3,
+            }
+        );
+        assert_eq!(summary.nodes(), 8);
+        assert!(summary.to_string().starts_with("nodes: 8\n"));
+    }
+
+    #[test]
+    fn created_entries_honor_umask() {
+        let path = temp_json("umask", r#"{}"#);
+        let mut fs = JsonFS::new(&path).unwrap();
+
+        let file = fs.create_file(FUSE_ROOT_ID, "f", 0o666 & !0o077).unwrap();
+        assert_eq!(file.perm, 0o600);
+        let dir = fs.make_dir(FUSE_ROOT_ID, "d", 0o777 & !0o022).unwrap();
+        assert_eq!(dir.perm, 0o755);
+        let dir = fs
+            .make_dir(FUSE_ROOT_ID, "private", 0o777 & !0o077)
+            .unwrap();
+        assert_eq!(dir.perm, 0o700);
+
+        // Recreating an existing entry keeps its permissions.
+        assert_eq!(
+            fs.create_file(FUSE_ROOT_ID, "f", 0o644).unwrap().perm,
+            0o600
+        );
+    }
+
+    #[test]
+    fn write_offsets_are_validated() {
+        assert_eq!(write_offset(-1, 4), Err(libc::EINVAL));
+        assert_eq!(write_offset(i64::MIN, 4), Err(libc::EINVAL));
+        assert_eq!(write_offset(2, 4), Ok(2));
+        assert_eq!(write_offset(i64::MAX, 4), Ok(4));
+
+        let path = temp_json("offsets", r#"{"s": "abc", "l": ["x"]}"#);
+        let options = Options {
+            array_as_lines: true,
+            ..Default::default()
+        };
+        let mut fs = JsonFS::with_options(&path, options).unwrap();
+        let s = child_ino(&fs, FUSE_ROOT_ID, "s");
+        let l = child_ino(&fs, FUSE_ROOT_ID, "l");
+
+        for ino in [s, l, DOCUMENT_INO] {
+            assert_eq!(fs.write_data(ino, -5, b"z"), Err(libc::EINVAL));
+        }
+        assert_eq!(fs.json, serde_json::json!({"s": "abc", "l": ["x"]}));
+
+        fs.write_data(s, i64::MAX, b"d").unwrap();
+        assert_eq!(fs.json["s"], "abcd");
+        fs.write_data(l, 1 << 40, b"y\n").unwrap();
+        assert_eq!(fs.json["l"], serde_json::json!(["x", "y"]));
+    }
 }